@@ -1,24 +1,30 @@
 use crate::error::ContractError;
 use crate::response::MsgInstantiateContractResponse;
-use crate::state::PAIR_INFO;
+use crate::state::{
+    load_latest_observation, load_observation_at, store_observation, Config, Observation, CONFIG,
+    LEGACY_PAIR_INFO, OBSERVATION_INDEX, PAIR_INFO,
+};
 
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 
 use cosmwasm_std::{
     from_binary, to_binary, Addr, Binary, CanonicalAddr, Coin, CosmosMsg, Decimal, Deps, DepsMut,
-    Env, MessageInfo, Reply, ReplyOn, Response, StdError, StdResult, SubMsg, Uint128, WasmMsg,
+    Env, MessageInfo, Reply, ReplyOn, Response, StdError, StdResult, Storage, SubMsg, Uint128,
+    WasmMsg,
 };
+use std::convert::TryInto;
 
 use cosmwasm_bignumber::{Decimal256, Uint256};
-use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg, MinterResponse};
-use integer_sqrt::IntegerSquareRoot;
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg, Expiration, MinterResponse};
 use protobuf::Message;
 use std::str::FromStr;
 use terraswap::asset::{Asset, AssetInfo, PairInfo, PairInfoRaw};
 use terraswap::pair::{
-    Cw20HookMsg, ExecuteMsg, InstantiateMsg, MigrateMsg, PoolResponse, QueryMsg,
-    ReverseSimulationResponse, SimulationResponse,
+    CumulativePricesResponse, Cw20HookMsg, ExecuteMsg, InstantiateMsg, MigrateMsg, ObserveResponse,
+    PoolResponse, PriceCumulative, PriceImpactResponse, QueryMsg, ReverseSimulationResponse,
+    SimulationResponse, SpotPriceResponse, TradeToPriceResponse, MINIMUM_INITIAL_SHARE,
+    MINIMUM_LIQUIDITY_AMOUNT,
 };
 use terraswap::querier::query_supply;
 use terraswap::token::InstantiateMsg as TokenInstantiateMsg;
@@ -27,23 +33,49 @@ const INSTANTIATE_REPLY_ID: u64 = 1;
 
 /// Commission rate == 0.3%
 const COMMISSION_RATE: &str = "0.003";
+/// Commission rate over 10000, as the pair info reports fees
+const COMMISSION_FEE: u128 = 30;
+/// Decimals of the prices the pair quotes and sums up in the price accumulators
+const PRICE_PRECISION: u32 = 18;
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
     deps: DepsMut,
     env: Env,
-    _info: MessageInfo,
+    info: MessageInfo,
     msg: InstantiateMsg,
 ) -> StdResult<Response> {
+    if msg.asset_infos.len() != 2 || msg.asset_infos[0].equal(&msg.asset_infos[1]) {
+        return Err(StdError::generic_err(
+            "the xyk pair takes exactly two distinct assets",
+        ));
+    }
+
     let pair_info: &PairInfoRaw = &PairInfoRaw {
         contract_addr: deps.api.addr_canonicalize(env.contract.address.as_str())?,
         liquidity_token: CanonicalAddr::from(vec![]),
-        asset_infos: [
+        asset_infos: vec![
             msg.asset_infos[0].to_raw(deps.api)?,
             msg.asset_infos[1].to_raw(deps.api)?,
         ],
+        // the constant product has no amplification and needs no decimals scaling
+        amplification: Uint128::zero(),
+        fee: Uint128::from(COMMISSION_FEE),
+        precision_multipliers: vec![],
     };
 
     PAIR_INFO.save(deps.storage, pair_info)?;
+    let owner = match msg.owner {
+        Some(owner) => deps.api.addr_validate(&owner)?,
+        None => info.sender,
+    };
+    CONFIG.save(deps.storage, &Config::new(owner))?;
+    store_observation(
+        deps.storage,
+        &Observation {
+            timestamp: env.block.time.seconds(),
+            price_cumulatives: vec![Uint128::zero(); 2],
+        },
+    )?;
 
     Ok(Response::new().add_submessage(SubMsg {
         // Create LP token
@@ -81,15 +113,21 @@ pub fn execute(
         ExecuteMsg::Receive(msg) => receive_cw20(deps, env, info, msg),
         ExecuteMsg::ProvideLiquidity {
             assets,
-            slippage_tolerance,
+            min_out_amount,
             receiver,
-        } => provide_liquidity(deps, env, info, assets, slippage_tolerance, receiver),
+            deadline,
+        } => {
+            assert_deadline(&env, deadline)?;
+            provide_liquidity(deps, env, info, assets, min_out_amount, receiver)
+        }
         ExecuteMsg::Swap {
             offer_asset,
-            belief_price,
-            max_spread,
+            ask_asset,
+            min_out_amount,
             to,
+            deadline,
         } => {
+            assert_deadline(&env, deadline)?;
             if !offer_asset.is_native_token() {
                 return Err(ContractError::Unauthorized {});
             }
@@ -106,11 +144,15 @@ pub fn execute(
                 info.clone(),
                 info.sender,
                 offer_asset,
-                belief_price,
-                max_spread,
+                ask_asset.info,
+                min_out_amount,
                 to_addr,
             )
         }
+        ExecuteMsg::Pause { expiry_height } => pause(deps, env, info, expiry_height),
+        ExecuteMsg::Unpause {} => unpause(deps, info),
+        ExecuteMsg::UpdateGuardian { guardian } => update_guardian(deps, info, guardian),
+        _ => Err(ContractError::Unsupported {}),
     }
 }
 
@@ -124,18 +166,21 @@ pub fn receive_cw20(
 
     match from_binary(&cw20_msg.msg) {
         Ok(Cw20HookMsg::Swap {
-            belief_price,
-            max_spread,
+            ask_asset,
+            min_out_amount,
             to,
+            deadline,
         }) => {
+            assert_deadline(&env, deadline)?;
+
             // only asset contract can execute this message
             let mut authorized: bool = false;
             let config: PairInfoRaw = PAIR_INFO.load(deps.storage)?;
             let pools: [Asset; 2] =
-                config.query_pools(&deps.querier, deps.api, env.contract.address.clone())?;
+                query_pools(deps.as_ref(), &config, env.contract.address.clone())?;
             for pool in pools.iter() {
                 if let AssetInfo::Token { contract_addr, .. } = &pool.info {
-                    if contract_addr == &info.sender {
+                    if contract_addr == info.sender.as_str() {
                         authorized = true;
                     }
                 }
@@ -162,20 +207,38 @@ pub fn receive_cw20(
                     },
                     amount: cw20_msg.amount,
                 },
-                belief_price,
-                max_spread,
+                ask_asset.info,
+                min_out_amount,
                 to_addr,
             )
         }
-        Ok(Cw20HookMsg::WithdrawLiquidity {}) => {
+        Ok(Cw20HookMsg::WithdrawLiquidity {
+            min_assets,
+            receiver,
+            deadline,
+        }) => {
+            assert_deadline(&env, deadline)?;
+
             let config: PairInfoRaw = PAIR_INFO.load(deps.storage)?;
             if deps.api.addr_canonicalize(info.sender.as_str())? != config.liquidity_token {
                 return Err(ContractError::Unauthorized {});
             }
 
             let sender_addr = deps.api.addr_validate(cw20_msg.sender.as_str())?;
-            withdraw_liquidity(deps, env, info, sender_addr, cw20_msg.amount)
+            let receiver_addr = match receiver {
+                Some(receiver) => deps.api.addr_validate(&receiver)?,
+                None => sender_addr.clone(),
+            };
+            withdraw_liquidity(
+                deps,
+                env,
+                sender_addr,
+                receiver_addr,
+                cw20_msg.amount,
+                min_assets,
+            )
         }
+        Ok(_) => Err(ContractError::Unsupported {}),
         Err(err) => Err(ContractError::Std(err)),
     }
 }
@@ -204,28 +267,34 @@ pub fn provide_liquidity(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
-    assets: [Asset; 2],
-    slippage_tolerance: Option<Decimal>,
+    assets: Vec<Asset>,
+    min_out_amount: Uint128,
     receiver: Option<String>,
 ) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage, &env)?;
+
     for asset in assets.iter() {
         asset.assert_sent_native_token_balance(&info)?;
     }
 
     let pair_info: PairInfoRaw = PAIR_INFO.load(deps.storage)?;
     let mut pools: [Asset; 2] =
-        pair_info.query_pools(&deps.querier, deps.api, env.contract.address.clone())?;
+        query_pools(deps.as_ref(), &pair_info, env.contract.address.clone())?;
+    // the constant product takes both assets, anything else is not one of them
+    if assets.len() != 2 {
+        return Err(ContractError::AssetMismatch {});
+    }
     let deposits: [Uint128; 2] = [
         assets
             .iter()
             .find(|a| a.info.equal(&pools[0].info))
             .map(|a| a.amount)
-            .expect("Wrong asset info is given"),
+            .ok_or(ContractError::AssetMismatch {})?,
         assets
             .iter()
             .find(|a| a.info.equal(&pools[1].info))
             .map(|a| a.amount)
-            .expect("Wrong asset info is given"),
+            .ok_or(ContractError::AssetMismatch {})?,
     ];
 
     let mut messages: Vec<CosmosMsg> = vec![];
@@ -248,14 +317,30 @@ pub fn provide_liquidity(
         }
     }
 
-    // assert slippage tolerance
-    assert_slippage_tolerance(&slippage_tolerance, &deposits, &pools)?;
+    update_price_cumulatives(deps.storage, &env, &pools)?;
 
     let liquidity_token = deps.api.addr_humanize(&pair_info.liquidity_token)?;
-    let total_share = query_supply(&deps.querier, liquidity_token)?;
+    let total_share = query_supply(&deps.querier, liquidity_token.clone())?;
     let share = if total_share == Uint128::zero() {
-        // Initial share = collateral amount
-        Uint128::from((deposits[0].u128() * deposits[1].u128()).integer_sqrt())
+        // Initial share = collateral amount, less the share locked in the pair. The product
+        // of the deposits may not fit 128 bits, its square root does.
+        let share: Uint128 = sqrt(Uint256::from(deposits[0]) * Uint256::from(deposits[1])).into();
+        let min_share = Uint128::from(MINIMUM_INITIAL_SHARE);
+        if share < min_share {
+            return Err(ContractError::MinimumLiquidity { min_share, share });
+        }
+
+        let locked_share = Uint128::from(MINIMUM_LIQUIDITY_AMOUNT);
+        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: liquidity_token.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Mint {
+                recipient: env.contract.address.to_string(),
+                amount: locked_share,
+            })?,
+            funds: vec![],
+        }));
+
+        share.checked_sub(locked_share)?
     } else {
         // min(1, 2)
         // 1. sqrt(deposit_0 * exchange_rate_0_to_1 * deposit_0) * (total_share / sqrt(pool_0 * pool_1))
@@ -273,13 +358,17 @@ pub fn provide_liquidity(
         return Err(ContractError::InvalidZeroAmount {});
     }
 
+    if share < min_out_amount {
+        return Err(ContractError::SlippageExceeded {
+            min_out_amount,
+            out_amount: share,
+        });
+    }
+
     // mint LP token to sender
     let receiver = receiver.unwrap_or_else(|| info.sender.to_string());
     messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
-        contract_addr: deps
-            .api
-            .addr_humanize(&pair_info.liquidity_token)?
-            .to_string(),
+        contract_addr: liquidity_token.to_string(),
         msg: to_binary(&Cw20ExecuteMsg::Mint {
             recipient: receiver.to_string(),
             amount: share,
@@ -291,7 +380,14 @@ pub fn provide_liquidity(
         ("action", "provide_liquidity"),
         ("sender", info.sender.as_str()),
         ("receiver", receiver.as_str()),
-        ("assets", &format!("{}, {}", assets[0], assets[1])),
+        (
+            "assets",
+            &assets
+                .iter()
+                .map(|a| a.to_string())
+                .collect::<Vec<String>>()
+                .join(", "),
+        ),
         ("share", &share.to_string()),
     ]))
 }
@@ -299,16 +395,19 @@ pub fn provide_liquidity(
 pub fn withdraw_liquidity(
     deps: DepsMut,
     env: Env,
-    _info: MessageInfo,
     sender: Addr,
+    receiver: Addr,
     amount: Uint128,
+    min_assets: Vec<Asset>,
 ) -> Result<Response, ContractError> {
     let pair_info: PairInfoRaw = PAIR_INFO.load(deps.storage)?;
     let liquidity_addr: Addr = deps.api.addr_humanize(&pair_info.liquidity_token)?;
 
-    let pools: [Asset; 2] = pair_info.query_pools(&deps.querier, deps.api, env.contract.address)?;
+    let pools: [Asset; 2] = query_pools(deps.as_ref(), &pair_info, env.contract.address.clone())?;
     let total_share: Uint128 = query_supply(&deps.querier, liquidity_addr)?;
 
+    update_price_cumulatives(deps.storage, &env, &pools)?;
+
     let share_ratio: Decimal = Decimal::from_ratio(amount, total_share);
     let refund_assets: Vec<Asset> = pools
         .iter()
@@ -317,16 +416,17 @@ pub fn withdraw_liquidity(
             amount: a.amount * share_ratio,
         })
         .collect();
+    assert_min_assets(&refund_assets, &min_assets)?;
 
     // update pool info
     Ok(Response::new()
         .add_messages(vec![
             refund_assets[0]
                 .clone()
-                .into_msg(&deps.querier, sender.clone())?,
+                .into_msg(&deps.querier, receiver.clone())?,
             refund_assets[1]
                 .clone()
-                .into_msg(&deps.querier, sender.clone())?,
+                .into_msg(&deps.querier, receiver.clone())?,
             // burn liquidity token
             CosmosMsg::Wasm(WasmMsg::Execute {
                 contract_addr: deps
@@ -340,6 +440,7 @@ pub fn withdraw_liquidity(
         .add_attributes(vec![
             ("action", "withdraw_liquidity"),
             ("sender", sender.as_str()),
+            ("receiver", receiver.as_str()),
             ("withdrawn_share", &amount.to_string()),
             (
                 "refund_assets",
@@ -356,15 +457,17 @@ pub fn swap(
     info: MessageInfo,
     sender: Addr,
     offer_asset: Asset,
-    belief_price: Option<Decimal>,
-    max_spread: Option<Decimal>,
+    ask_asset_info: AssetInfo,
+    min_out_amount: Uint128,
     to: Option<Addr>,
 ) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage, &env)?;
+
     offer_asset.assert_sent_native_token_balance(&info)?;
 
     let pair_info: PairInfoRaw = PAIR_INFO.load(deps.storage)?;
 
-    let pools: [Asset; 2] = pair_info.query_pools(&deps.querier, deps.api, env.contract.address)?;
+    let pools: [Asset; 2] = query_pools(deps.as_ref(), &pair_info, env.contract.address.clone())?;
 
     let offer_pool: Asset;
     let ask_pool: Asset;
@@ -386,19 +489,27 @@ pub fn swap(
     } else {
         return Err(ContractError::AssetMismatch {});
     }
+    if !ask_asset_info.equal(&ask_pool.info) {
+        return Err(ContractError::AssetMismatch {});
+    }
+
+    let reserves: [Asset; 2] = if offer_pool.info.equal(&pools[0].info) {
+        [offer_pool.clone(), ask_pool.clone()]
+    } else {
+        [ask_pool.clone(), offer_pool.clone()]
+    };
+    update_price_cumulatives(deps.storage, &env, &reserves)?;
 
     let offer_amount = offer_asset.amount;
     let (return_amount, spread_amount, commission_amount) =
         compute_swap(offer_pool.amount, ask_pool.amount, offer_amount);
 
-    // check max spread limit if exist
-    assert_max_spread(
-        belief_price,
-        max_spread,
-        offer_amount,
-        return_amount + commission_amount,
-        spread_amount,
-    )?;
+    if return_amount < min_out_amount {
+        return Err(ContractError::SlippageExceeded {
+            min_out_amount,
+            out_amount: return_amount,
+        });
+    }
 
     // compute tax
     let return_asset = Asset {
@@ -430,17 +541,140 @@ pub fn swap(
     ]))
 }
 
+pub fn pause(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    expiry_height: Option<u64>,
+) -> Result<Response, ContractError> {
+    let mut config = assert_owner_or_guardian(deps.storage, &info.sender)?;
+
+    if let Some(expiry_height) = expiry_height {
+        if expiry_height <= env.block.height {
+            return Err(ContractError::InvalidExpiryHeight {});
+        }
+    }
+
+    config.paused = true;
+    config.pause_expiry_height = expiry_height;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "pause"),
+        (
+            "expiry_height",
+            &expiry_height.map_or("none".to_string(), |height| height.to_string()),
+        ),
+    ]))
+}
+
+pub fn unpause(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    let mut config = assert_owner_or_guardian(deps.storage, &info.sender)?;
+
+    config.paused = false;
+    config.pause_expiry_height = None;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new().add_attribute("action", "unpause"))
+}
+
+pub fn update_guardian(
+    deps: DepsMut,
+    info: MessageInfo,
+    guardian: Option<String>,
+) -> Result<Response, ContractError> {
+    let mut config: Config = CONFIG.load(deps.storage)?;
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    config.guardian = guardian
+        .map(|guardian| deps.api.addr_validate(&guardian))
+        .transpose()?;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "update_guardian"),
+        (
+            "guardian",
+            config
+                .guardian
+                .as_ref()
+                .map_or("none", |addr| addr.as_str()),
+        ),
+    ]))
+}
+
+fn assert_owner_or_guardian(storage: &dyn Storage, sender: &Addr) -> Result<Config, ContractError> {
+    let config: Config = CONFIG.load(storage)?;
+    if *sender != config.owner && config.guardian.as_ref() != Some(sender) {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    Ok(config)
+}
+
+/// Withdrawals are left out so LPs can always exit a paused pair
+fn assert_not_paused(storage: &dyn Storage, env: &Env) -> Result<(), ContractError> {
+    if CONFIG.load(storage)?.is_paused(env.block.height) {
+        return Err(ContractError::Paused {});
+    }
+
+    Ok(())
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> Result<Binary, ContractError> {
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> Result<Binary, ContractError> {
     match msg {
         QueryMsg::Pair {} => Ok(to_binary(&query_pair_info(deps)?)?),
         QueryMsg::Pool {} => Ok(to_binary(&query_pool(deps)?)?),
-        QueryMsg::Simulation { offer_asset } => {
-            Ok(to_binary(&query_simulation(deps, offer_asset)?)?)
-        }
-        QueryMsg::ReverseSimulation { ask_asset } => {
-            Ok(to_binary(&query_reverse_simulation(deps, ask_asset)?)?)
+        QueryMsg::Simulation {
+            offer_asset,
+            ask_asset_info,
+        } => Ok(to_binary(&query_simulation(
+            deps,
+            offer_asset,
+            ask_asset_info,
+        )?)?),
+        QueryMsg::ReverseSimulation {
+            offer_asset_info,
+            ask_asset,
+        } => Ok(to_binary(&query_reverse_simulation(
+            deps,
+            offer_asset_info,
+            ask_asset,
+        )?)?),
+        QueryMsg::CumulativePrices {} => Ok(to_binary(&query_cumulative_prices(deps, env)?)?),
+        QueryMsg::Observe { seconds_ago } => {
+            Ok(to_binary(&query_observe(deps, env, seconds_ago)?)?)
         }
+        QueryMsg::SpotPrice {
+            offer_asset_info,
+            ask_asset_info,
+        } => Ok(to_binary(&query_spot_price(
+            deps,
+            offer_asset_info,
+            ask_asset_info,
+        )?)?),
+        QueryMsg::PriceImpact {
+            offer_asset,
+            ask_asset_info,
+        } => Ok(to_binary(&query_price_impact(
+            deps,
+            offer_asset,
+            ask_asset_info,
+        )?)?),
+        QueryMsg::TradeToPrice {
+            offer_asset_info,
+            ask_asset_info,
+            target_price,
+        } => Ok(to_binary(&query_trade_to_price(
+            deps,
+            offer_asset_info,
+            ask_asset_info,
+            target_price,
+        )?)?),
+        _ => Err(ContractError::Unsupported {}),
     }
 }
 
@@ -454,7 +688,7 @@ pub fn query_pair_info(deps: Deps) -> Result<PairInfo, ContractError> {
 pub fn query_pool(deps: Deps) -> Result<PoolResponse, ContractError> {
     let pair_info: PairInfoRaw = PAIR_INFO.load(deps.storage)?;
     let contract_addr = deps.api.addr_humanize(&pair_info.contract_addr)?;
-    let assets: [Asset; 2] = pair_info.query_pools(&deps.querier, deps.api, contract_addr)?;
+    let assets: Vec<Asset> = pair_info.query_pools(&deps.querier, deps.api, contract_addr)?;
     let total_share: Uint128 = query_supply(
         &deps.querier,
         deps.api.addr_humanize(&pair_info.liquidity_token)?,
@@ -471,23 +705,10 @@ pub fn query_pool(deps: Deps) -> Result<PoolResponse, ContractError> {
 pub fn query_simulation(
     deps: Deps,
     offer_asset: Asset,
+    ask_asset_info: AssetInfo,
 ) -> Result<SimulationResponse, ContractError> {
-    let pair_info: PairInfoRaw = PAIR_INFO.load(deps.storage)?;
-
-    let contract_addr = deps.api.addr_humanize(&pair_info.contract_addr)?;
-    let pools: [Asset; 2] = pair_info.query_pools(&deps.querier, deps.api, contract_addr)?;
-
-    let offer_pool: Asset;
-    let ask_pool: Asset;
-    if offer_asset.info.equal(&pools[0].info) {
-        offer_pool = pools[0].clone();
-        ask_pool = pools[1].clone();
-    } else if offer_asset.info.equal(&pools[1].info) {
-        offer_pool = pools[1].clone();
-        ask_pool = pools[0].clone();
-    } else {
-        return Err(ContractError::AssetMismatch {});
-    }
+    let (offer_pool, ask_pool) =
+        load_offer_and_ask_pools(deps, &offer_asset.info, &ask_asset_info)?;
 
     let (return_amount, spread_amount, commission_amount) =
         compute_swap(offer_pool.amount, ask_pool.amount, offer_asset.amount);
@@ -496,29 +717,18 @@ pub fn query_simulation(
         return_amount,
         spread_amount,
         commission_amount,
+        fee: Uint128::zero(),
+        rates: vec![],
     })
 }
 
 pub fn query_reverse_simulation(
     deps: Deps,
+    offer_asset_info: AssetInfo,
     ask_asset: Asset,
 ) -> Result<ReverseSimulationResponse, ContractError> {
-    let pair_info: PairInfoRaw = PAIR_INFO.load(deps.storage)?;
-
-    let contract_addr = deps.api.addr_humanize(&pair_info.contract_addr)?;
-    let pools: [Asset; 2] = pair_info.query_pools(&deps.querier, deps.api, contract_addr)?;
-
-    let offer_pool: Asset;
-    let ask_pool: Asset;
-    if ask_asset.info.equal(&pools[0].info) {
-        ask_pool = pools[0].clone();
-        offer_pool = pools[1].clone();
-    } else if ask_asset.info.equal(&pools[1].info) {
-        ask_pool = pools[1].clone();
-        offer_pool = pools[0].clone();
-    } else {
-        return Err(ContractError::AssetMismatch {});
-    }
+    let (offer_pool, ask_pool) =
+        load_offer_and_ask_pools(deps, &offer_asset_info, &ask_asset.info)?;
 
     let (offer_amount, spread_amount, commission_amount) =
         compute_offer_amount(offer_pool.amount, ask_pool.amount, ask_asset.amount)?;
@@ -527,9 +737,234 @@ pub fn query_reverse_simulation(
         offer_amount,
         spread_amount,
         commission_amount,
+        fee: Uint128::zero(),
+        rates: vec![],
     })
 }
 
+/// The marginal price of an xyk pool is the ratio of its reserves
+pub fn query_spot_price(
+    deps: Deps,
+    offer_asset_info: AssetInfo,
+    ask_asset_info: AssetInfo,
+) -> Result<SpotPriceResponse, ContractError> {
+    let (offer_pool, ask_pool) =
+        load_offer_and_ask_pools(deps, &offer_asset_info, &ask_asset_info)?;
+    if offer_pool.amount.is_zero() {
+        return Err(ContractError::InvalidZeroAmount {});
+    }
+
+    let precision = Uint128::from(10u128.pow(PRICE_PRECISION));
+    Ok(SpotPriceResponse {
+        price: precision.multiply_ratio(ask_pool.amount, offer_pool.amount),
+        precision: PRICE_PRECISION as u8,
+    })
+}
+
+pub fn query_price_impact(
+    deps: Deps,
+    offer_asset: Asset,
+    ask_asset_info: AssetInfo,
+) -> Result<PriceImpactResponse, ContractError> {
+    let (offer_pool, ask_pool) =
+        load_offer_and_ask_pools(deps, &offer_asset.info, &ask_asset_info)?;
+    if offer_pool.amount.is_zero() || offer_asset.amount.is_zero() {
+        return Err(ContractError::InvalidZeroAmount {});
+    }
+
+    let (return_amount, spread_amount, commission_amount) =
+        compute_swap(offer_pool.amount, ask_pool.amount, offer_asset.amount);
+
+    // the spread is what the swap gets below the spot price before the commission
+    let precision = Uint128::from(10u128.pow(PRICE_PRECISION));
+    let spot_value = return_amount + spread_amount + commission_amount;
+    let price_impact = if spot_value.is_zero() {
+        Decimal::zero()
+    } else {
+        Decimal::from_ratio(spread_amount, spot_value)
+    };
+
+    Ok(PriceImpactResponse {
+        spot_price: precision.multiply_ratio(ask_pool.amount, offer_pool.amount),
+        effective_price: precision.multiply_ratio(return_amount, offer_asset.amount),
+        price_impact,
+        precision: PRICE_PRECISION as u8,
+    })
+}
+
+/// Selling `offer_amount` into the constant product leaves a spot price of
+/// `k / (offer_pool + offer_amount)^2`, so the target is met at
+/// `offer_pool + offer_amount = sqrt(k / target_price)`. The commission the pool keeps leaves
+/// the price a little above the target.
+pub fn query_trade_to_price(
+    deps: Deps,
+    offer_asset_info: AssetInfo,
+    ask_asset_info: AssetInfo,
+    target_price: Uint128,
+) -> Result<TradeToPriceResponse, ContractError> {
+    if target_price.is_zero() {
+        return Err(ContractError::InvalidZeroAmount {});
+    }
+
+    let (offer_pool, ask_pool) =
+        load_offer_and_ask_pools(deps, &offer_asset_info, &ask_asset_info)?;
+
+    let cp: Uint256 = Uint256::from(offer_pool.amount) * Uint256::from(ask_pool.amount);
+    let offer_pool_after = sqrt(cp.multiply_ratio(
+        Uint256::from(10u128.pow(PRICE_PRECISION)),
+        Uint256::from(target_price),
+    ));
+    let offer_amount: Uint128 = if offer_pool_after > Uint256::from(offer_pool.amount) {
+        (offer_pool_after - Uint256::from(offer_pool.amount)).into()
+    } else {
+        Uint128::zero()
+    };
+
+    let (return_amount, commission_amount) = if offer_amount.is_zero() {
+        (Uint128::zero(), Uint128::zero())
+    } else {
+        let (return_amount, _, commission_amount) =
+            compute_swap(offer_pool.amount, ask_pool.amount, offer_amount);
+        (return_amount, commission_amount)
+    };
+
+    Ok(TradeToPriceResponse {
+        offer_amount,
+        return_amount,
+        commission_amount,
+    })
+}
+
+fn load_offer_and_ask_pools(
+    deps: Deps,
+    offer_asset_info: &AssetInfo,
+    ask_asset_info: &AssetInfo,
+) -> Result<(Asset, Asset), ContractError> {
+    let pair_info: PairInfoRaw = PAIR_INFO.load(deps.storage)?;
+
+    let contract_addr = deps.api.addr_humanize(&pair_info.contract_addr)?;
+    let pools: [Asset; 2] = query_pools(deps, &pair_info, contract_addr)?;
+
+    if offer_asset_info.equal(&pools[0].info) && ask_asset_info.equal(&pools[1].info) {
+        Ok((pools[0].clone(), pools[1].clone()))
+    } else if offer_asset_info.equal(&pools[1].info) && ask_asset_info.equal(&pools[0].info) {
+        Ok((pools[1].clone(), pools[0].clone()))
+    } else {
+        Err(ContractError::AssetMismatch {})
+    }
+}
+
+/// Pools of the pair in the order of its asset infos
+fn query_pools(deps: Deps, pair_info: &PairInfoRaw, contract_addr: Addr) -> StdResult<[Asset; 2]> {
+    let pools: Vec<Asset> = pair_info.query_pools(&deps.querier, deps.api, contract_addr)?;
+    pools
+        .try_into()
+        .map_err(|_| StdError::generic_err("the xyk pair holds exactly two assets"))
+}
+
+pub fn query_cumulative_prices(
+    deps: Deps,
+    env: Env,
+) -> Result<CumulativePricesResponse, ContractError> {
+    observe(deps, &env, 0)
+}
+
+pub fn query_observe(
+    deps: Deps,
+    env: Env,
+    seconds_ago: Vec<u64>,
+) -> Result<ObserveResponse, ContractError> {
+    let observations = seconds_ago
+        .into_iter()
+        .map(|seconds_ago| observe(deps, &env, seconds_ago))
+        .collect::<Result<Vec<CumulativePricesResponse>, ContractError>>()?;
+
+    Ok(ObserveResponse { observations })
+}
+
+/// Price accumulators `seconds_ago` before the current block, past the latest observation
+/// they are extrapolated with the current prices
+fn observe(
+    deps: Deps,
+    env: &Env,
+    seconds_ago: u64,
+) -> Result<CumulativePricesResponse, ContractError> {
+    let timestamp = env
+        .block
+        .time
+        .seconds()
+        .checked_sub(seconds_ago)
+        .ok_or(ContractError::ObservationUnavailable { seconds_ago })?;
+    let pair_info: PairInfoRaw = PAIR_INFO.load(deps.storage)?;
+    let pools: [Asset; 2] = query_pools(deps, &pair_info, env.contract.address.clone())?;
+
+    let latest = load_latest_observation(deps.storage)?;
+    let observation = if timestamp >= latest.timestamp {
+        latest.accumulate(timestamp, &compute_prices(&pools))
+    } else {
+        load_observation_at(deps.storage, timestamp)?
+            .ok_or(ContractError::ObservationUnavailable { seconds_ago })?
+    };
+
+    Ok(CumulativePricesResponse {
+        timestamp,
+        price_cumulatives: vec![
+            PriceCumulative {
+                offer_asset_info: pools[0].info.clone(),
+                ask_asset_info: pools[1].info.clone(),
+                price_cumulative: observation.price_cumulatives[0],
+            },
+            PriceCumulative {
+                offer_asset_info: pools[1].info.clone(),
+                ask_asset_info: pools[0].info.clone(),
+                price_cumulative: observation.price_cumulatives[1],
+            },
+        ],
+        precision: PRICE_PRECISION as u8,
+    })
+}
+
+/// Price of each asset in units of the other, zero while the pool is empty
+fn compute_prices(pools: &[Asset; 2]) -> [Uint128; 2] {
+    if pools[0].amount.is_zero() || pools[1].amount.is_zero() {
+        return [Uint128::zero(), Uint128::zero()];
+    }
+
+    let precision = Uint128::from(10u128.pow(PRICE_PRECISION));
+    [
+        precision.multiply_ratio(pools[1].amount, pools[0].amount),
+        precision.multiply_ratio(pools[0].amount, pools[1].amount),
+    ]
+}
+
+/// Accumulates the prices held since the latest observation, on the first action of a block
+fn update_price_cumulatives(
+    storage: &mut dyn Storage,
+    env: &Env,
+    pools: &[Asset; 2],
+) -> StdResult<()> {
+    let latest = load_latest_observation(storage)?;
+    let timestamp = env.block.time.seconds();
+    if timestamp <= latest.timestamp {
+        return Ok(());
+    }
+
+    store_observation(
+        storage,
+        &latest.accumulate(timestamp, &compute_prices(pools)),
+    )
+}
+
+fn assert_deadline(env: &Env, deadline: Option<Expiration>) -> Result<(), ContractError> {
+    if let Some(deadline) = deadline {
+        if deadline.is_expired(&env.block) {
+            return Err(ContractError::DeadlineExpired {});
+        }
+    }
+
+    Ok(())
+}
+
 pub fn amount_of(coins: &[Coin], denom: String) -> Uint128 {
     match coins.iter().find(|x| x.denom == denom) {
         Some(coin) => coin.amount,
@@ -537,6 +972,24 @@ pub fn amount_of(coins: &[Coin], denom: String) -> Uint128 {
     }
 }
 
+/// Integer square root by Newton's method, rounded down
+fn sqrt(value: Uint256) -> Uint256 {
+    if value.is_zero() {
+        return value;
+    }
+
+    let two = Uint256::from(2u64);
+    let mut root = value;
+    let mut next = (value + Uint256::one()).multiply_ratio(Uint256::one(), two);
+    while next < root {
+        root = next;
+        next =
+            (root + value.multiply_ratio(Uint256::one(), root)).multiply_ratio(Uint256::one(), two);
+    }
+
+    root
+}
+
 fn compute_swap(
     offer_pool: Uint128,
     ask_pool: Uint128,
@@ -626,75 +1079,58 @@ fn compute_offer_amount(
     ))
 }
 
-/// If `belief_price` and `max_spread` both are given,
-/// we compute new spread else we just use terraswap
-/// spread to check `max_spread`
-pub fn assert_max_spread(
-    belief_price: Option<Decimal>,
-    max_spread: Option<Decimal>,
-    offer_amount: Uint128,
-    return_amount: Uint128,
-    spread_amount: Uint128,
-) -> Result<(), ContractError> {
-    let offer_amount: Uint256 = offer_amount.into();
-    let return_amount: Uint256 = return_amount.into();
-    let spread_amount: Uint256 = spread_amount.into();
-
-    if let (Some(max_spread), Some(belief_price)) = (max_spread, belief_price) {
-        let belief_price: Decimal256 = belief_price.into();
-        let max_spread: Decimal256 = max_spread.into();
-
-        let expected_return = offer_amount / belief_price;
-        let spread_amount = if expected_return > return_amount {
-            expected_return - return_amount
-        } else {
-            Uint256::zero()
-        };
-
-        if return_amount < expected_return
-            && Decimal256::from_ratio(spread_amount, expected_return) > max_spread
-        {
-            return Err(ContractError::MaxSpreadAssertion {});
-        }
-    } else if let Some(max_spread) = max_spread {
-        let max_spread: Decimal256 = max_spread.into();
-        if Decimal256::from_ratio(spread_amount, return_amount + spread_amount) > max_spread {
-            return Err(ContractError::MaxSpreadAssertion {});
+fn assert_min_assets(assets: &[Asset], min_assets: &[Asset]) -> Result<(), ContractError> {
+    for min_asset in min_assets.iter() {
+        let asset = assets
+            .iter()
+            .find(|a| a.info.equal(&min_asset.info))
+            .ok_or(ContractError::AssetMismatch {})?;
+        if asset.amount < min_asset.amount {
+            return Err(ContractError::MinAssetAssertion {
+                asset: asset.info.to_string(),
+                min_amount: min_asset.amount,
+                amount: asset.amount,
+            });
         }
     }
 
     Ok(())
 }
 
-fn assert_slippage_tolerance(
-    slippage_tolerance: &Option<Decimal>,
-    deposits: &[Uint128; 2],
-    pools: &[Asset; 2],
-) -> Result<(), ContractError> {
-    if let Some(slippage_tolerance) = *slippage_tolerance {
-        let slippage_tolerance: Decimal256 = slippage_tolerance.into();
-        if slippage_tolerance > Decimal256::one() {
-            return Err(StdError::generic_err("slippage_tolerance cannot bigger than 1").into());
-        }
-
-        let one_minus_slippage_tolerance = Decimal256::one() - slippage_tolerance;
-        let deposits: [Uint256; 2] = [deposits[0].into(), deposits[1].into()];
-        let pools: [Uint256; 2] = [pools[0].amount.into(), pools[1].amount.into()];
-
-        // Ensure each prices are not dropped as much as slippage tolerance rate
-        if Decimal256::from_ratio(deposits[0], deposits[1]) * one_minus_slippage_tolerance
-            > Decimal256::from_ratio(pools[0], pools[1])
-            || Decimal256::from_ratio(deposits[1], deposits[0]) * one_minus_slippage_tolerance
-                > Decimal256::from_ratio(pools[1], pools[0])
-        {
-            return Err(ContractError::MaxSlippageAssertion {});
-        }
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+    // pairs instantiated before the shared pair info, the pause and the price accumulators were
+    // added lack their state, it is seeded as instantiate would have, so migrating a current
+    // pair changes nothing
+    if PAIR_INFO.load(deps.storage).is_err() {
+        let legacy_pair_info = LEGACY_PAIR_INFO.load(deps.storage)?;
+        PAIR_INFO.save(
+            deps.storage,
+            &PairInfoRaw {
+                contract_addr: legacy_pair_info.contract_addr,
+                liquidity_token: legacy_pair_info.liquidity_token,
+                asset_infos: legacy_pair_info.asset_infos.to_vec(),
+                amplification: Uint128::zero(),
+                fee: Uint128::from(COMMISSION_FEE),
+                precision_multipliers: vec![],
+            },
+        )?;
+    }
+    if CONFIG.may_load(deps.storage)?.is_none() {
+        let owner = msg
+            .owner
+            .ok_or_else(|| StdError::generic_err("Owner is required to migrate this pair"))?;
+        CONFIG.save(deps.storage, &Config::new(deps.api.addr_validate(&owner)?))?;
+    }
+    if OBSERVATION_INDEX.may_load(deps.storage)?.is_none() {
+        store_observation(
+            deps.storage,
+            &Observation {
+                timestamp: env.block.time.seconds(),
+                price_cumulatives: vec![Uint128::zero(); 2],
+            },
+        )?;
     }
 
-    Ok(())
-}
-
-#[cfg_attr(not(feature = "library"), entry_point)]
-pub fn migrate(_deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
     Ok(Response::default())
 }
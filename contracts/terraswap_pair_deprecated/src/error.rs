@@ -1,4 +1,4 @@
-use cosmwasm_std::{OverflowError, StdError};
+use cosmwasm_std::{OverflowError, StdError, Uint128};
 use thiserror::Error;
 
 #[derive(Error, Debug, PartialEq)]
@@ -15,15 +15,40 @@ pub enum ContractError {
     #[error("Invalid zero amount")]
     InvalidZeroAmount {},
 
-    #[error("Max spread assertion")]
-    MaxSpreadAssertion {},
+    #[error("Slippage exceeded: expected at least {min_out_amount}, got {out_amount}")]
+    SlippageExceeded {
+        min_out_amount: Uint128,
+        out_amount: Uint128,
+    },
 
-    #[error("Max slippage assertion")]
-    MaxSlippageAssertion {},
+    #[error("Withdrawn amount {amount} of {asset} is below the minimum {min_amount}")]
+    MinAssetAssertion {
+        asset: String,
+        min_amount: Uint128,
+        amount: Uint128,
+    },
+
+    #[error("First deposit has to mint at least {min_share} LP tokens, got {share}")]
+    MinimumLiquidity { min_share: Uint128, share: Uint128 },
+
+    #[error("Deadline expired")]
+    DeadlineExpired {},
 
     #[error("Asset mismatch")]
     AssetMismatch {},
 
     #[error("Too small offer amount")]
     TooSmallOfferAmount {},
+
+    #[error("Pair is paused")]
+    Paused {},
+
+    #[error("Pause expiry height must be in the future")]
+    InvalidExpiryHeight {},
+
+    #[error("No observation is kept from {seconds_ago} seconds ago")]
+    ObservationUnavailable { seconds_ago: u64 },
+
+    #[error("Not supported by the xyk pair")]
+    Unsupported {},
 }
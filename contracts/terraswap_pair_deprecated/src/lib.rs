@@ -2,6 +2,7 @@ pub mod contract;
 pub mod state;
 
 mod error;
+#[allow(renamed_and_removed_lints, unused_parens, mismatched_lifetime_syntaxes)]
 mod response;
 
 #[cfg(test)]
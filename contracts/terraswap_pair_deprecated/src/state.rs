@@ -1,9 +1,170 @@
-use cw_storage_plus::Item;
-use terraswap::asset::PairInfoRaw;
+use cosmwasm_std::{Addr, CanonicalAddr, StdResult, Storage, Uint128};
+use cw_storage_plus::{Item, Map, U64Key};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use terraswap::asset::{AssetInfoRaw, PairInfoRaw};
 
 // put the length bytes at the first for compatibility with legacy singleton store
 pub const PAIR_INFO: Item<PairInfoRaw> = Item::new("\u{0}\u{9}pair_info");
 
+/// Pair info as pairs instantiated before the pair info was shared with the stable pair stored it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LegacyPairInfoRaw {
+    pub asset_infos: [AssetInfoRaw; 2],
+    pub contract_addr: CanonicalAddr,
+    pub liquidity_token: CanonicalAddr,
+}
+
+pub const LEGACY_PAIR_INFO: Item<LegacyPairInfoRaw> = Item::new("\u{0}\u{9}pair_info");
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Config {
+    pub owner: Addr,
+    /// Guardian can pause and unpause the pair next to the owner
+    pub guardian: Option<Addr>,
+    pub paused: bool,
+    /// Block height at which a pause lifts by itself
+    pub pause_expiry_height: Option<u64>,
+}
+
+impl Config {
+    /// Config of a new pair, unpaused and without a guardian
+    pub fn new(owner: Addr) -> Self {
+        Config {
+            owner,
+            guardian: None,
+            paused: false,
+            pause_expiry_height: None,
+        }
+    }
+
+    pub fn is_paused(&self, block_height: u64) -> bool {
+        match self.pause_expiry_height {
+            Some(expiry_height) => self.paused && block_height < expiry_height,
+            None => self.paused,
+        }
+    }
+}
+
+pub const CONFIG: Item<Config> = Item::new("config");
+
+/// Observations kept for `Observe`, at most one per block, the oldest are overwritten first
+pub const OBSERVATION_CAPACITY: u64 = 1_000;
+
+/// Price accumulators at `timestamp` in seconds. Each ordered asset pair accumulates its
+/// marginal price times the seconds it held, wrapping around on overflow.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Observation {
+    pub timestamp: u64,
+    pub price_cumulatives: Vec<Uint128>,
+}
+
+impl Observation {
+    /// Accumulates `prices`, held from this observation until `timestamp`
+    pub fn accumulate(&self, timestamp: u64, prices: &[Uint128]) -> Observation {
+        let elapsed = Uint128::from(timestamp - self.timestamp);
+        Observation {
+            timestamp,
+            price_cumulatives: self
+                .price_cumulatives
+                .iter()
+                .zip(prices.iter())
+                .map(|(cumulative, price)| cumulative.wrapping_add(price.wrapping_mul(elapsed)))
+                .collect(),
+        }
+    }
+
+    /// Interpolates between this observation and the later `next` one
+    pub fn interpolate(&self, next: &Observation, timestamp: u64) -> Observation {
+        let elapsed = timestamp - self.timestamp;
+        let duration = next.timestamp - self.timestamp;
+        Observation {
+            timestamp,
+            price_cumulatives: self
+                .price_cumulatives
+                .iter()
+                .zip(next.price_cumulatives.iter())
+                .map(|(cumulative, next_cumulative)| {
+                    cumulative.wrapping_add(
+                        next_cumulative
+                            .wrapping_sub(*cumulative)
+                            .multiply_ratio(elapsed, duration),
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Slot of the latest observation and the number of slots in use
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ObservationIndex {
+    pub latest: u64,
+    pub length: u64,
+}
+
+/// Ring buffer of observations keyed by slot
+pub const OBSERVATIONS: Map<U64Key, Observation> = Map::new("observations");
+pub const OBSERVATION_INDEX: Item<ObservationIndex> = Item::new("observation_index");
+
+pub fn store_observation(storage: &mut dyn Storage, observation: &Observation) -> StdResult<()> {
+    let index = match OBSERVATION_INDEX.may_load(storage)? {
+        Some(index) => ObservationIndex {
+            latest: (index.latest + 1) % OBSERVATION_CAPACITY,
+            length: (index.length + 1).min(OBSERVATION_CAPACITY),
+        },
+        None => ObservationIndex {
+            latest: 0,
+            length: 1,
+        },
+    };
+    OBSERVATIONS.save(storage, U64Key::new(index.latest), observation)?;
+    OBSERVATION_INDEX.save(storage, &index)
+}
+
+pub fn load_latest_observation(storage: &dyn Storage) -> StdResult<Observation> {
+    let index = OBSERVATION_INDEX.load(storage)?;
+    OBSERVATIONS.load(storage, U64Key::new(index.latest))
+}
+
+/// Finds the accumulators at `timestamp`, which must not be after the latest observation.
+/// Returns `None` when `timestamp` is before the oldest observation kept.
+pub fn load_observation_at(
+    storage: &dyn Storage,
+    timestamp: u64,
+) -> StdResult<Option<Observation>> {
+    let index = OBSERVATION_INDEX.load(storage)?;
+    let oldest = (index.latest + 1 + OBSERVATION_CAPACITY - index.length) % OBSERVATION_CAPACITY;
+    let load = |position: u64| {
+        OBSERVATIONS.load(
+            storage,
+            U64Key::new((oldest + position) % OBSERVATION_CAPACITY),
+        )
+    };
+
+    if timestamp < load(0)?.timestamp {
+        return Ok(None);
+    }
+
+    // binary search the last observation at or before `timestamp`
+    let (mut low, mut high) = (0, index.length - 1);
+    while low < high {
+        let mid = (low + high).div_ceil(2);
+        if load(mid)?.timestamp <= timestamp {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+
+    let before = load(low)?;
+    if before.timestamp == timestamp || low == index.length - 1 {
+        return Ok(Some(before));
+    }
+
+    Ok(Some(before.interpolate(&load(low + 1)?, timestamp)))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -11,7 +172,6 @@ mod test {
     use cosmwasm_std::testing::mock_dependencies;
     use cosmwasm_std::{Api, StdResult, Storage};
     use cosmwasm_storage::{singleton, singleton_read};
-    use terraswap::asset::AssetInfoRaw;
     const KEY_PAIR_INFO: &[u8] = b"pair_info";
 
     pub fn store_pair_info(storage: &mut dyn Storage, config: &PairInfoRaw) -> StdResult<()> {
@@ -27,7 +187,7 @@ mod test {
         store_pair_info(
             &mut deps.storage,
             &PairInfoRaw {
-                asset_infos: [
+                asset_infos: vec![
                     AssetInfoRaw::NativeToken {
                         denom: "uusd".to_string(),
                     },
@@ -37,6 +197,9 @@ mod test {
                 ],
                 contract_addr: deps.api.addr_canonicalize("pair0000").unwrap(),
                 liquidity_token: deps.api.addr_canonicalize("liquidity0000").unwrap(),
+                amplification: Uint128::zero(),
+                fee: Uint128::from(30u128),
+                precision_multipliers: vec![],
             },
         )
         .unwrap();
@@ -46,4 +209,56 @@ mod test {
             read_pair_info(&deps.storage).unwrap()
         );
     }
+
+    #[test]
+    fn observation_ring_buffer() {
+        let mut deps = mock_dependencies(&[]);
+        let observation = |i: u64| Observation {
+            timestamp: 10 * i,
+            price_cumulatives: vec![Uint128::from(100 * i), Uint128::from(200 * i)],
+        };
+
+        // five past the capacity, the first five are overwritten
+        for i in 0..OBSERVATION_CAPACITY + 5 {
+            store_observation(&mut deps.storage, &observation(i)).unwrap();
+        }
+        assert_eq!(
+            OBSERVATION_INDEX.load(&deps.storage).unwrap(),
+            ObservationIndex {
+                latest: 4,
+                length: OBSERVATION_CAPACITY,
+            }
+        );
+        assert_eq!(
+            load_latest_observation(&deps.storage).unwrap(),
+            observation(OBSERVATION_CAPACITY + 4)
+        );
+
+        assert_eq!(load_observation_at(&deps.storage, 49).unwrap(), None);
+        for i in 5..OBSERVATION_CAPACITY + 5 {
+            assert_eq!(
+                load_observation_at(&deps.storage, 10 * i).unwrap(),
+                Some(observation(i))
+            );
+        }
+
+        // between the last slot and the first one, across the wrap
+        assert_eq!(
+            load_observation_at(&deps.storage, 10 * (OBSERVATION_CAPACITY - 1) + 5).unwrap(),
+            Some(Observation {
+                timestamp: 10 * (OBSERVATION_CAPACITY - 1) + 5,
+                price_cumulatives: vec![
+                    Uint128::from(100 * (OBSERVATION_CAPACITY - 1) + 50),
+                    Uint128::from(200 * (OBSERVATION_CAPACITY - 1) + 100),
+                ],
+            })
+        );
+        assert_eq!(
+            load_observation_at(&deps.storage, 10 * 7 + 3).unwrap(),
+            Some(Observation {
+                timestamp: 73,
+                price_cumulatives: vec![Uint128::from(730u128), Uint128::from(1_460u128)],
+            })
+        );
+    }
 }
@@ -1,20 +1,23 @@
 use crate::contract::{
-    assert_max_spread, execute, instantiate, query_pair_info, query_pool, query_reverse_simulation,
-    query_simulation, reply,
+    execute, instantiate, migrate, query_cumulative_prices, query_observe, query_pair_info,
+    query_pool, query_price_impact, query_reverse_simulation, query_simulation, query_spot_price,
+    query_trade_to_price, reply,
 };
 use crate::error::ContractError;
 use crate::mock_querier::mock_dependencies;
+use crate::state::{Config, LegacyPairInfoRaw, CONFIG, OBSERVATION_INDEX};
 
 use cosmwasm_std::testing::{mock_env, mock_info, MOCK_CONTRACT_ADDR};
 use cosmwasm_std::{
-    attr, to_binary, BankMsg, Coin, ContractResult, CosmosMsg, Decimal, Reply, ReplyOn, Response,
-    StdError, SubMsg, SubMsgExecutionResponse, Uint128, WasmMsg,
+    attr, to_binary, Addr, Api, BankMsg, Coin, ContractResult, CosmosMsg, Decimal, Reply, ReplyOn,
+    Response, StdError, SubMsg, SubMsgExecutionResponse, Uint128, WasmMsg,
 };
-use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg, MinterResponse};
-use terraswap::asset::{Asset, AssetInfo, PairInfo};
+use cosmwasm_storage::singleton;
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg, Expiration, MinterResponse};
+use terraswap::asset::{Asset, AssetInfo, AssetInfoRaw, PairInfo};
 use terraswap::pair::{
-    Cw20HookMsg, ExecuteMsg, InstantiateMsg, PoolResponse, ReverseSimulationResponse,
-    SimulationResponse,
+    Cw20HookMsg, ExecuteMsg, InstantiateMsg, MigrateMsg, PoolResponse, PriceImpactResponse,
+    ReverseSimulationResponse, SimulationResponse, SpotPriceResponse, TradeToPriceResponse,
 };
 use terraswap::token::InstantiateMsg as TokenInstantiateMsg;
 
@@ -23,7 +26,7 @@ fn proper_initialization() {
     let mut deps = mock_dependencies(&[]);
 
     let msg = InstantiateMsg {
-        asset_infos: [
+        asset_infos: vec![
             AssetInfo::NativeToken {
                 denom: "uusd".to_string(),
             },
@@ -31,7 +34,16 @@ fn proper_initialization() {
                 contract_addr: "asset0000".to_string(),
             },
         ],
+        amplification: Uint128::zero(),
+        fee: Uint128::zero(),
         token_code_id: 10u64,
+        native_decimals: vec![],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
     };
 
     // we can just call .unwrap() to assert this was a success
@@ -101,7 +113,7 @@ fn proper_initialization() {
 fn provide_liquidity() {
     let mut deps = mock_dependencies(&[Coin {
         denom: "uusd".to_string(),
-        amount: Uint128::from(200u128),
+        amount: Uint128::from(2000000u128),
     }]);
 
     deps.querier.with_token_balances(&[
@@ -113,7 +125,7 @@ fn provide_liquidity() {
     ]);
 
     let msg = InstantiateMsg {
-        asset_infos: [
+        asset_infos: vec![
             AssetInfo::NativeToken {
                 denom: "uusd".to_string(),
             },
@@ -121,7 +133,16 @@ fn provide_liquidity() {
                 contract_addr: "asset0000".to_string(),
             },
         ],
+        amplification: Uint128::zero(),
+        fee: Uint128::zero(),
         token_code_id: 10u64,
+        native_decimals: vec![],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
     };
 
     let env = mock_env();
@@ -147,22 +168,23 @@ fn provide_liquidity() {
 
     // successfully provide liquidity for the exist pool
     let msg = ExecuteMsg::ProvideLiquidity {
-        assets: [
+        assets: vec![
             Asset {
                 info: AssetInfo::Token {
                     contract_addr: "asset0000".to_string(),
                 },
-                amount: Uint128::from(100u128),
+                amount: Uint128::from(2000000u128),
             },
             Asset {
                 info: AssetInfo::NativeToken {
                     denom: "uusd".to_string(),
                 },
-                amount: Uint128::from(100u128),
+                amount: Uint128::from(2000000u128),
             },
         ],
-        slippage_tolerance: None,
+        min_out_amount: Uint128::zero(),
         receiver: None,
+        deadline: None,
     };
 
     let env = mock_env();
@@ -170,12 +192,37 @@ fn provide_liquidity() {
         "addr0000",
         &[Coin {
             denom: "uusd".to_string(),
-            amount: Uint128::from(100u128),
+            amount: Uint128::from(2000000u128),
+        }],
+    );
+
+    // a first deposit minting less than the minimum is rejected
+    let mut small_msg = msg.clone();
+    if let ExecuteMsg::ProvideLiquidity { assets, .. } = &mut small_msg {
+        for asset in assets.iter_mut() {
+            asset.amount = Uint128::from(2000u128);
+        }
+    }
+    let small_info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(2000u128),
         }],
     );
+    let res = execute(deps.as_mut(), env.clone(), small_info, small_msg);
+    assert_eq!(
+        res,
+        Err(ContractError::MinimumLiquidity {
+            min_share: Uint128::from(1000000u128),
+            share: Uint128::from(2000u128),
+        })
+    );
+
     let res = execute(deps.as_mut(), env, info, msg).unwrap();
-    let transfer_from_msg = res.messages.get(0).expect("no message");
-    let mint_msg = res.messages.get(1).expect("no message");
+    let transfer_from_msg = res.messages.first().expect("no message");
+    let lock_msg = res.messages.get(1).expect("no message");
+    let mint_msg = res.messages.get(2).expect("no message");
     assert_eq!(
         transfer_from_msg,
         &SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
@@ -183,7 +230,20 @@ fn provide_liquidity() {
             msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
                 owner: "addr0000".to_string(),
                 recipient: MOCK_CONTRACT_ADDR.to_string(),
-                amount: Uint128::from(100u128),
+                amount: Uint128::from(2000000u128),
+            })
+            .unwrap(),
+            funds: vec![],
+        }))
+    );
+    // the first deposit locks the minimum liquidity in the pair
+    assert_eq!(
+        lock_msg,
+        &SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "liquidity0000".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Mint {
+                recipient: MOCK_CONTRACT_ADDR.to_string(),
+                amount: Uint128::from(1000u128),
             })
             .unwrap(),
             funds: vec![],
@@ -195,7 +255,7 @@ fn provide_liquidity() {
             contract_addr: "liquidity0000".to_string(),
             msg: to_binary(&Cw20ExecuteMsg::Mint {
                 recipient: "addr0000".to_string(),
-                amount: Uint128::from(100u128),
+                amount: Uint128::from(1999000u128),
             })
             .unwrap(),
             funds: vec![],
@@ -226,7 +286,7 @@ fn provide_liquidity() {
     ]);
 
     let msg = ExecuteMsg::ProvideLiquidity {
-        assets: [
+        assets: vec![
             Asset {
                 info: AssetInfo::Token {
                     contract_addr: "asset0000".to_string(),
@@ -240,8 +300,9 @@ fn provide_liquidity() {
                 amount: Uint128::from(200u128),
             },
         ],
-        slippage_tolerance: None,
+        min_out_amount: Uint128::zero(),
         receiver: Some("staking0000".to_string()), // try changing receiver
+        deadline: None,
     };
 
     let env = mock_env();
@@ -255,7 +316,7 @@ fn provide_liquidity() {
 
     // only accept 100, then 50 share will be generated with 100 * (100 / 200)
     let res: Response = execute(deps.as_mut(), env, info, msg).unwrap();
-    let transfer_from_msg = res.messages.get(0).expect("no message");
+    let transfer_from_msg = res.messages.first().expect("no message");
     let mint_msg = res.messages.get(1).expect("no message");
     assert_eq!(
         transfer_from_msg,
@@ -285,7 +346,7 @@ fn provide_liquidity() {
 
     // check wrong argument
     let msg = ExecuteMsg::ProvideLiquidity {
-        assets: [
+        assets: vec![
             Asset {
                 info: AssetInfo::Token {
                     contract_addr: "asset0000".to_string(),
@@ -299,8 +360,9 @@ fn provide_liquidity() {
                 amount: Uint128::from(50u128),
             },
         ],
-        slippage_tolerance: None,
+        min_out_amount: Uint128::zero(),
         receiver: None,
+        deadline: None,
     };
 
     let env = mock_env();
@@ -342,9 +404,9 @@ fn provide_liquidity() {
         ),
     ]);
 
-    // failed because the price is under slippage_tolerance
+    // failed because the share is under min_out_amount
     let msg = ExecuteMsg::ProvideLiquidity {
-        assets: [
+        assets: vec![
             Asset {
                 info: AssetInfo::Token {
                     contract_addr: "asset0000".to_string(),
@@ -358,8 +420,9 @@ fn provide_liquidity() {
                 amount: Uint128::from(100u128),
             },
         ],
-        slippage_tolerance: Some(Decimal::percent(1)),
+        min_out_amount: Uint128::from(99u128),
         receiver: None,
+        deadline: None,
     };
 
     let env = mock_env();
@@ -371,10 +434,13 @@ fn provide_liquidity() {
         }],
     );
     let res = execute(deps.as_mut(), env, info, msg).unwrap_err();
-    match res {
-        ContractError::MaxSlippageAssertion {} => {}
-        _ => panic!("DO NOT ENTER HERE"),
-    }
+    assert_eq!(
+        res,
+        ContractError::SlippageExceeded {
+            min_out_amount: Uint128::from(99u128),
+            out_amount: Uint128::from(98u128),
+        }
+    );
 
     // initialize token balance to 1:1
     deps.querier.with_balance(&[(
@@ -385,9 +451,9 @@ fn provide_liquidity() {
         }],
     )]);
 
-    // failed because the price is under slippage_tolerance
+    // failed because the share is under min_out_amount
     let msg = ExecuteMsg::ProvideLiquidity {
-        assets: [
+        assets: vec![
             Asset {
                 info: AssetInfo::Token {
                     contract_addr: "asset0000".to_string(),
@@ -401,8 +467,9 @@ fn provide_liquidity() {
                 amount: Uint128::from(98u128),
             },
         ],
-        slippage_tolerance: Some(Decimal::percent(1)),
+        min_out_amount: Uint128::from(99u128),
         receiver: None,
+        deadline: None,
     };
 
     let env = mock_env();
@@ -414,10 +481,13 @@ fn provide_liquidity() {
         }],
     );
     let res = execute(deps.as_mut(), env, info, msg).unwrap_err();
-    match res {
-        ContractError::MaxSlippageAssertion {} => {}
-        _ => panic!("DO NOT ENTER HERE"),
-    }
+    assert_eq!(
+        res,
+        ContractError::SlippageExceeded {
+            min_out_amount: Uint128::from(99u128),
+            out_amount: Uint128::from(98u128),
+        }
+    );
 
     // initialize token balance to 1:1
     deps.querier.with_balance(&[(
@@ -432,7 +502,7 @@ fn provide_liquidity() {
 
     // successfully provides
     let msg = ExecuteMsg::ProvideLiquidity {
-        assets: [
+        assets: vec![
             Asset {
                 info: AssetInfo::Token {
                     contract_addr: "asset0000".to_string(),
@@ -446,8 +516,9 @@ fn provide_liquidity() {
                 amount: Uint128::from(100u128),
             },
         ],
-        slippage_tolerance: Some(Decimal::percent(1)),
+        min_out_amount: Uint128::from(99u128),
         receiver: None,
+        deadline: None,
     };
 
     let env = mock_env();
@@ -471,7 +542,7 @@ fn provide_liquidity() {
 
     // successfully provides
     let msg = ExecuteMsg::ProvideLiquidity {
-        assets: [
+        assets: vec![
             Asset {
                 info: AssetInfo::Token {
                     contract_addr: "asset0000".to_string(),
@@ -485,8 +556,9 @@ fn provide_liquidity() {
                 amount: Uint128::from(99u128),
             },
         ],
-        slippage_tolerance: Some(Decimal::percent(1)),
+        min_out_amount: Uint128::from(99u128),
         receiver: None,
+        deadline: None,
     };
 
     let env = mock_env();
@@ -498,6 +570,143 @@ fn provide_liquidity() {
         }],
     );
     let _res = execute(deps.as_mut(), env, info, msg).unwrap();
+
+    // both assets of the pair are required, and nothing else
+    let uusd = Asset {
+        info: AssetInfo::NativeToken {
+            denom: "uusd".to_string(),
+        },
+        amount: Uint128::from(100u128),
+    };
+    let asset0000 = Asset {
+        info: AssetInfo::Token {
+            contract_addr: "asset0000".to_string(),
+        },
+        amount: Uint128::from(100u128),
+    };
+    let asset0001 = Asset {
+        info: AssetInfo::Token {
+            contract_addr: "asset0001".to_string(),
+        },
+        amount: Uint128::from(100u128),
+    };
+    for assets in [
+        vec![asset0000.clone()],
+        vec![asset0000.clone(), asset0001.clone()],
+        vec![asset0000, uusd.clone(), asset0001],
+    ] {
+        let msg = ExecuteMsg::ProvideLiquidity {
+            assets,
+            min_out_amount: Uint128::zero(),
+            receiver: None,
+            deadline: None,
+        };
+        let info = mock_info(
+            "addr0001",
+            &[Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(100u128),
+            }],
+        );
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+        assert_eq!(res, ContractError::AssetMismatch {});
+    }
+}
+
+#[test]
+fn provide_liquidity_large_first_deposit() {
+    // the product of the deposits is far beyond 128 bits
+    let deposit = Uint128::from(1_000_000_000_000_000_000_000_000_000_000u128);
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: deposit,
+    }]);
+
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::zero())],
+        ),
+        (&"asset0000".to_string(), &[]),
+    ]);
+
+    let msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::zero(),
+        fee: Uint128::zero(),
+        token_code_id: 10u64,
+        native_decimals: vec![],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+    let _res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // store liquidity token
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(
+                vec![
+                    10, 13, 108, 105, 113, 117, 105, 100, 105, 116, 121, 48, 48, 48, 48,
+                ]
+                .into(),
+            ),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    let msg = ExecuteMsg::ProvideLiquidity {
+        assets: vec![
+            Asset {
+                info: AssetInfo::Token {
+                    contract_addr: "asset0000".to_string(),
+                },
+                amount: deposit,
+            },
+            Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+                amount: deposit,
+            },
+        ],
+        min_out_amount: Uint128::zero(),
+        receiver: None,
+        deadline: None,
+    };
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: deposit,
+        }],
+    );
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    let mint_msg = res.messages.get(2).expect("no message");
+    assert_eq!(
+        mint_msg,
+        &SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "liquidity0000".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Mint {
+                recipient: "addr0000".to_string(),
+                amount: deposit - Uint128::from(1000u128),
+            })
+            .unwrap(),
+            funds: vec![],
+        }))
+    );
 }
 
 #[test]
@@ -523,7 +732,7 @@ fn withdraw_liquidity() {
     ]);
 
     let msg = InstantiateMsg {
-        asset_infos: [
+        asset_infos: vec![
             AssetInfo::NativeToken {
                 denom: "uusd".to_string(),
             },
@@ -531,7 +740,16 @@ fn withdraw_liquidity() {
                 contract_addr: "asset0000".to_string(),
             },
         ],
+        amplification: Uint128::zero(),
+        fee: Uint128::zero(),
         token_code_id: 10u64,
+        native_decimals: vec![],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
     };
 
     let env = mock_env();
@@ -558,16 +776,21 @@ fn withdraw_liquidity() {
     // withdraw liquidity
     let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
         sender: "addr0000".to_string(),
-        msg: to_binary(&Cw20HookMsg::WithdrawLiquidity {}).unwrap(),
+        msg: to_binary(&Cw20HookMsg::WithdrawLiquidity {
+            min_assets: vec![],
+            receiver: None,
+            deadline: None,
+        })
+        .unwrap(),
         amount: Uint128::from(100u128),
     });
 
     let env = mock_env();
     let info = mock_info("liquidity0000", &[]);
     let res = execute(deps.as_mut(), env, info, msg).unwrap();
-    let log_withdrawn_share = res.attributes.get(2).expect("no log");
-    let log_refund_assets = res.attributes.get(3).expect("no log");
-    let msg_refund_0 = res.messages.get(0).expect("no message");
+    let log_withdrawn_share = res.attributes.get(3).expect("no log");
+    let log_refund_assets = res.attributes.get(4).expect("no log");
+    let msg_refund_0 = res.messages.first().expect("no message");
     let msg_refund_1 = res.messages.get(1).expect("no message");
     let msg_burn_liquidity = res.messages.get(2).expect("no message");
     assert_eq!(
@@ -644,7 +867,7 @@ fn try_native_to_token() {
     ]);
 
     let msg = InstantiateMsg {
-        asset_infos: [
+        asset_infos: vec![
             AssetInfo::NativeToken {
                 denom: "uusd".to_string(),
             },
@@ -652,7 +875,16 @@ fn try_native_to_token() {
                 contract_addr: "asset0000".to_string(),
             },
         ],
+        amplification: Uint128::zero(),
+        fee: Uint128::zero(),
         token_code_id: 10u64,
+        native_decimals: vec![],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
     };
 
     let env = mock_env();
@@ -684,9 +916,15 @@ fn try_native_to_token() {
             },
             amount: offer_amount,
         },
-        belief_price: None,
-        max_spread: None,
+        ask_asset: Asset {
+            info: AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+            amount: Uint128::zero(),
+        },
+        min_out_amount: Uint128::zero(),
         to: None,
+        deadline: None,
     };
     let env = mock_env();
     let info = mock_info(
@@ -697,7 +935,7 @@ fn try_native_to_token() {
         }],
     );
     let res = execute(deps.as_mut(), env, info, msg).unwrap();
-    let msg_transfer = res.messages.get(0).expect("no message");
+    let msg_transfer = res.messages.first().expect("no message");
 
     // current price is 1.5, so expected return without spread is 1000
     // 952.380952 = 20000 - 20000 * 30000 / (30000 + 1500)
@@ -728,6 +966,9 @@ fn try_native_to_token() {
             },
             amount: offer_amount,
         },
+        AssetInfo::Token {
+            contract_addr: "asset0000".to_string(),
+        },
     )
     .unwrap();
     assert_eq!(expected_return_amount, simulation_res.return_amount);
@@ -737,6 +978,9 @@ fn try_native_to_token() {
     // check reverse simulation res
     let reverse_simulation_res: ReverseSimulationResponse = query_reverse_simulation(
         deps.as_ref(),
+        AssetInfo::NativeToken {
+            denom: "uusd".to_string(),
+        },
         Asset {
             info: AssetInfo::Token {
                 contract_addr: "asset0000".to_string(),
@@ -791,6 +1035,48 @@ fn try_native_to_token() {
         })),
         msg_transfer,
     );
+
+    // failed because the return amount is under min_out_amount
+    deps.querier.with_balance(&[(
+        &MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: collateral_pool_amount + offer_amount, /* user deposit must be pre-applied */
+        }],
+    )]);
+    let msg = ExecuteMsg::Swap {
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: offer_amount,
+        },
+        ask_asset: Asset {
+            info: AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+            amount: Uint128::zero(),
+        },
+        min_out_amount: expected_return_amount + Uint128::from(1u128),
+        to: None,
+        deadline: None,
+    };
+    let env = mock_env();
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: offer_amount,
+        }],
+    );
+    let res = execute(deps.as_mut(), env, info, msg).unwrap_err();
+    assert_eq!(
+        res,
+        ContractError::SlippageExceeded {
+            min_out_amount: expected_return_amount + Uint128::from(1u128),
+            out_amount: expected_return_amount,
+        }
+    );
 }
 
 #[test]
@@ -824,7 +1110,7 @@ fn try_token_to_native() {
     ]);
 
     let msg = InstantiateMsg {
-        asset_infos: [
+        asset_infos: vec![
             AssetInfo::NativeToken {
                 denom: "uusd".to_string(),
             },
@@ -832,7 +1118,16 @@ fn try_token_to_native() {
                 contract_addr: "asset0000".to_string(),
             },
         ],
+        amplification: Uint128::zero(),
+        fee: Uint128::zero(),
         token_code_id: 10u64,
+        native_decimals: vec![],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
     };
 
     let env = mock_env();
@@ -864,9 +1159,15 @@ fn try_token_to_native() {
             },
             amount: offer_amount,
         },
-        belief_price: None,
-        max_spread: None,
+        ask_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::zero(),
+        },
+        min_out_amount: Uint128::zero(),
         to: None,
+        deadline: None,
     };
     let env = mock_env();
     let info = mock_info("addr0000", &[]);
@@ -881,9 +1182,15 @@ fn try_token_to_native() {
         sender: "addr0000".to_string(),
         amount: offer_amount,
         msg: to_binary(&Cw20HookMsg::Swap {
-            belief_price: None,
-            max_spread: None,
+            ask_asset: Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+                amount: Uint128::zero(),
+            },
+            min_out_amount: Uint128::zero(),
             to: None,
+            deadline: None,
         })
         .unwrap(),
     });
@@ -891,7 +1198,7 @@ fn try_token_to_native() {
     let info = mock_info("asset0000", &[]);
 
     let res = execute(deps.as_mut(), env, info, msg).unwrap();
-    let msg_transfer = res.messages.get(0).expect("no message");
+    let msg_transfer = res.messages.first().expect("no message");
 
     // current price is 1.5, so expected return without spread is 1000
     // 952.380952 = 20000 - 20000 * 30000 / (30000 + 1500)
@@ -933,6 +1240,9 @@ fn try_token_to_native() {
                 contract_addr: "asset0000".to_string(),
             },
         },
+        AssetInfo::NativeToken {
+            denom: "uusd".to_string(),
+        },
     )
     .unwrap();
     assert_eq!(expected_return_amount, simulation_res.return_amount);
@@ -942,6 +1252,9 @@ fn try_token_to_native() {
     // check reverse simulation res
     let reverse_simulation_res: ReverseSimulationResponse = query_reverse_simulation(
         deps.as_ref(),
+        AssetInfo::Token {
+            contract_addr: "asset0000".to_string(),
+        },
         Asset {
             amount: expected_return_amount,
             info: AssetInfo::NativeToken {
@@ -1001,9 +1314,15 @@ fn try_token_to_native() {
         sender: "addr0000".to_string(),
         amount: offer_amount,
         msg: to_binary(&Cw20HookMsg::Swap {
-            belief_price: None,
-            max_spread: None,
+            ask_asset: Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+                amount: Uint128::zero(),
+            },
+            min_out_amount: Uint128::zero(),
             to: None,
+            deadline: None,
         })
         .unwrap(),
     });
@@ -1017,96 +1336,30 @@ fn try_token_to_native() {
 }
 
 #[test]
-fn test_max_spread() {
-    assert_max_spread(
-        Some(Decimal::from_ratio(1200u128, 1u128)),
-        Some(Decimal::percent(1)),
-        Uint128::from(1200000000u128),
-        Uint128::from(989999u128),
-        Uint128::zero(),
-    )
-    .unwrap_err();
-
-    assert_max_spread(
-        Some(Decimal::from_ratio(1200u128, 1u128)),
-        Some(Decimal::percent(1)),
-        Uint128::from(1200000000u128),
-        Uint128::from(990000u128),
-        Uint128::zero(),
-    )
-    .unwrap();
-
-    assert_max_spread(
-        None,
-        Some(Decimal::percent(1)),
-        Uint128::zero(),
-        Uint128::from(989999u128),
-        Uint128::from(10001u128),
-    )
-    .unwrap_err();
-
-    assert_max_spread(
-        None,
-        Some(Decimal::percent(1)),
-        Uint128::zero(),
-        Uint128::from(990000u128),
-        Uint128::from(10000u128),
-    )
-    .unwrap();
-}
-
-#[test]
-fn test_deduct() {
-    let mut deps = mock_dependencies(&[]);
-
-    let tax_rate = Decimal::percent(2);
-    let tax_cap = Uint128::from(1_000_000u128);
-    deps.querier.with_tax(
-        Decimal::percent(2),
-        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
-    );
-
-    let amount = Uint128::from(1_000_000_000u128);
-    let expected_after_amount = std::cmp::max(
-        amount.checked_sub(amount * tax_rate).unwrap(),
-        amount.checked_sub(tax_cap).unwrap(),
-    );
-
-    let after_amount = (Asset {
-        info: AssetInfo::NativeToken {
-            denom: "uusd".to_string(),
-        },
-        amount,
-    })
-    .deduct_tax(&deps.as_ref().querier)
-    .unwrap();
-
-    assert_eq!(expected_after_amount, after_amount.amount);
-}
-
-#[test]
-fn test_query_pool() {
-    let total_share_amount = Uint128::from(111u128);
-    let asset_0_amount = Uint128::from(222u128);
-    let asset_1_amount = Uint128::from(333u128);
+fn pause() {
     let mut deps = mock_dependencies(&[Coin {
         denom: "uusd".to_string(),
-        amount: asset_0_amount,
+        amount: Uint128::from(1100u128),
     }]);
 
+    deps.querier.with_tax(
+        Decimal::zero(),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
     deps.querier.with_token_balances(&[
         (
-            &"asset0000".to_string(),
-            &[(&MOCK_CONTRACT_ADDR.to_string(), &asset_1_amount)],
+            &"liquidity0000".to_string(),
+            &[(&"addr0000".to_string(), &Uint128::from(1000u128))],
         ),
         (
-            &"liquidity0000".to_string(),
-            &[(&MOCK_CONTRACT_ADDR.to_string(), &total_share_amount)],
+            &"asset0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::from(1000u128))],
         ),
     ]);
 
+    // the owner is taken from the message rather than the sender
     let msg = InstantiateMsg {
-        asset_infos: [
+        asset_infos: vec![
             AssetInfo::NativeToken {
                 denom: "uusd".to_string(),
             },
@@ -1114,13 +1367,18 @@ fn test_query_pool() {
                 contract_addr: "asset0000".to_string(),
             },
         ],
+        amplification: Uint128::zero(),
+        fee: Uint128::zero(),
         token_code_id: 10u64,
+        native_decimals: vec![],
+        owner: Some("owner0000".to_string()),
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
     };
-
-    let env = mock_env();
-    let info = mock_info("addr0000", &[]);
-    // we can just call .unwrap() to assert this was a success
-    let _res = instantiate(deps.as_mut(), env, info, msg).unwrap();
+    let _res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
 
     // store liquidity token
     let reply_msg = Reply {
@@ -1135,13 +1393,280 @@ fn test_query_pool() {
             ),
         }),
     };
-
     let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
 
-    let res: PoolResponse = query_pool(deps.as_ref()).unwrap();
+    let config = CONFIG.load(deps.as_ref().storage).unwrap();
+    assert_eq!(config, Config::new(Addr::unchecked("owner0000")));
 
-    assert_eq!(
-        res.assets,
+    // only the owner sets the guardian
+    let msg = ExecuteMsg::UpdateGuardian {
+        guardian: Some("guardian0000".to_string()),
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("owner0000", &[]), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "update_guardian"),
+            attr("guardian", "guardian0000")
+        ]
+    );
+
+    let guardian = mock_info("guardian0000", &[]);
+    let height = mock_env().block.height;
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        ExecuteMsg::Pause {
+            expiry_height: None,
+        },
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+
+    let msg = ExecuteMsg::Pause {
+        expiry_height: Some(height),
+    };
+    let res = execute(deps.as_mut(), mock_env(), guardian.clone(), msg).unwrap_err();
+    assert_eq!(res, ContractError::InvalidExpiryHeight {});
+
+    let msg = ExecuteMsg::Pause {
+        expiry_height: Some(height + 100),
+    };
+    let res = execute(deps.as_mut(), mock_env(), guardian.clone(), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "pause"),
+            attr("expiry_height", (height + 100).to_string())
+        ]
+    );
+
+    let config = CONFIG.load(deps.as_ref().storage).unwrap();
+    assert_eq!(config.guardian, Some(Addr::unchecked("guardian0000")));
+    assert!(config.paused);
+    assert_eq!(config.pause_expiry_height, Some(height + 100));
+
+    // swaps and deposits are blocked
+    let swap_msg = ExecuteMsg::Swap {
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::from(100u128),
+        },
+        ask_asset: Asset {
+            info: AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+            amount: Uint128::zero(),
+        },
+        min_out_amount: Uint128::zero(),
+        to: None,
+        deadline: None,
+    };
+    let swap_info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(100u128),
+        }],
+    );
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        swap_info.clone(),
+        swap_msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Paused {});
+
+    let msg = ExecuteMsg::ProvideLiquidity {
+        assets: vec![
+            Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+                amount: Uint128::from(100u128),
+            },
+            Asset {
+                info: AssetInfo::Token {
+                    contract_addr: "asset0000".to_string(),
+                },
+                amount: Uint128::from(100u128),
+            },
+        ],
+        min_out_amount: Uint128::zero(),
+        receiver: None,
+        deadline: None,
+    };
+    let res = execute(deps.as_mut(), mock_env(), swap_info.clone(), msg).unwrap_err();
+    assert_eq!(res, ContractError::Paused {});
+
+    // withdrawals keep working
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0000".to_string(),
+        msg: to_binary(&Cw20HookMsg::WithdrawLiquidity {
+            min_assets: vec![],
+            receiver: None,
+            deadline: None,
+        })
+        .unwrap(),
+        amount: Uint128::from(10u128),
+    });
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("liquidity0000", &[]),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 3);
+
+    // the pause lifts by itself at the expiry height
+    let mut env = mock_env();
+    env.block.height = height + 100;
+    execute(deps.as_mut(), env, swap_info.clone(), swap_msg.clone()).unwrap();
+
+    // the guardian pauses without an expiry height, only an unpause lifts it
+    let msg = ExecuteMsg::Pause {
+        expiry_height: None,
+    };
+    let res = execute(deps.as_mut(), mock_env(), guardian.clone(), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![attr("action", "pause"), attr("expiry_height", "none")]
+    );
+
+    let mut env = mock_env();
+    env.block.height = height + 1_000_000;
+    let res = execute(deps.as_mut(), env, swap_info.clone(), swap_msg.clone()).unwrap_err();
+    assert_eq!(res, ContractError::Paused {});
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        ExecuteMsg::Unpause {},
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+
+    let res = execute(deps.as_mut(), mock_env(), guardian, ExecuteMsg::Unpause {}).unwrap();
+    assert_eq!(res.attributes, vec![attr("action", "unpause")]);
+    let config = CONFIG.load(deps.as_ref().storage).unwrap();
+    assert!(!config.paused);
+    assert_eq!(config.pause_expiry_height, None);
+
+    execute(deps.as_mut(), mock_env(), swap_info, swap_msg).unwrap();
+}
+
+#[test]
+fn test_deduct() {
+    let mut deps = mock_dependencies(&[]);
+
+    let tax_rate = Decimal::percent(2);
+    let tax_cap = Uint128::from(1_000_000u128);
+    deps.querier.with_tax(
+        Decimal::percent(2),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    let amount = Uint128::from(1_000_000_000u128);
+    let expected_after_amount = std::cmp::max(
+        amount.checked_sub(amount * tax_rate).unwrap(),
+        amount.checked_sub(tax_cap).unwrap(),
+    );
+
+    let after_amount = (Asset {
+        info: AssetInfo::NativeToken {
+            denom: "uusd".to_string(),
+        },
+        amount,
+    })
+    .deduct_tax(&deps.as_ref().querier)
+    .unwrap();
+
+    assert_eq!(expected_after_amount, after_amount.amount);
+}
+
+#[test]
+fn test_query_pool() {
+    let total_share_amount = Uint128::from(111u128);
+    let asset_0_amount = Uint128::from(222u128);
+    let asset_1_amount = Uint128::from(333u128);
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: asset_0_amount,
+    }]);
+
+    deps.querier.with_token_balances(&[
+        (
+            &"asset0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &asset_1_amount)],
+        ),
+        (
+            &"liquidity0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &total_share_amount)],
+        ),
+    ]);
+
+    let msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::zero(),
+        fee: Uint128::zero(),
+        token_code_id: 10u64,
+        native_decimals: vec![],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+
+    let env = mock_env();
+    let info = mock_info("addr0000", &[]);
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env, info, msg).unwrap();
+
+    // store liquidity token
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(
+                vec![
+                    10, 13, 108, 105, 113, 117, 105, 100, 105, 116, 121, 48, 48, 48, 48,
+                ]
+                .into(),
+            ),
+        }),
+    };
+
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    let res: PoolResponse = query_pool(deps.as_ref()).unwrap();
+
+    assert_eq!(
+        res.assets,
         [
             Asset {
                 info: AssetInfo::NativeToken {
@@ -1159,3 +1684,770 @@ fn test_query_pool() {
     );
     assert_eq!(res.total_share, total_share_amount);
 }
+
+#[test]
+fn migrate_legacy_pair() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(1100u128),
+    }]);
+
+    deps.querier.with_tax(
+        Decimal::zero(),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&"addr0000".to_string(), &Uint128::from(1000u128))],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::from(1000u128))],
+        ),
+    ]);
+
+    // a pair instantiated before the shared pair info, the pause and the price accumulators
+    // were added stored nothing but its two asset pair info
+    let legacy_pair_info = LegacyPairInfoRaw {
+        asset_infos: [
+            AssetInfoRaw::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfoRaw::Token {
+                contract_addr: deps.api.addr_canonicalize("asset0000").unwrap(),
+            },
+        ],
+        contract_addr: deps.api.addr_canonicalize(MOCK_CONTRACT_ADDR).unwrap(),
+        liquidity_token: deps.api.addr_canonicalize("liquidity0000").unwrap(),
+    };
+    singleton(&mut deps.storage, b"pair_info")
+        .save(&legacy_pair_info)
+        .unwrap();
+
+    let swap_msg = ExecuteMsg::Swap {
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::from(100u128),
+        },
+        ask_asset: Asset {
+            info: AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+            amount: Uint128::zero(),
+        },
+        min_out_amount: Uint128::zero(),
+        to: None,
+        deadline: None,
+    };
+    let swap_info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(100u128),
+        }],
+    );
+    execute(
+        deps.as_mut(),
+        mock_env(),
+        swap_info.clone(),
+        swap_msg.clone(),
+    )
+    .unwrap_err();
+
+    let migrate_msg = |owner: &str| MigrateMsg {
+        owner: Some(owner.to_string()),
+        native_decimals: vec![],
+    };
+    let res = migrate(
+        deps.as_mut(),
+        mock_env(),
+        MigrateMsg {
+            owner: None,
+            native_decimals: vec![],
+        },
+    )
+    .unwrap_err();
+    assert_eq!(
+        res,
+        ContractError::Std(StdError::generic_err(
+            "Owner is required to migrate this pair"
+        ))
+    );
+    migrate(deps.as_mut(), mock_env(), migrate_msg("owner0000")).unwrap();
+
+    let pair_info: PairInfo = query_pair_info(deps.as_ref()).unwrap();
+    assert_eq!(
+        pair_info,
+        PairInfo {
+            asset_infos: vec![
+                AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+                AssetInfo::Token {
+                    contract_addr: "asset0000".to_string(),
+                },
+            ],
+            contract_addr: MOCK_CONTRACT_ADDR.to_string(),
+            liquidity_token: "liquidity0000".to_string(),
+            amplification: Uint128::zero(),
+            fee: Uint128::from(30u128),
+            precision_multipliers: vec![],
+        }
+    );
+    let config = CONFIG.load(deps.as_ref().storage).unwrap();
+    assert_eq!(config, Config::new(Addr::unchecked("owner0000")));
+    let res = query_cumulative_prices(deps.as_ref(), mock_env()).unwrap();
+    assert_eq!(res.timestamp, mock_env().block.time.seconds());
+
+    // the migrated pair swaps and withdraws
+    let res = execute(deps.as_mut(), mock_env(), swap_info, swap_msg).unwrap();
+    assert_eq!(res.messages.len(), 1);
+
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0000".to_string(),
+        msg: to_binary(&Cw20HookMsg::WithdrawLiquidity {
+            min_assets: vec![],
+            receiver: None,
+            deadline: None,
+        })
+        .unwrap(),
+        amount: Uint128::from(100u128),
+    });
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("liquidity0000", &[]),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 3);
+
+    // migrating a pair that has the state already keeps it
+    migrate(deps.as_mut(), mock_env(), migrate_msg("owner0001")).unwrap();
+    let config = CONFIG.load(deps.as_ref().storage).unwrap();
+    assert_eq!(config.owner, Addr::unchecked("owner0000"));
+    assert_eq!(query_pair_info(deps.as_ref()).unwrap(), pair_info);
+}
+
+#[test]
+fn trade_to_price() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(4_000_000u128),
+    }]);
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&"addr0000".to_string(), &Uint128::from(2_000_000u128))],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(
+                &MOCK_CONTRACT_ADDR.to_string(),
+                &Uint128::from(1_000_000u128),
+            )],
+        ),
+    ]);
+
+    let msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::zero(),
+        fee: Uint128::zero(),
+        token_code_id: 10u64,
+        native_decimals: vec![],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+    let _res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    let uusd_info = AssetInfo::NativeToken {
+        denom: "uusd".to_string(),
+    };
+    let asset_info = AssetInfo::Token {
+        contract_addr: "asset0000".to_string(),
+    };
+    let one = Uint128::from(10u128.pow(18));
+
+    // the spot price of asset0000 is 4 uusd, selling it down to 1 takes the offer pool from
+    // sqrt(k / 4) to sqrt(k / 1), so doubles it
+    let res =
+        query_trade_to_price(deps.as_ref(), asset_info.clone(), uusd_info.clone(), one).unwrap();
+    assert_eq!(
+        res,
+        TradeToPriceResponse {
+            offer_amount: Uint128::from(1_000_000u128),
+            return_amount: Uint128::from(1_994_000u128),
+            commission_amount: Uint128::from(6_000u128),
+        }
+    );
+    let simulation_res = query_simulation(
+        deps.as_ref(),
+        Asset {
+            info: asset_info.clone(),
+            amount: res.offer_amount,
+        },
+        uusd_info.clone(),
+    )
+    .unwrap();
+    assert_eq!(res.return_amount, simulation_res.return_amount);
+    assert_eq!(res.commission_amount, simulation_res.commission_amount);
+
+    // after the swap the commission the pool keeps leaves the price a little above the target
+    deps.querier.with_balance(&[(
+        &MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(4_000_000u128 - 1_994_000u128),
+        }],
+    )]);
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&"addr0000".to_string(), &Uint128::from(2_000_000u128))],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(
+                &MOCK_CONTRACT_ADDR.to_string(),
+                &Uint128::from(2_000_000u128),
+            )],
+        ),
+    ]);
+    let res = query_spot_price(deps.as_ref(), asset_info.clone(), uusd_info.clone()).unwrap();
+    assert_eq!(res.price, Uint128::from(1_003_000_000_000_000_000u128));
+
+    // a target at the spot price takes no trade
+    let res = query_trade_to_price(
+        deps.as_ref(),
+        asset_info.clone(),
+        uusd_info.clone(),
+        res.price,
+    )
+    .unwrap();
+    assert_eq!(
+        res,
+        TradeToPriceResponse {
+            offer_amount: Uint128::zero(),
+            return_amount: Uint128::zero(),
+            commission_amount: Uint128::zero(),
+        }
+    );
+
+    // neither does a target above it, selling only lowers the price
+    let res = query_trade_to_price(
+        deps.as_ref(),
+        asset_info.clone(),
+        uusd_info.clone(),
+        Uint128::from(2 * 10u128.pow(18)),
+    )
+    .unwrap();
+    assert_eq!(res.offer_amount, Uint128::zero());
+    assert_eq!(res.return_amount, Uint128::zero());
+
+    // the other way round, taking uusd from 0.997 asset0000 down to 0.5 means selling uusd
+    let res = query_trade_to_price(
+        deps.as_ref(),
+        uusd_info.clone(),
+        asset_info.clone(),
+        Uint128::from(5 * 10u128.pow(17)),
+    )
+    .unwrap();
+    assert_eq!(res.offer_amount, Uint128::from(826666u128));
+
+    let res =
+        query_trade_to_price(deps.as_ref(), uusd_info, asset_info, Uint128::zero()).unwrap_err();
+    assert_eq!(res, ContractError::InvalidZeroAmount {});
+}
+
+#[test]
+fn spot_price_and_price_impact() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(4_000_000u128),
+    }]);
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&"addr0000".to_string(), &Uint128::from(2_000_000u128))],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(
+                &MOCK_CONTRACT_ADDR.to_string(),
+                &Uint128::from(1_000_000u128),
+            )],
+        ),
+    ]);
+
+    let msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::zero(),
+        fee: Uint128::zero(),
+        token_code_id: 10u64,
+        native_decimals: vec![],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+    let _res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    let uusd_info = AssetInfo::NativeToken {
+        denom: "uusd".to_string(),
+    };
+    let asset_info = AssetInfo::Token {
+        contract_addr: "asset0000".to_string(),
+    };
+
+    let res = query_spot_price(deps.as_ref(), asset_info.clone(), uusd_info.clone()).unwrap();
+    assert_eq!(
+        res,
+        SpotPriceResponse {
+            price: Uint128::from(4_000_000_000_000_000_000u128),
+            precision: 18,
+        }
+    );
+    let res = query_spot_price(deps.as_ref(), uusd_info.clone(), asset_info.clone()).unwrap();
+    assert_eq!(res.price, Uint128::from(250_000_000_000_000_000u128));
+
+    // a small swap barely moves the price, the commission is most of what it loses
+    let res = query_price_impact(
+        deps.as_ref(),
+        Asset {
+            info: asset_info.clone(),
+            amount: Uint128::from(1_000u128),
+        },
+        uusd_info.clone(),
+    )
+    .unwrap();
+    assert_eq!(
+        res,
+        PriceImpactResponse {
+            spot_price: Uint128::from(4_000_000_000_000_000_000u128),
+            effective_price: Uint128::from(3_985_000_000_000_000_000u128),
+            price_impact: Decimal::from_ratio(1u128, 1_000u128),
+            precision: 18,
+        }
+    );
+
+    // doubling the offer pool halves the value the swap gets at the spot price
+    let res = query_price_impact(
+        deps.as_ref(),
+        Asset {
+            info: asset_info.clone(),
+            amount: Uint128::from(1_000_000u128),
+        },
+        uusd_info.clone(),
+    )
+    .unwrap();
+    assert_eq!(
+        res,
+        PriceImpactResponse {
+            spot_price: Uint128::from(4_000_000_000_000_000_000u128),
+            effective_price: Uint128::from(1_994_000_000_000_000_000u128),
+            price_impact: Decimal::percent(50),
+            precision: 18,
+        }
+    );
+
+    let res = query_price_impact(
+        deps.as_ref(),
+        Asset {
+            info: asset_info.clone(),
+            amount: Uint128::zero(),
+        },
+        uusd_info.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::InvalidZeroAmount {});
+
+    // an empty pool has no price
+    deps.querier.with_token_balances(&[(
+        &"asset0000".to_string(),
+        &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::zero())],
+    )]);
+    let res = query_spot_price(deps.as_ref(), asset_info.clone(), uusd_info.clone()).unwrap_err();
+    assert_eq!(res, ContractError::InvalidZeroAmount {});
+    let res = query_price_impact(
+        deps.as_ref(),
+        Asset {
+            info: asset_info,
+            amount: Uint128::from(1_000u128),
+        },
+        uusd_info,
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::InvalidZeroAmount {});
+}
+
+#[test]
+fn deadline() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(1100u128),
+    }]);
+
+    deps.querier.with_tax(
+        Decimal::zero(),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&"addr0000".to_string(), &Uint128::from(1000u128))],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::from(1000u128))],
+        ),
+    ]);
+
+    let msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::zero(),
+        fee: Uint128::zero(),
+        token_code_id: 10u64,
+        native_decimals: vec![],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+    let _res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // store liquidity token
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(
+                vec![
+                    10, 13, 108, 105, 113, 117, 105, 100, 105, 116, 121, 48, 48, 48, 48,
+                ]
+                .into(),
+            ),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    // a deadline at the current height has passed, one later in time has not
+    let env = mock_env();
+    let expired = Expiration::AtHeight(env.block.height);
+    let future = Expiration::AtTime(env.block.time.plus_seconds(60));
+
+    let native_info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(100u128),
+        }],
+    );
+    let swap_msg = |deadline: Expiration| ExecuteMsg::Swap {
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::from(100u128),
+        },
+        ask_asset: Asset {
+            info: AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+            amount: Uint128::zero(),
+        },
+        min_out_amount: Uint128::zero(),
+        to: None,
+        deadline: Some(deadline),
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        native_info.clone(),
+        swap_msg(expired),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::DeadlineExpired {});
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        native_info.clone(),
+        swap_msg(future),
+    )
+    .unwrap();
+
+    let cw20_swap_msg = |deadline: Expiration| {
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: "addr0000".to_string(),
+            msg: to_binary(&Cw20HookMsg::Swap {
+                ask_asset: Asset {
+                    info: AssetInfo::NativeToken {
+                        denom: "uusd".to_string(),
+                    },
+                    amount: Uint128::zero(),
+                },
+                min_out_amount: Uint128::zero(),
+                to: None,
+                deadline: Some(deadline),
+            })
+            .unwrap(),
+            amount: Uint128::from(100u128),
+        })
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("asset0000", &[]),
+        cw20_swap_msg(expired),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::DeadlineExpired {});
+    execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("asset0000", &[]),
+        cw20_swap_msg(future),
+    )
+    .unwrap();
+
+    let provide_msg = |deadline: Expiration| ExecuteMsg::ProvideLiquidity {
+        assets: vec![
+            Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+                amount: Uint128::from(100u128),
+            },
+            Asset {
+                info: AssetInfo::Token {
+                    contract_addr: "asset0000".to_string(),
+                },
+                amount: Uint128::from(100u128),
+            },
+        ],
+        min_out_amount: Uint128::zero(),
+        receiver: None,
+        deadline: Some(deadline),
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        native_info.clone(),
+        provide_msg(expired),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::DeadlineExpired {});
+    execute(deps.as_mut(), env.clone(), native_info, provide_msg(future)).unwrap();
+
+    let withdraw_msg = |deadline: Expiration| {
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: "addr0000".to_string(),
+            msg: to_binary(&Cw20HookMsg::WithdrawLiquidity {
+                min_assets: vec![],
+                receiver: None,
+                deadline: Some(deadline),
+            })
+            .unwrap(),
+            amount: Uint128::from(10u128),
+        })
+    };
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        mock_info("liquidity0000", &[]),
+        withdraw_msg(expired),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::DeadlineExpired {});
+    let res = execute(
+        deps.as_mut(),
+        env,
+        mock_info("liquidity0000", &[]),
+        withdraw_msg(future),
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 3);
+}
+
+#[test]
+fn observe() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(2_000u128),
+    }]);
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&"addr0000".to_string(), &Uint128::from(1_000u128))],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::from(1_000u128))],
+        ),
+    ]);
+
+    let msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::zero(),
+        fee: Uint128::zero(),
+        token_code_id: 10u64,
+        native_decimals: vec![],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+    let _res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // store liquidity token
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(
+                vec![
+                    10, 13, 108, 105, 113, 117, 105, 100, 105, 116, 121, 48, 48, 48, 48,
+                ]
+                .into(),
+            ),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    let env_at = |seconds: u64| {
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(seconds);
+        env
+    };
+    let withdraw_msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0000".to_string(),
+        msg: to_binary(&Cw20HookMsg::WithdrawLiquidity {
+            min_assets: vec![],
+            receiver: None,
+            deadline: None,
+        })
+        .unwrap(),
+        amount: Uint128::from(10u128),
+    });
+    let lp_info = mock_info("liquidity0000", &[]);
+    let e = |mantissa: u128, exp: u32| Uint128::from(mantissa * 10u128.pow(exp));
+
+    // uusd is worth 0.5 asset0000 for the first 100 seconds
+    execute(
+        deps.as_mut(),
+        env_at(100),
+        lp_info.clone(),
+        withdraw_msg.clone(),
+    )
+    .unwrap();
+
+    // then 0.25 for the next 100, a second action in the same block records nothing
+    deps.querier.with_balance(&[(
+        &MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(4_000u128),
+        }],
+    )]);
+    execute(
+        deps.as_mut(),
+        env_at(200),
+        lp_info.clone(),
+        withdraw_msg.clone(),
+    )
+    .unwrap();
+    execute(deps.as_mut(), env_at(200), lp_info, withdraw_msg).unwrap();
+    let index = OBSERVATION_INDEX.load(deps.as_ref().storage).unwrap();
+    assert_eq!(index.length, 3);
+
+    let res = query_cumulative_prices(deps.as_ref(), env_at(300)).unwrap();
+    assert_eq!(res.timestamp, env_at(300).block.time.seconds());
+    assert_eq!(res.precision, 18);
+    assert_eq!(
+        res.price_cumulatives[0].offer_asset_info,
+        AssetInfo::NativeToken {
+            denom: "uusd".to_string(),
+        }
+    );
+    assert_eq!(res.price_cumulatives[0].price_cumulative, e(1, 20));
+    assert_eq!(res.price_cumulatives[1].price_cumulative, e(1, 21));
+
+    // past the latest observation the current prices are extrapolated, between observations
+    // the accumulators are interpolated
+    let res = query_observe(
+        deps.as_ref(),
+        env_at(300),
+        vec![0, 50, 100, 150, 200, 250, 300],
+    )
+    .unwrap();
+    let cumulatives: Vec<(u64, Uint128, Uint128)> = res
+        .observations
+        .iter()
+        .map(|observation| {
+            (
+                observation.timestamp - mock_env().block.time.seconds(),
+                observation.price_cumulatives[0].price_cumulative,
+                observation.price_cumulatives[1].price_cumulative,
+            )
+        })
+        .collect();
+    assert_eq!(
+        cumulatives,
+        vec![
+            (300, e(1, 20), e(1, 21)),
+            (250, e(875, 17), e(8, 20)),
+            (200, e(75, 18), e(6, 20)),
+            (150, e(625, 17), e(4, 20)),
+            (100, e(5, 19), e(2, 20)),
+            (50, e(25, 18), e(1, 20)),
+            (0, Uint128::zero(), Uint128::zero()),
+        ]
+    );
+
+    // nothing is kept from before the pair was created
+    let res = query_observe(deps.as_ref(), env_at(300), vec![0, 301]).unwrap_err();
+    assert_eq!(
+        res,
+        ContractError::ObservationUnavailable { seconds_ago: 301 }
+    );
+}
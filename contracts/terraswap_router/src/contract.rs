@@ -6,11 +6,12 @@ use cosmwasm_std::{
     QueryRequest, Response, StdError, StdResult, Uint128, WasmMsg, WasmQuery,
 };
 
+use crate::error::ContractError;
 use crate::operations::execute_swap_operation;
 use crate::querier::compute_tax;
 use crate::state::{Config, CONFIG};
 
-use cw20::Cw20ReceiveMsg;
+use cw20::{Cw20ReceiveMsg, Expiration};
 use std::collections::HashMap;
 use terra_cosmwasm::{SwapResponse, TerraMsgWrapper, TerraQuerier};
 use terraswap::asset::{Asset, AssetInfo, PairInfo};
@@ -44,13 +45,14 @@ pub fn execute(
     env: Env,
     info: MessageInfo,
     msg: ExecuteMsg,
-) -> StdResult<Response<TerraMsgWrapper>> {
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
     match msg {
         ExecuteMsg::Receive(msg) => receive_cw20(deps, env, info, msg),
         ExecuteMsg::ExecuteSwapOperations {
             operations,
             minimum_receive,
             to,
+            deadline,
         } => {
             let api = deps.api;
             execute_swap_operations(
@@ -60,30 +62,31 @@ pub fn execute(
                 operations,
                 minimum_receive,
                 optional_addr_validate(api, to)?,
+                deadline,
             )
         }
         ExecuteMsg::ExecuteSwapOperation { operation, to } => {
             let api = deps.api;
-            execute_swap_operation(
+            Ok(execute_swap_operation(
                 deps,
                 env,
                 info,
                 operation,
                 optional_addr_validate(api, to)?.map(|v| v.to_string()),
-            )
+            )?)
         }
         ExecuteMsg::AssertMinimumReceive {
             asset_info,
             prev_balance,
             minimum_receive,
             receiver,
-        } => assert_minium_receive(
+        } => Ok(assert_minium_receive(
             deps.as_ref(),
             asset_info,
             prev_balance,
             minimum_receive,
             deps.api.addr_validate(&receiver)?,
-        ),
+        )?),
     }
 }
 
@@ -102,13 +105,14 @@ pub fn receive_cw20(
     env: Env,
     _info: MessageInfo,
     cw20_msg: Cw20ReceiveMsg,
-) -> StdResult<Response<TerraMsgWrapper>> {
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
     let sender = deps.api.addr_validate(&cw20_msg.sender)?;
     match from_binary(&cw20_msg.msg)? {
         Cw20HookMsg::ExecuteSwapOperations {
             operations,
             minimum_receive,
             to,
+            deadline,
         } => {
             let api = deps.api;
             execute_swap_operations(
@@ -118,6 +122,7 @@ pub fn receive_cw20(
                 operations,
                 minimum_receive,
                 optional_addr_validate(api, to)?,
+                deadline,
             )
         }
     }
@@ -130,10 +135,17 @@ pub fn execute_swap_operations(
     operations: Vec<SwapOperation>,
     minimum_receive: Option<Uint128>,
     to: Option<Addr>,
-) -> StdResult<Response<TerraMsgWrapper>> {
+    deadline: Option<Expiration>,
+) -> Result<Response<TerraMsgWrapper>, ContractError> {
+    if let Some(deadline) = deadline {
+        if deadline.is_expired(&env.block) {
+            return Err(ContractError::DeadlineExpired {});
+        }
+    }
+
     let operations_len = operations.len();
     if operations_len == 0 {
-        return Err(StdError::generic_err("must provide operations"));
+        return Err(StdError::generic_err("must provide operations").into());
     }
 
     // Assert the operations are properly set
@@ -295,6 +307,7 @@ fn simulate_swap_operations(
                                 info: offer_asset_info,
                                 amount: offer_amount,
                             },
+                            ask_asset_info: ask_asset_info.clone(),
                         })?,
                     }))?;
 
@@ -357,7 +370,7 @@ fn test_invalid_operations() {
     assert!(assert_operations(&[]).is_err());
 
     // uluna output
-    assert!(assert_operations(&vec![
+    assert!(assert_operations(&[
         SwapOperation::NativeSwap {
             offer_denom: "uusd".to_string(),
             ask_denom: "uluna".to_string(),
@@ -382,7 +395,7 @@ fn test_invalid_operations() {
     .is_ok());
 
     // asset0002 output
-    assert!(assert_operations(&vec![
+    assert!(assert_operations(&[
         SwapOperation::NativeSwap {
             offer_denom: "uusd".to_string(),
             ask_denom: "uluna".to_string(),
@@ -415,7 +428,7 @@ fn test_invalid_operations() {
     .is_ok());
 
     // multiple output token types error
-    assert!(assert_operations(&vec![
+    assert!(assert_operations(&[
         SwapOperation::NativeSwap {
             offer_denom: "uusd".to_string(),
             ask_denom: "ukrw".to_string(),
@@ -1,6 +1,6 @@
 use cosmwasm_std::{
-    to_binary, Addr, Coin, CosmosMsg, Decimal, Deps, DepsMut, Env, MessageInfo, Response, StdError,
-    StdResult, WasmMsg,
+    to_binary, Addr, Coin, CosmosMsg, Deps, DepsMut, Env, MessageInfo, Response, StdError,
+    StdResult, Uint128, WasmMsg,
 };
 
 use crate::querier::compute_tax;
@@ -9,7 +9,7 @@ use crate::state::{Config, CONFIG};
 use cw20::Cw20ExecuteMsg;
 use terra_cosmwasm::{create_swap_msg, create_swap_send_msg, TerraMsgWrapper};
 use terraswap::asset::{Asset, AssetInfo, PairInfo};
-use terraswap::pair::ExecuteMsg as PairExecuteMsg;
+use terraswap::pair::{Cw20HookMsg as PairCw20HookMsg, ExecuteMsg as PairExecuteMsg};
 use terraswap::querier::{query_balance, query_pair_info, query_token_balance};
 use terraswap::router::SwapOperation;
 
@@ -65,7 +65,7 @@ pub fn execute_swap_operation(
             let pair_info: PairInfo = query_pair_info(
                 &deps.querier,
                 terraswap_factory,
-                &[offer_asset_info.clone(), ask_asset_info],
+                &[offer_asset_info.clone(), ask_asset_info.clone()],
             )?;
 
             let amount = match offer_asset_info.clone() {
@@ -87,7 +87,7 @@ pub fn execute_swap_operation(
                 deps.as_ref(),
                 Addr::unchecked(pair_info.contract_addr),
                 offer_asset,
-                None,
+                ask_asset_info,
                 to,
             )?]
         }
@@ -100,9 +100,15 @@ pub fn asset_into_swap_msg(
     deps: Deps,
     pair_contract: Addr,
     offer_asset: Asset,
-    max_spread: Option<Decimal>,
+    ask_asset_info: AssetInfo,
     to: Option<String>,
 ) -> StdResult<CosmosMsg<TerraMsgWrapper>> {
+    // the minimum receive amount is asserted once the whole route is done
+    let ask_asset = Asset {
+        info: ask_asset_info,
+        amount: Uint128::zero(),
+    };
+
     match offer_asset.info.clone() {
         AssetInfo::NativeToken { denom } => {
             // deduct tax first
@@ -120,9 +126,10 @@ pub fn asset_into_swap_msg(
                         amount,
                         ..offer_asset
                     },
-                    belief_price: None,
-                    max_spread,
+                    ask_asset,
+                    min_out_amount: Uint128::zero(),
                     to,
+                    deadline: None,
                 })?,
             }))
         }
@@ -132,11 +139,11 @@ pub fn asset_into_swap_msg(
             msg: to_binary(&Cw20ExecuteMsg::Send {
                 contract: pair_contract.to_string(),
                 amount: offer_asset.amount,
-                msg: to_binary(&PairExecuteMsg::Swap {
-                    offer_asset,
-                    belief_price: None,
-                    max_spread,
+                msg: to_binary(&PairCw20HookMsg::Swap {
+                    ask_asset,
+                    min_out_amount: Uint128::zero(),
                     to,
+                    deadline: None,
                 })?,
             })?,
         })),
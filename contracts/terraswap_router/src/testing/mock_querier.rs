@@ -133,12 +133,6 @@ impl Querier for WasmMockQuerier {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
-#[serde(rename_all = "snake_case")]
-pub enum MockQueryMsg {
-    Price {},
-}
-
 impl WasmMockQuerier {
     pub fn handle_query(&self, request: &QueryRequest<TerraQueryWrapper>) -> QuerierResult {
         match &request {
@@ -187,7 +181,7 @@ impl WasmMockQuerier {
                         Some(v) => SystemResult::Ok(ContractResult::from(to_binary(&PairInfo {
                             contract_addr: v.clone(),
                             liquidity_token: "liquidity".to_string(),
-                            asset_infos: [
+                            asset_infos: vec![
                                 AssetInfo::NativeToken {
                                     denom: "uusd".to_string(),
                                 },
@@ -195,6 +189,9 @@ impl WasmMockQuerier {
                                     denom: "uusd".to_string(),
                                 },
                             ],
+                            amplification: Uint128::zero(),
+                            fee: Uint128::zero(),
+                            precision_multipliers: vec![],
                         }))),
                         None => SystemResult::Err(SystemError::InvalidRequest {
                             error: "No pair info exists".to_string(),
@@ -207,6 +204,8 @@ impl WasmMockQuerier {
                         return_amount: offer_asset.amount,
                         commission_amount: Uint128::zero(),
                         spread_amount: Uint128::zero(),
+                        fee: Uint128::zero(),
+                        rates: vec![],
                     })))
                 }
                 _ => match from_binary(msg).unwrap() {
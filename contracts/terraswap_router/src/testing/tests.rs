@@ -4,12 +4,13 @@ use cosmwasm_std::{
 };
 
 use crate::contract::{execute, instantiate, query};
+use crate::error::ContractError;
 use crate::testing::mock_querier::mock_dependencies;
 
-use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg};
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg, Expiration};
 use terra_cosmwasm::{create_swap_msg, create_swap_send_msg};
 use terraswap::asset::{Asset, AssetInfo};
-use terraswap::pair::ExecuteMsg as PairExecuteMsg;
+use terraswap::pair::Cw20HookMsg as PairCw20HookMsg;
 use terraswap::router::{
     ConfigResponse, Cw20HookMsg, ExecuteMsg, InstantiateMsg, QueryMsg,
     SimulateSwapOperationsResponse, SwapOperation,
@@ -55,15 +56,33 @@ fn execute_swap_operations() {
         operations: vec![],
         minimum_receive: None,
         to: None,
+        deadline: None,
     };
 
     let info = mock_info("addr0000", &[]);
     let res = execute(deps.as_mut(), mock_env(), info, msg);
     match res {
-        Err(StdError::GenericErr { msg, .. }) => assert_eq!(msg, "must provide operations"),
+        Err(ContractError::Std(StdError::GenericErr { msg, .. })) => {
+            assert_eq!(msg, "must provide operations")
+        }
         _ => panic!("DO NOT ENTER HERE"),
     }
 
+    let env = mock_env();
+    let msg = ExecuteMsg::ExecuteSwapOperations {
+        operations: vec![SwapOperation::NativeSwap {
+            offer_denom: "uusd".to_string(),
+            ask_denom: "ukrw".to_string(),
+        }],
+        minimum_receive: None,
+        to: None,
+        deadline: Some(Expiration::AtHeight(env.block.height)),
+    };
+
+    let info = mock_info("addr0000", &[]);
+    let res = execute(deps.as_mut(), env, info, msg);
+    assert_eq!(res, Err(ContractError::DeadlineExpired {}));
+
     let msg = ExecuteMsg::ExecuteSwapOperations {
         operations: vec![
             SwapOperation::NativeSwap {
@@ -97,6 +116,7 @@ fn execute_swap_operations() {
         ],
         minimum_receive: Some(Uint128::from(1000000u128)),
         to: None,
+        deadline: None,
     };
 
     let info = mock_info("addr0000", &[]);
@@ -216,6 +236,7 @@ fn execute_swap_operations() {
             ],
             minimum_receive: None,
             to: Some("addr0002".to_string()),
+            deadline: None,
         })
         .unwrap(),
     });
@@ -325,7 +346,9 @@ fn execute_swap_operation() {
     let info = mock_info("addr0000", &[]);
     let res = execute(deps.as_mut(), mock_env(), info, msg.clone());
     match res {
-        Err(StdError::GenericErr { msg, .. }) => assert_eq!(msg, "unauthorized"),
+        Err(ContractError::Std(StdError::GenericErr { msg, .. })) => {
+            assert_eq!(msg, "unauthorized")
+        }
         _ => panic!("DO NOT ENTER HERE"),
     }
 
@@ -393,16 +416,16 @@ fn execute_swap_operation() {
             msg: to_binary(&Cw20ExecuteMsg::Send {
                 contract: "pair".to_string(),
                 amount: Uint128::from(1000000u128),
-                msg: to_binary(&PairExecuteMsg::Swap {
-                    offer_asset: Asset {
-                        info: AssetInfo::Token {
-                            contract_addr: "asset".to_string(),
+                msg: to_binary(&PairCw20HookMsg::Swap {
+                    ask_asset: Asset {
+                        info: AssetInfo::NativeToken {
+                            denom: "uusd".to_string(),
                         },
-                        amount: Uint128::from(1000000u128),
+                        amount: Uint128::zero(),
                     },
-                    belief_price: None,
-                    max_spread: None,
+                    min_out_amount: Uint128::zero(),
                     to: Some("addr0000".to_string()),
+                    deadline: None,
                 })
                 .unwrap()
             })
@@ -531,7 +554,7 @@ fn assert_minimum_receive_native_token() {
     };
     let res = execute(deps.as_mut(), mock_env(), info, msg);
     match res {
-        Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+        Err(ContractError::Std(StdError::GenericErr { msg, .. })) => assert_eq!(
             msg,
             "assertion failed; minimum receive amount: 1000001, swap amount: 1000000"
         ),
@@ -570,7 +593,7 @@ fn assert_minimum_receive_token() {
     };
     let res = execute(deps.as_mut(), mock_env(), info, msg);
     match res {
-        Err(StdError::GenericErr { msg, .. }) => assert_eq!(
+        Err(ContractError::Std(StdError::GenericErr { msg, .. })) => assert_eq!(
             msg,
             "assertion failed; minimum receive amount: 1000001, swap amount: 1000000"
         ),
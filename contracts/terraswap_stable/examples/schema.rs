@@ -5,8 +5,11 @@ use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 
 use terraswap::asset::PairInfo;
 use terraswap::pair::{
-    Cw20HookMsg, ExecuteMsg, InstantiateMsg, MigrateMsg, PoolResponse, QueryMsg,
-    ReverseSimulationResponse, SimulationResponse,
+    AdminFeesResponse, AmpResponse, ConfigResponse, CumulativePricesResponse, Cw20HookMsg,
+    ExecuteMsg, InstantiateMsg, MigrateMsg, ObserveResponse, PoolResponse, PriceImpactResponse,
+    QueryMsg, ReverseSimulationResponse, SimulateProvideLiquidityResponse,
+    SimulateWithdrawLiquidityResponse, SimulateWithdrawSingleLiquidityResponse, SimulationResponse,
+    SpotPriceResponse, TradeToPriceResponse, VirtualPriceResponse,
 };
 
 fn main() {
@@ -24,4 +27,19 @@ fn main() {
     export_schema(&schema_for!(PoolResponse), &out_dir);
     export_schema(&schema_for!(ReverseSimulationResponse), &out_dir);
     export_schema(&schema_for!(SimulationResponse), &out_dir);
+    export_schema(&schema_for!(SimulateProvideLiquidityResponse), &out_dir);
+    export_schema(&schema_for!(SimulateWithdrawLiquidityResponse), &out_dir);
+    export_schema(
+        &schema_for!(SimulateWithdrawSingleLiquidityResponse),
+        &out_dir,
+    );
+    export_schema(&schema_for!(AmpResponse), &out_dir);
+    export_schema(&schema_for!(ConfigResponse), &out_dir);
+    export_schema(&schema_for!(AdminFeesResponse), &out_dir);
+    export_schema(&schema_for!(VirtualPriceResponse), &out_dir);
+    export_schema(&schema_for!(CumulativePricesResponse), &out_dir);
+    export_schema(&schema_for!(ObserveResponse), &out_dir);
+    export_schema(&schema_for!(SpotPriceResponse), &out_dir);
+    export_schema(&schema_for!(PriceImpactResponse), &out_dir);
+    export_schema(&schema_for!(TradeToPriceResponse), &out_dir);
 }
@@ -1,31 +1,62 @@
 use crate::error::ContractError;
 use crate::response::MsgInstantiateContractResponse;
-use crate::state::PAIR_INFO;
+use crate::state::{
+    load_latest_observation, load_observation_at, store_observation, AmpRamp, BasePool, Config,
+    FlashLoan, Observation, PendingExchange, ADMIN_FEES, AMP_RAMP, BASE_POOL, CONFIG, FLASH_LOAN,
+    OBSERVATION_INDEX, PAIR_INFO, PENDING_EXCHANGE, RATE_PROVIDERS, RESERVES,
+};
 
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 
 use cosmwasm_std::{
-    from_binary, to_binary, Addr, Binary, CanonicalAddr, Coin, CosmosMsg, Decimal, Deps, DepsMut,
-    Env, MessageInfo, Reply, ReplyOn, Response, StdError, StdResult, SubMsg, Uint128, WasmMsg,
+    from_binary, to_binary, Addr, BankMsg, Binary, CanonicalAddr, Coin, CosmosMsg, Decimal, Deps,
+    DepsMut, Env, MessageInfo, Reply, ReplyOn, Response, StdError, StdResult, Storage, SubMsg,
+    Uint128, WasmMsg,
 };
 
-use crate::curve::Curve;
+use crate::curve::{ceil_fee, Curve, FEE_DENOMINATOR, RATE_PRECISION};
 
-use cosmwasm_bignumber::{Decimal256, Uint256};
-use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg, MinterResponse};
-use integer_sqrt::IntegerSquareRoot;
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg, Expiration, MinterResponse};
 use protobuf::Message;
-use std::str::FromStr;
 use terraswap::asset::{Asset, AssetInfo, PairInfo, PairInfoRaw};
 use terraswap::pair::{
-    Cw20HookMsg, ExecuteMsg, InstantiateMsg, MigrateMsg, PoolResponse, QueryMsg,
-    ReverseSimulationResponse, SimulationResponse,
+    AdminFeesResponse, AmpResponse, ConfigResponse, CumulativePricesResponse, Cw20HookMsg,
+    ExecuteMsg, InstantiateMsg, MigrateMsg, NativeDecimals, ObserveResponse, PoolResponse,
+    PriceCumulative, PriceImpactResponse, QueryMsg, RateProvider, ReverseSimulationResponse,
+    SimulateProvideLiquidityResponse, SimulateWithdrawLiquidityResponse,
+    SimulateWithdrawSingleLiquidityResponse, SimulationResponse, SpotPriceResponse,
+    TradeToPriceResponse, VirtualPriceResponse, MINIMUM_INITIAL_SHARE, MINIMUM_LIQUIDITY_AMOUNT,
+};
+use terraswap::querier::{
+    query_pair_info_from_pair, query_rate, query_supply, query_token_balance, query_token_decimals,
+    query_token_symbol, query_virtual_price as query_base_virtual_price,
 };
-use terraswap::querier::query_supply;
 use terraswap::token::InstantiateMsg as TokenInstantiateMsg;
 
 const INSTANTIATE_REPLY_ID: u64 = 1;
+const FLASH_LOAN_REPLY_ID: u64 = 2;
+const EXCHANGE_UNDERLYING_REPLY_ID: u64 = 3;
+
+/// Amp ramps must last at least a day and can not restart within a day of the last one
+const MIN_RAMP_TIME: u64 = 86_400;
+const MAX_AMP: u128 = 1_000_000;
+/// A ramp can move the amp by at most this factor in either direction
+const MAX_AMP_CHANGE: u128 = 10;
+/// Fee numerator cap, 50% of the 10000 fee denominator
+const MAX_FEE: u128 = 5_000;
+/// Dynamic fee cap, ten times the fee over the 10000 fee denominator
+const MAX_FEE_MULTIPLIER: u128 = 100_000;
+/// Decimals of the virtual price, one LP token of a balanced new pool is worth 10^18
+const VIRTUAL_PRICE_PRECISION: u8 = 18;
+/// Decimals of the prices the pair quotes and sums up in the price accumulators
+const PRICE_PRECISION: u8 = 18;
+const LP_TOKEN_SUFFIX: &str = "-LP";
+/// Symbol segment of an asset whose symbol or denom has no letters
+const LP_TOKEN_FALLBACK_LABEL: &str = "TOKEN";
+/// Longest name and symbol `terraswap::token::InstantiateMsg::validate` accepts
+const MAX_LP_TOKEN_NAME_LEN: usize = 50;
+const MAX_LP_TOKEN_SYMBOL_LEN: usize = 12;
 
 /// Commission rate == 0.3%
 // const AMPLIFICATION: u64 = 60;
@@ -34,38 +65,160 @@ const INSTANTIATE_REPLY_ID: u64 = 1;
 pub fn instantiate(
     deps: DepsMut,
     env: Env,
-    _info: MessageInfo,
+    info: MessageInfo,
     msg: InstantiateMsg,
-) -> StdResult<Response> {
+) -> Result<Response, ContractError> {
+    assert_swap_fee(msg.fee, Uint128::zero())?;
+    if msg.amplification.is_zero() || msg.amplification.u128() > MAX_AMP {
+        return Err(ContractError::InvalidAmp {});
+    }
+    if msg.asset_infos.len() < 2 {
+        return Err(StdError::generic_err("A pair takes at least two assets").into());
+    }
+    for (i, asset_info) in msg.asset_infos.iter().enumerate() {
+        if msg.asset_infos[..i]
+            .iter()
+            .any(|other| other.equal(asset_info))
+        {
+            return Err(ContractError::DuplicateAsset {
+                asset: asset_info.to_string(),
+            });
+        }
+    }
+
     let mut asset_infos = vec![];
     for asset in msg.asset_infos.iter() {
         asset_infos.push(asset.to_raw(deps.api)?);
     }
+    let decimals = query_decimals(deps.as_ref(), &msg.asset_infos, &msg.native_decimals)?;
+    let precision_multipliers = compute_precision_multipliers(&decimals)?;
     let pair_info: &PairInfoRaw = &PairInfoRaw {
         contract_addr: deps.api.addr_canonicalize(env.contract.address.as_str())?,
         liquidity_token: CanonicalAddr::from(vec![]),
-        asset_infos: asset_infos,
+        asset_infos,
         amplification: msg.amplification,
         fee: msg.fee,
+        precision_multipliers,
     };
 
     PAIR_INFO.save(deps.storage, pair_info)?;
+    if let Some(base_pool) = msg.base_pool {
+        let contract_addr = deps.api.addr_validate(&base_pool)?;
+        let base_pair_info = query_pair_info_from_pair(&deps.querier, contract_addr.clone())?;
+        let liquidity_token = deps.api.addr_validate(&base_pair_info.liquidity_token)?;
+        let lp_token_info = AssetInfo::Token {
+            contract_addr: liquidity_token.to_string(),
+        };
+        if !msg
+            .asset_infos
+            .iter()
+            .any(|asset_info| asset_info.equal(&lp_token_info))
+        {
+            return Err(
+                StdError::generic_err("Base pool LP token is not one of the pair assets").into(),
+            );
+        }
+
+        BASE_POOL.save(
+            deps.storage,
+            &BasePool {
+                contract_addr,
+                liquidity_token,
+                asset_infos: base_pair_info.asset_infos,
+            },
+        )?;
+    }
+
+    // the LP leg of a metapool is valued at the base pool virtual price instead
+    let base_lp_token_info = BASE_POOL
+        .may_load(deps.storage)?
+        .map(|base_pool| AssetInfo::Token {
+            contract_addr: base_pool.liquidity_token.to_string(),
+        });
+    let mut rate_providers: Vec<Option<Addr>> = vec![None; msg.asset_infos.len()];
+    for rate_provider in msg.rate_providers.iter() {
+        if let Some(base_lp_token_info) = &base_lp_token_info {
+            if rate_provider.asset_info.equal(base_lp_token_info) {
+                return Err(StdError::generic_err(
+                    "Base pool LP token can not have a rate provider",
+                )
+                .into());
+            }
+        }
+        let index = msg
+            .asset_infos
+            .iter()
+            .position(|asset_info| asset_info.equal(&rate_provider.asset_info))
+            .ok_or_else(|| {
+                StdError::generic_err(format!(
+                    "Rate provider asset {} is not one of the pair assets",
+                    rate_provider.asset_info
+                ))
+            })?;
+        if rate_providers[index].is_some() {
+            return Err(StdError::generic_err(format!(
+                "Duplicate rate provider for {}",
+                rate_provider.asset_info
+            ))
+            .into());
+        }
+        rate_providers[index] = Some(deps.api.addr_validate(&rate_provider.contract_addr)?);
+    }
+    RATE_PROVIDERS.save(deps.storage, &rate_providers)?;
+
+    ADMIN_FEES.save(
+        deps.storage,
+        &vec![Uint128::zero(); pair_info.asset_infos.len()],
+    )?;
+    RESERVES.save(
+        deps.storage,
+        &vec![Uint128::zero(); pair_info.asset_infos.len()],
+    )?;
+    let n_coins = pair_info.asset_infos.len();
+    store_observation(
+        deps.storage,
+        &Observation {
+            timestamp: env.block.time.seconds(),
+            price_cumulatives: vec![Uint128::zero(); n_coins * (n_coins - 1)],
+        },
+    )?;
+    let owner = match msg.owner {
+        Some(owner) => deps.api.addr_validate(&owner)?,
+        None => info.sender,
+    };
+    CONFIG.save(deps.storage, &Config::new(owner))?;
+    AMP_RAMP.save(
+        deps.storage,
+        &AmpRamp {
+            initial_amp: msg.amplification,
+            initial_time: 0,
+            future_amp: msg.amplification,
+            future_time: 0,
+        },
+    )?;
+
+    let (lp_token_name, lp_token_symbol) = query_lp_token_name(deps.as_ref(), &msg.asset_infos)?;
+    let token_msg = TokenInstantiateMsg {
+        name: msg.lp_token_name.unwrap_or(lp_token_name),
+        symbol: msg.lp_token_symbol.unwrap_or(lp_token_symbol),
+        // the LP token shares the precision the assets are normalized to
+        decimals: msg
+            .lp_token_decimals
+            .unwrap_or_else(|| decimals.iter().max().cloned().unwrap_or_default()),
+        initial_balances: vec![],
+        mint: Some(MinterResponse {
+            minter: env.contract.address.to_string(),
+            cap: None,
+        }),
+    };
+    token_msg.validate()?;
 
     Ok(Response::new().add_submessage(SubMsg {
         // Create LP token
         msg: WasmMsg::Instantiate {
             admin: None,
             code_id: msg.token_code_id,
-            msg: to_binary(&TokenInstantiateMsg {
-                name: "terraswap liquidity token".to_string(),
-                symbol: "uLP".to_string(),
-                decimals: 6,
-                initial_balances: vec![],
-                mint: Some(MinterResponse {
-                    minter: env.contract.address.to_string(),
-                    cap: None,
-                }),
-            })?,
+            msg: to_binary(&token_msg)?,
             funds: vec![],
             label: "".to_string(),
         }
@@ -94,13 +247,19 @@ pub fn execute(
             assets,
             min_out_amount,
             receiver,
-        } => provide_liquidity(deps, env, info, assets, min_out_amount, receiver),
+            deadline,
+        } => {
+            assert_deadline(&env, deadline)?;
+            provide_liquidity(deps, env, info, assets, min_out_amount, receiver)
+        }
         ExecuteMsg::Swap {
             offer_asset,
             ask_asset,
             min_out_amount,
             to,
+            deadline,
         } => {
+            assert_deadline(&env, deadline)?;
             if !offer_asset.is_native_token() {
                 return Err(ContractError::Unauthorized {});
             }
@@ -122,6 +281,89 @@ pub fn execute(
                 to_addr,
             )
         }
+        ExecuteMsg::SwapExactOut {
+            ask_asset,
+            max_offer_amount,
+            to,
+            deadline,
+        } => {
+            assert_deadline(&env, deadline)?;
+            let offer_asset = match info.funds.as_slice() {
+                [coin] => Asset {
+                    info: AssetInfo::NativeToken {
+                        denom: coin.denom.clone(),
+                    },
+                    amount: max_offer_amount,
+                },
+                _ => return Err(ContractError::AssetMismatch {}),
+            };
+            offer_asset.assert_sent_native_token_balance(&info)?;
+
+            let to_addr = if let Some(to_addr) = to {
+                Some(deps.api.addr_validate(&to_addr)?)
+            } else {
+                None
+            };
+
+            swap_exact_out(deps, env, info.sender, offer_asset, ask_asset, to_addr)
+        }
+        ExecuteMsg::ExchangeUnderlying {
+            offer_asset,
+            ask_asset,
+            min_out_amount,
+            to,
+            deadline,
+        } => {
+            assert_deadline(&env, deadline)?;
+            if !offer_asset.is_native_token() {
+                return Err(ContractError::Unauthorized {});
+            }
+
+            let to_addr = if let Some(to_addr) = to {
+                Some(deps.api.addr_validate(&to_addr)?)
+            } else {
+                None
+            };
+
+            exchange_underlying(
+                deps,
+                env,
+                info.clone(),
+                info.sender,
+                offer_asset,
+                ask_asset,
+                min_out_amount,
+                to_addr,
+            )
+        }
+        ExecuteMsg::RampAmp {
+            future_amp,
+            future_time,
+        } => ramp_amp(deps, env, info, future_amp, future_time),
+        ExecuteMsg::StopRampAmp {} => stop_ramp_amp(deps, env, info),
+        ExecuteMsg::UpdateFee { fee } => update_fee(deps, info, fee),
+        ExecuteMsg::Pause { expiry_height } => pause(deps, env, info, expiry_height),
+        ExecuteMsg::Unpause {} => unpause(deps, info),
+        ExecuteMsg::UpdateGuardian { guardian } => update_guardian(deps, info, guardian),
+        ExecuteMsg::ProposeNewOwner { owner } => propose_new_owner(deps, info, owner),
+        ExecuteMsg::AcceptOwnership {} => accept_ownership(deps, info),
+        ExecuteMsg::UpdateAdminFee { admin_fee } => update_admin_fee(deps, info, admin_fee),
+        ExecuteMsg::UpdateFeeCollector { fee_collector } => {
+            update_fee_collector(deps, info, fee_collector)
+        }
+        ExecuteMsg::WithdrawAdminFees {} => withdraw_admin_fees(deps),
+        ExecuteMsg::Skim { to } => skim(deps, env, to),
+        ExecuteMsg::Sync {} => sync(deps, env),
+        ExecuteMsg::FlashLoan {
+            assets,
+            callback_msg,
+        } => flash_loan(deps, env, info, assets, callback_msg),
+        ExecuteMsg::UpdateFlashLoanFee { flash_loan_fee } => {
+            update_flash_loan_fee(deps, info, flash_loan_fee)
+        }
+        ExecuteMsg::UpdateFeeMultiplier { fee_multiplier } => {
+            update_fee_multiplier(deps, info, fee_multiplier)
+        }
     }
 }
 
@@ -138,12 +380,14 @@ pub fn receive_cw20(
             ask_asset,
             min_out_amount,
             to,
+            deadline,
         }) => {
+            assert_deadline(&env, deadline)?;
+
             // only asset contract can execute this message
             let mut authorized: bool = false;
             let config: PairInfoRaw = PAIR_INFO.load(deps.storage)?;
-            let pools: Vec<Asset> =
-                config.query_pools(&deps.querier, deps.api, env.contract.address.clone())?;
+            let pools: Vec<Asset> = load_pools(deps.as_ref(), &config)?;
             for pool in pools.iter() {
                 if let AssetInfo::Token { contract_addr, .. } = &pool.info {
                     if contract_addr == &info.sender {
@@ -178,33 +422,177 @@ pub fn receive_cw20(
                 to_addr,
             )
         }
+        Ok(Cw20HookMsg::SwapExactOut {
+            ask_asset,
+            to,
+            deadline,
+        }) => {
+            assert_deadline(&env, deadline)?;
+
+            // only asset contract can execute this message
+            let pair_info: PairInfoRaw = PAIR_INFO.load(deps.storage)?;
+            let pools: Vec<Asset> = load_pools(deps.as_ref(), &pair_info)?;
+            let offer_asset = Asset {
+                info: AssetInfo::Token {
+                    contract_addr: contract_addr.to_string(),
+                },
+                amount: cw20_msg.amount,
+            };
+            if find_pool_index(&pools, &offer_asset.info).is_err() {
+                return Err(ContractError::Unauthorized {});
+            }
+
+            let to_addr = if let Some(to_addr) = to {
+                Some(deps.api.addr_validate(to_addr.as_str())?)
+            } else {
+                None
+            };
+
+            swap_exact_out(
+                deps,
+                env,
+                Addr::unchecked(cw20_msg.sender),
+                offer_asset,
+                ask_asset,
+                to_addr,
+            )
+        }
+        Ok(Cw20HookMsg::ExchangeUnderlying {
+            ask_asset,
+            min_out_amount,
+            to,
+            deadline,
+        }) => {
+            assert_deadline(&env, deadline)?;
+
+            let to_addr = if let Some(to_addr) = to {
+                Some(deps.api.addr_validate(to_addr.as_str())?)
+            } else {
+                None
+            };
+
+            // the offer asset is the sending token contract itself, so only a pair or base
+            // pool asset can be offered
+            exchange_underlying(
+                deps,
+                env,
+                info,
+                Addr::unchecked(cw20_msg.sender),
+                Asset {
+                    info: AssetInfo::Token {
+                        contract_addr: contract_addr.to_string(),
+                    },
+                    amount: cw20_msg.amount,
+                },
+                ask_asset,
+                min_out_amount,
+                to_addr,
+            )
+        }
         Ok(Cw20HookMsg::WithdrawSingleLiquidity {
             asset,
             min_out_amount,
+            receiver,
+            deadline,
+        }) => {
+            assert_deadline(&env, deadline)?;
+
+            let config: PairInfoRaw = PAIR_INFO.load(deps.storage)?;
+            if deps.api.addr_canonicalize(info.sender.as_str())? != config.liquidity_token {
+                return Err(ContractError::Unauthorized {});
+            }
+
+            let sender_addr = deps.api.addr_validate(cw20_msg.sender.as_str())?;
+            let receiver_addr = match receiver {
+                Some(receiver) => deps.api.addr_validate(&receiver)?,
+                None => sender_addr.clone(),
+            };
+            withdraw_single_liquidity(
+                deps,
+                env,
+                sender_addr,
+                receiver_addr,
+                asset,
+                cw20_msg.amount,
+                min_out_amount,
+            )
+        }
+        Ok(Cw20HookMsg::WithdrawLiquidity {
+            min_assets,
+            receiver,
+            deadline,
         }) => {
+            assert_deadline(&env, deadline)?;
+
             let config: PairInfoRaw = PAIR_INFO.load(deps.storage)?;
             if deps.api.addr_canonicalize(info.sender.as_str())? != config.liquidity_token {
                 return Err(ContractError::Unauthorized {});
             }
-            withdraw_single_liquidity(deps, env, info, asset, cw20_msg.amount, min_out_amount)
+
+            let sender_addr = deps.api.addr_validate(cw20_msg.sender.as_str())?;
+            let receiver_addr = match receiver {
+                Some(receiver) => deps.api.addr_validate(&receiver)?,
+                None => sender_addr.clone(),
+            };
+            withdraw_liquidity(
+                deps,
+                env,
+                sender_addr,
+                receiver_addr,
+                cw20_msg.amount,
+                min_assets,
+            )
         }
-        Ok(Cw20HookMsg::WithdrawLiquidity {}) => {
+        Ok(Cw20HookMsg::WithdrawImbalancedLiquidity {
+            assets,
+            max_burn_amount,
+            receiver,
+            deadline,
+        }) => {
+            assert_deadline(&env, deadline)?;
+
             let config: PairInfoRaw = PAIR_INFO.load(deps.storage)?;
             if deps.api.addr_canonicalize(info.sender.as_str())? != config.liquidity_token {
                 return Err(ContractError::Unauthorized {});
             }
 
             let sender_addr = deps.api.addr_validate(cw20_msg.sender.as_str())?;
-            withdraw_liquidity(deps, env, info, sender_addr, cw20_msg.amount)
+            let receiver_addr = match receiver {
+                Some(receiver) => deps.api.addr_validate(&receiver)?,
+                None => sender_addr.clone(),
+            };
+            withdraw_imbalanced_liquidity(
+                deps,
+                env,
+                sender_addr,
+                receiver_addr,
+                assets,
+                cw20_msg.amount,
+                max_burn_amount,
+            )
         }
         Err(err) => Err(ContractError::Std(err)),
     }
 }
 
-/// This just stores the result for future query
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> StdResult<Response> {
-    let data = msg.result.unwrap().data.unwrap();
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    match msg.id {
+        INSTANTIATE_REPLY_ID => Ok(store_liquidity_token(deps, msg)?),
+        FLASH_LOAN_REPLY_ID => repay_flash_loan(deps, env),
+        EXCHANGE_UNDERLYING_REPLY_ID => complete_exchange_underlying(deps, env),
+        id => Err(StdError::generic_err(format!("Unknown reply id {}", id)).into()),
+    }
+}
+
+/// This just stores the result for future query
+fn store_liquidity_token(deps: DepsMut, msg: Reply) -> StdResult<Response> {
+    let data = msg
+        .result
+        .into_result()
+        .map_err(StdError::generic_err)?
+        .data
+        .ok_or_else(|| StdError::generic_err("Missing instantiate response data"))?;
     let res: MsgInstantiateContractResponse =
         Message::parse_from_bytes(data.as_slice()).map_err(|_| {
             StdError::parse_err("MsgInstantiateContractResponse", "failed to parse data")
@@ -229,36 +617,38 @@ pub fn provide_liquidity(
     min_out_amount: Uint128,
     receiver: Option<String>,
 ) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage, &env)?;
+    assert_no_flash_loan(deps.storage)?;
+
     for asset in assets.iter() {
         asset.assert_sent_native_token_balance(&info)?;
     }
+    // coins that are not deposited would be left in the pair without any share for them
+    for coin in info.funds.iter() {
+        if !assets.iter().any(
+            |asset| matches!(&asset.info, AssetInfo::NativeToken { denom } if *denom == coin.denom),
+        ) {
+            return Err(ContractError::UnknownAsset {
+                asset: coin.denom.clone(),
+            });
+        }
+    }
 
-    let pair_info: PairInfoRaw = PAIR_INFO.load(deps.storage)?;
-    let mut pools: Vec<Asset> =
-        pair_info.query_pools(&deps.querier, deps.api, env.contract.address.clone())?;
-
-    let mut deposits = Vec::with_capacity(pools.len());
-    for (i, asset) in assets.iter().enumerate() {
-        assert_eq!(asset.info, pools[i].info);
-        deposits.push(asset.amount);
-    }
-    // let deposits: [Uint128; 2] = [
-    //     assets
-    //         .iter()
-    //         .find(|a| a.info.equal(&pools[0].info))
-    //         .map(|a| a.amount)
-    //         .expect("Wrong asset info is given"),
-    //     assets
-    //         .iter()
-    //         .find(|a| a.info.equal(&pools[1].info))
-    //         .map(|a| a.amount)
-    //         .expect("Wrong asset info is given"),
-    // ];
+    let pair_info: PairInfoRaw = load_pair_info(deps.storage, &env)?;
+    let config: Config = CONFIG.load(deps.storage)?;
+    let pools: Vec<Asset> = load_pools(deps.as_ref(), &pair_info)?;
+
+    let deposits = get_deposits(&pools, &assets)?;
 
     let mut messages: Vec<CosmosMsg> = vec![];
-    for (i, pool) in pools.iter_mut().enumerate() {
+    for (i, pool) in pools.iter().enumerate() {
         // If the pool is token contract, then we need to execute TransferFrom msg to receive funds
         if let AssetInfo::Token { contract_addr, .. } = &pool.info {
+            // cw20 rejects zero amount transfers
+            if deposits[i].is_zero() {
+                continue;
+            }
+
             messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
                 contract_addr: contract_addr.to_string(),
                 msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
@@ -268,10 +658,6 @@ pub fn provide_liquidity(
                 })?,
                 funds: vec![],
             }));
-        } else {
-            // If the asset is native token, balance is already increased
-            // To calculated properly we should subtract user deposit from the pool
-            pool.amount = pool.amount.checked_sub(deposits[i])?;
         }
     }
 
@@ -279,42 +665,41 @@ pub fn provide_liquidity(
     // assert_slippage_tolerance(&slippage_tolerance, &deposits, &pools)?;
 
     let liquidity_token = deps.api.addr_humanize(&pair_info.liquidity_token)?;
-    let total_share = query_supply(&deps.querier, liquidity_token)?;
-
-    let mut old_balances = Vec::with_capacity(pools.len());
-    let mut new_balances = Vec::with_capacity(pools.len());
-    for (i, pool) in pools.iter().enumerate() {
-        let pool_balance = pool.amount.u128();
-        old_balances.push(pool_balance);
-        new_balances.push(pool_balance.checked_add(deposits[i].u128()).unwrap());
-    }
+    let total_share = query_supply(&deps.querier, liquidity_token.clone())?;
 
-    // TODO find better way for type conversion
-    let mint_amount = Curve {
-        amp: pair_info.amplification.u128() as u64,
-        fee_numerator: pair_info.fee.u128() as u64,
-    }
-    .deposit(&old_balances, &new_balances, total_share.u128() as u64)
-    .unwrap();
-
-    if mint_amount < min_out_amount.u128() as u64 {
-        return Err(ContractError::MaxSlippageAssertion {});
+    let curve = get_curve(deps.as_ref(), &pair_info, &config)?;
+    update_price_cumulatives(deps.storage, &env, &curve, &pools)?;
+    let (share, admin_fees) = compute_mint_amount(&curve, &pools, &deposits, total_share)?;
+    if share < min_out_amount {
+        return Err(ContractError::SlippageExceeded {
+            min_out_amount,
+            out_amount: share,
+        });
     }
 
-    let share = Uint128::from(mint_amount as u128);
-
     // prevent providing free token
     if share.is_zero() {
         return Err(ContractError::InvalidZeroAmount {});
     }
 
+    add_admin_fees(deps.storage, &admin_fees)?;
+    update_reserves(deps.storage, &deposits, &admin_fees)?;
+
+    if total_share.is_zero() {
+        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: liquidity_token.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Mint {
+                recipient: env.contract.address.to_string(),
+                amount: Uint128::from(MINIMUM_LIQUIDITY_AMOUNT),
+            })?,
+            funds: vec![],
+        }));
+    }
+
     // mint LP token to sender
     let receiver = receiver.unwrap_or_else(|| info.sender.to_string());
     messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
-        contract_addr: deps
-            .api
-            .addr_humanize(&pair_info.liquidity_token)?
-            .to_string(),
+        contract_addr: liquidity_token.to_string(),
         msg: to_binary(&Cw20ExecuteMsg::Mint {
             recipient: receiver.to_string(),
             amount: share,
@@ -338,29 +723,36 @@ pub fn provide_liquidity(
 pub fn withdraw_liquidity(
     deps: DepsMut,
     env: Env,
-    _info: MessageInfo,
     sender: Addr,
+    receiver: Addr,
     amount: Uint128,
+    min_assets: Vec<Asset>,
 ) -> Result<Response, ContractError> {
-    let pair_info: PairInfoRaw = PAIR_INFO.load(deps.storage)?;
+    assert_no_flash_loan(deps.storage)?;
+
+    let pair_info: PairInfoRaw = load_pair_info(deps.storage, &env)?;
     let liquidity_addr: Addr = deps.api.addr_humanize(&pair_info.liquidity_token)?;
 
-    let pools: Vec<Asset> = pair_info.query_pools(&deps.querier, deps.api, env.contract.address)?;
+    let pools: Vec<Asset> = load_pools(deps.as_ref(), &pair_info)?;
     let total_share: Uint128 = query_supply(&deps.querier, liquidity_addr)?;
 
-    let share_ratio: Decimal = Decimal::from_ratio(amount, total_share);
-    let refund_assets: Vec<Asset> = pools
-        .iter()
-        .map(|a| Asset {
-            info: a.info.clone(),
-            amount: a.amount * share_ratio,
-        })
-        .collect();
+    let curve = get_curve(deps.as_ref(), &pair_info, &CONFIG.load(deps.storage)?)?;
+    update_price_cumulatives(deps.storage, &env, &curve, &pools)?;
+
+    let refund_assets = compute_withdraw_amounts(&pools, amount, total_share)?;
+    assert_min_assets(&refund_assets, &min_assets)?;
+
+    let refund_amounts: Vec<Uint128> = refund_assets.iter().map(|a| a.amount).collect();
+    update_reserves(
+        deps.storage,
+        &vec![Uint128::zero(); pools.len()],
+        &refund_amounts,
+    )?;
 
     let mut refund_assets_msg: Vec<CosmosMsg> = refund_assets
         .iter()
-        .map(|a| a.clone().into_msg(&deps.querier, sender.clone()).unwrap())
-        .collect();
+        .map(|a| a.clone().into_msg(&deps.querier, receiver.clone()))
+        .collect::<StdResult<Vec<CosmosMsg>>>()?;
 
     refund_assets_msg.push(CosmosMsg::Wasm(WasmMsg::Execute {
         contract_addr: deps
@@ -381,6 +773,7 @@ pub fn withdraw_liquidity(
         .add_attributes(vec![
             ("action", "withdraw_liquidity"),
             ("sender", sender.as_str()),
+            ("receiver", receiver.as_str()),
             ("withdrawn_share", &amount.to_string()),
             // ("refund_assets", assets_msg),
         ]))
@@ -389,57 +782,54 @@ pub fn withdraw_liquidity(
 pub fn withdraw_single_liquidity(
     deps: DepsMut,
     env: Env,
-    info: MessageInfo,
+    sender: Addr,
+    receiver: Addr,
     asset: Asset,
     unmint_amount: Uint128,
     min_out_amount: Uint128,
 ) -> Result<Response, ContractError> {
-    let pair_info: PairInfoRaw = PAIR_INFO.load(deps.storage)?;
+    assert_not_paused(deps.storage, &env)?;
+    assert_no_flash_loan(deps.storage)?;
+
+    let pair_info: PairInfoRaw = load_pair_info(deps.storage, &env)?;
     let liquidity_addr: Addr = deps.api.addr_humanize(&pair_info.liquidity_token)?;
 
-    let pools: Vec<Asset> = pair_info.query_pools(&deps.querier, deps.api, env.contract.address)?;
+    let pools: Vec<Asset> = load_pools(deps.as_ref(), &pair_info)?;
     let total_share: Uint128 = query_supply(&deps.querier, liquidity_addr)?;
 
-    // TODO find better way for type conversion
+    let i = find_pool_index(&pools, &asset.info)?;
+    let curve = get_curve(deps.as_ref(), &pair_info, &CONFIG.load(deps.storage)?)?;
+    update_price_cumulatives(deps.storage, &env, &curve, &pools)?;
+    let (out_amount, admin_fee) =
+        compute_withdraw_single_amount(&curve, &pools, i, unmint_amount, total_share)?;
 
-    let old_balances: Vec<u128> = pools.iter().map(|a| a.amount.u128()).collect();
-    let mut i = 0;
-    let mut is_find = false;
-    for (index, pool) in pools.iter().enumerate() {
-        if asset.info.equal(&pool.info) {
-            i = index;
-            is_find = true;
-            break;
-        }
+    if out_amount < min_out_amount {
+        return Err(ContractError::SlippageExceeded {
+            min_out_amount,
+            out_amount,
+        });
     }
-    assert_eq!(is_find, true);
 
-    let out_amount = Curve {
-        amp: pair_info.amplification.u128() as u64,
-        fee_numerator: pair_info.fee.u128() as u64,
-    }
-    .remove_liquidity_single_token(
-        &old_balances,
-        unmint_amount.u128() as u64,
-        i as u8,
-        total_share.u128() as u64,
-    )
-    .unwrap();
+    let mut admin_fees = vec![Uint128::zero(); pools.len()];
+    admin_fees[i] = admin_fee;
+    add_admin_fees(deps.storage, &admin_fees)?;
 
-    assert_eq!(out_amount > min_out_amount.u128() as u64, true);
+    let mut outflows = admin_fees;
+    outflows[i] = outflows[i].checked_add(out_amount)?;
+    update_reserves(deps.storage, &vec![Uint128::zero(); pools.len()], &outflows)?;
 
     let refund_asset = Asset {
-        info: pools[i as usize].info.clone(),
-        amount: Uint128::from(out_amount as u128),
+        info: pools[i].info.clone(),
+        amount: out_amount,
     };
 
     // update pool info
-    let amount = Uint128::from(unmint_amount);
+    let amount = unmint_amount;
     Ok(Response::new()
         .add_messages(vec![
             refund_asset
                 .clone()
-                .into_msg(&deps.querier, info.sender.clone())?,
+                .into_msg(&deps.querier, receiver.clone())?,
             // burn liquidity token
             CosmosMsg::Wasm(WasmMsg::Execute {
                 contract_addr: deps
@@ -452,12 +842,98 @@ pub fn withdraw_single_liquidity(
         ])
         .add_attributes(vec![
             ("action", "withdraw_single_liquidity"),
-            ("sender", info.sender.as_str()),
+            ("sender", sender.as_str()),
+            ("receiver", receiver.as_str()),
             ("withdrawn_share", &amount.to_string()),
             ("refund_asset", &format!("{}", refund_asset)),
         ]))
 }
 
+pub fn withdraw_imbalanced_liquidity(
+    deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    receiver: Addr,
+    assets: Vec<Asset>,
+    amount: Uint128,
+    max_burn_amount: Uint128,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage, &env)?;
+    assert_no_flash_loan(deps.storage)?;
+
+    let pair_info: PairInfoRaw = load_pair_info(deps.storage, &env)?;
+    let liquidity_addr: Addr = deps.api.addr_humanize(&pair_info.liquidity_token)?;
+
+    let pools: Vec<Asset> = load_pools(deps.as_ref(), &pair_info)?;
+    let total_share: Uint128 = query_supply(&deps.querier, liquidity_addr.clone())?;
+
+    let amounts = get_deposits(&pools, &assets)?;
+    let old_balances: Vec<Uint128> = pools.iter().map(|a| a.amount).collect();
+    let curve = get_curve(deps.as_ref(), &pair_info, &CONFIG.load(deps.storage)?)?;
+    update_price_cumulatives(deps.storage, &env, &curve, &pools)?;
+    let (burn_amount, admin_fees) =
+        curve.remove_liquidity_imbalance(&old_balances, &amounts, total_share)?;
+
+    // the sent LP tokens bound the burn as well
+    let max_burn_amount = max_burn_amount.min(amount);
+    if burn_amount > max_burn_amount {
+        return Err(ContractError::MaxBurnExceeded {
+            max_burn_amount,
+            burn_amount,
+        });
+    }
+
+    add_admin_fees(deps.storage, &admin_fees)?;
+
+    let outflows = amounts
+        .iter()
+        .zip(admin_fees.iter())
+        .map(|(amount, admin_fee)| amount.checked_add(*admin_fee))
+        .collect::<Result<Vec<Uint128>, _>>()?;
+    update_reserves(deps.storage, &vec![Uint128::zero(); pools.len()], &outflows)?;
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    for (pool, amount) in pools.iter().zip(amounts) {
+        if !amount.is_zero() {
+            let refund_asset = Asset {
+                info: pool.info.clone(),
+                amount,
+            };
+            messages.push(refund_asset.into_msg(&deps.querier, receiver.clone())?);
+        }
+    }
+
+    // burn liquidity token
+    messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: liquidity_addr.to_string(),
+        msg: to_binary(&Cw20ExecuteMsg::Burn {
+            amount: burn_amount,
+        })?,
+        funds: vec![],
+    }));
+
+    // refund the LP tokens that were not burned
+    let refund_share = amount.checked_sub(burn_amount)?;
+    if !refund_share.is_zero() {
+        messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: liquidity_addr.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: sender.to_string(),
+                amount: refund_share,
+            })?,
+            funds: vec![],
+        }));
+    }
+
+    Ok(Response::new().add_messages(messages).add_attributes(vec![
+        ("action", "withdraw_imbalanced_liquidity"),
+        ("sender", sender.as_str()),
+        ("receiver", receiver.as_str()),
+        ("withdrawn_share", &burn_amount.to_string()),
+        ("refund_share", &refund_share.to_string()),
+    ]))
+}
+
 // CONTRACT - a user must do token approval
 #[allow(clippy::too_many_arguments)]
 pub fn swap(
@@ -470,11 +946,14 @@ pub fn swap(
     min_out_amount: Uint128,
     to: Option<Addr>,
 ) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage, &env)?;
+    assert_no_flash_loan(deps.storage)?;
+
     offer_asset.assert_sent_native_token_balance(&info)?;
 
-    let pair_info: PairInfoRaw = PAIR_INFO.load(deps.storage)?;
+    let pair_info: PairInfoRaw = load_pair_info(deps.storage, &env)?;
 
-    let pools: Vec<Asset> = pair_info.query_pools(&deps.querier, deps.api, env.contract.address)?;
+    let pools: Vec<Asset> = load_pools(deps.as_ref(), &pair_info)?;
 
     // let offer_pool: Asset;
     // let ask_pool: Asset;
@@ -498,39 +977,28 @@ pub fn swap(
     // }
 
     let offer_amount = offer_asset.amount;
-    let mut i = 0;
-    let mut j = 0;
-    // let mut offer_pool: Asset;
-    let mut ask_pool = pools[0].clone();
-    let mut balances = Vec::with_capacity(pools.len());
-    for (index, pool) in pools.iter().enumerate() {
-        if offer_asset.info.equal(&pool.info) {
-            i = index;
-            let amount = pool.amount.checked_sub(offer_asset.amount)?;
-            // offer_pool = Asset {
-            //     amount: amount,
-            //     info: pool.info.clone(),
-            // };
-            balances.push(amount.u128());
-            continue;
-        }
-        if ask_asset.info.equal(&pool.info) {
-            j = index;
-            ask_pool = pool.clone();
-        }
-        balances.push(pool.amount.u128());
-    }
-    let out_amount = Curve {
-        amp: pair_info.amplification.u128() as u64,
-        fee_numerator: pair_info.fee.u128() as u64,
-    }
-    .exchange(i, j, offer_amount.u128() as u64, &balances)
-    .unwrap();
-    let return_amount = Uint128::from(out_amount);
+    let i = find_pool_index(&pools, &offer_asset.info)?;
+    let j = find_pool_index(&pools, &ask_asset.info)?;
+    if i == j {
+        return Err(ContractError::AssetMismatch {});
+    }
+
+    let ask_pool = pools[j].clone();
+
+    let curve = get_curve(deps.as_ref(), &pair_info, &CONFIG.load(deps.storage)?)?;
+    update_price_cumulatives(deps.storage, &env, &curve, &pools)?;
+    let (return_amount, commission_amount, admin_fee_amount, fee) =
+        exchange_reserves(deps.storage, &curve, &pools, i, j, offer_amount)?;
 
     // let return_amount = compute_swap(offer_pool.amount, ask_pool.amount, offer_amount);
 
-    assert_eq!(return_amount.u128() > min_out_amount.u128(), true);
+    if return_amount < min_out_amount {
+        return Err(ContractError::SlippageExceeded {
+            min_out_amount,
+            out_amount: return_amount,
+        });
+    }
+
     // check max spread limit if exist
     // compute tax
     let return_asset = Asset {
@@ -553,34 +1021,1054 @@ pub fn swap(
         ("ask_asset", &ask_pool.info.to_string()),
         ("offer_amount", &offer_amount.to_string()),
         ("return_amount", &return_amount.to_string()),
+        ("fee", &fee.to_string()),
+        ("commission_amount", &commission_amount.to_string()),
+        ("admin_fee_amount", &admin_fee_amount.to_string()),
+        ("rates", &format_rates(&curve.rates)),
     ]))
 }
 
-#[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> Result<Binary, ContractError> {
-    match msg {
-        QueryMsg::Pair {} => Ok(to_binary(&query_pair_info(deps)?)?),
-        QueryMsg::Pool {} => Ok(to_binary(&query_pool(deps)?)?),
-        // QueryMsg::Simulation { offer_asset } => {
-        //     Ok(to_binary(&query_simulation(deps, offer_asset)?)?)
-        // }
-        // QueryMsg::ReverseSimulation { ask_asset } => {
-        //     Ok(to_binary(&query_reverse_simulation(deps, ask_asset)?)?)
-        // }
-    }
-}
-
-pub fn query_pair_info(deps: Deps) -> Result<PairInfo, ContractError> {
-    let pair_info: PairInfoRaw = PAIR_INFO.load(deps.storage)?;
-    let pair_info = pair_info.to_normal(deps.api)?;
-
-    Ok(pair_info)
+/// Buys exactly `ask_asset`, `offer_asset` is what the sender paid in and bounds the offer,
+/// the part of it the swap does not take is refunded
+pub fn swap_exact_out(
+    deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    offer_asset: Asset,
+    ask_asset: Asset,
+    to: Option<Addr>,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage, &env)?;
+    assert_no_flash_loan(deps.storage)?;
+
+    if ask_asset.amount.is_zero() {
+        return Err(ContractError::InvalidZeroAmount {});
+    }
+
+    let pair_info: PairInfoRaw = load_pair_info(deps.storage, &env)?;
+    let pools: Vec<Asset> = load_pools(deps.as_ref(), &pair_info)?;
+
+    let i = find_pool_index(&pools, &offer_asset.info)?;
+    let j = find_pool_index(&pools, &ask_asset.info)?;
+    if i == j {
+        return Err(ContractError::AssetMismatch {});
+    }
+
+    let balances: Vec<Uint128> = pools.iter().map(|a| a.amount).collect();
+    let curve = get_curve(deps.as_ref(), &pair_info, &CONFIG.load(deps.storage)?)?;
+    update_price_cumulatives(deps.storage, &env, &curve, &pools)?;
+    // the commission is taken from the ask asset on top of the returned amount
+    let (offer_amount, commission_amount, fee) =
+        curve.reverse_exchange(j, i, ask_asset.amount, &balances)?;
+    let admin_fee_amount = curve.admin_fee(commission_amount)?;
+
+    if offer_amount > offer_asset.amount {
+        return Err(ContractError::MaxOfferExceeded {
+            max_offer_amount: offer_asset.amount,
+            offer_amount,
+        });
+    }
+
+    let mut admin_fees = vec![Uint128::zero(); pools.len()];
+    admin_fees[j] = admin_fee_amount;
+    add_admin_fees(deps.storage, &admin_fees)?;
+
+    let mut inflows = vec![Uint128::zero(); pools.len()];
+    inflows[i] = offer_amount;
+    let mut outflows = admin_fees;
+    outflows[j] = outflows[j].checked_add(ask_asset.amount)?;
+    update_reserves(deps.storage, &inflows, &outflows)?;
+
+    let receiver = to.unwrap_or_else(|| sender.clone());
+    let mut messages: Vec<CosmosMsg> = vec![Asset {
+        info: pools[j].info.clone(),
+        amount: ask_asset.amount,
+    }
+    .into_msg(&deps.querier, receiver.clone())?];
+
+    let refund_amount = offer_asset.amount.checked_sub(offer_amount)?;
+    if !refund_amount.is_zero() {
+        let refund_asset = Asset {
+            info: pools[i].info.clone(),
+            amount: refund_amount,
+        };
+        messages.push(refund_asset.into_msg(&deps.querier, sender.clone())?);
+    }
+
+    Ok(Response::new().add_messages(messages).add_attributes(vec![
+        ("action", "swap_exact_out"),
+        ("sender", sender.as_str()),
+        ("receiver", receiver.as_str()),
+        ("offer_asset", &pools[i].info.to_string()),
+        ("ask_asset", &pools[j].info.to_string()),
+        ("offer_amount", &offer_amount.to_string()),
+        ("return_amount", &ask_asset.amount.to_string()),
+        ("refund_amount", &refund_amount.to_string()),
+        ("fee", &fee.to_string()),
+        ("commission_amount", &commission_amount.to_string()),
+        ("admin_fee_amount", &admin_fee_amount.to_string()),
+        ("rates", &format_rates(&curve.rates)),
+    ]))
+}
+
+/// Swaps between the metapool assets and the base pool assets. A base pool asset is deposited
+/// into the base pool first or withdrawn from it last, an exchange between two base pool
+/// assets is a plain swap on the base pool.
+#[allow(clippy::too_many_arguments)]
+pub fn exchange_underlying(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    sender: Addr,
+    offer_asset: Asset,
+    ask_asset: Asset,
+    min_out_amount: Uint128,
+    to: Option<Addr>,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage, &env)?;
+    assert_no_flash_loan(deps.storage)?;
+
+    offer_asset.assert_sent_native_token_balance(&info)?;
+
+    let base_pool = BASE_POOL
+        .may_load(deps.storage)?
+        .ok_or(ContractError::NotMetapool {})?;
+    let pair_info: PairInfoRaw = load_pair_info(deps.storage, &env)?;
+    let pools: Vec<Asset> = load_pools(deps.as_ref(), &pair_info)?;
+    let lp_token_info = AssetInfo::Token {
+        contract_addr: base_pool.liquidity_token.to_string(),
+    };
+    let base_index = find_pool_index(&pools, &lp_token_info)?;
+
+    // the LP token itself is not an underlying asset
+    if offer_asset.info.equal(&ask_asset.info)
+        || offer_asset.info.equal(&lp_token_info)
+        || ask_asset.info.equal(&lp_token_info)
+    {
+        return Err(ContractError::AssetMismatch {});
+    }
+
+    let offer_index = find_underlying_index(&pools, &base_pool, &offer_asset.info)?;
+    let ask_index = find_underlying_index(&pools, &base_pool, &ask_asset.info)?;
+    let receiver = to.unwrap_or_else(|| sender.clone());
+    match (offer_index, ask_index) {
+        (Some(_), Some(_)) => swap(
+            deps,
+            env,
+            info,
+            sender,
+            offer_asset,
+            ask_asset,
+            min_out_amount,
+            Some(receiver),
+        ),
+        (Some(i), None) => {
+            let curve = get_curve(deps.as_ref(), &pair_info, &CONFIG.load(deps.storage)?)?;
+            update_price_cumulatives(deps.storage, &env, &curve, &pools)?;
+            let (lp_amount, commission_amount, admin_fee_amount, fee) = exchange_reserves(
+                deps.storage,
+                &curve,
+                &pools,
+                i,
+                base_index,
+                offer_asset.amount,
+            )?;
+
+            // the base pool pays the receiver and asserts the minimum output
+            let withdraw_msg = CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: base_pool.liquidity_token.to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Send {
+                    contract: base_pool.contract_addr.to_string(),
+                    amount: lp_amount,
+                    msg: to_binary(&Cw20HookMsg::WithdrawSingleLiquidity {
+                        asset: Asset {
+                            info: ask_asset.info.clone(),
+                            amount: Uint128::zero(),
+                        },
+                        min_out_amount,
+                        receiver: Some(receiver.to_string()),
+                        deadline: None,
+                    })?,
+                })?,
+                funds: vec![],
+            });
+
+            Ok(Response::new()
+                .add_submessage(SubMsg::new(withdraw_msg))
+                .add_attributes(vec![
+                    ("action", "exchange_underlying"),
+                    ("sender", sender.as_str()),
+                    ("receiver", receiver.as_str()),
+                    ("offer_asset", &offer_asset.info.to_string()),
+                    ("ask_asset", &ask_asset.info.to_string()),
+                    ("offer_amount", &offer_asset.amount.to_string()),
+                    ("base_lp_amount", &lp_amount.to_string()),
+                    ("fee", &fee.to_string()),
+                    ("commission_amount", &commission_amount.to_string()),
+                    ("admin_fee_amount", &admin_fee_amount.to_string()),
+                    ("rates", &format_rates(&curve.rates)),
+                ]))
+        }
+        (None, Some(_)) => {
+            let mut messages: Vec<CosmosMsg> = vec![];
+            let (deposit_asset, funds) = match &offer_asset.info {
+                AssetInfo::NativeToken { .. } => {
+                    let coin = offer_asset.deduct_tax(&deps.querier)?;
+                    let deposit_asset = Asset {
+                        info: offer_asset.info.clone(),
+                        amount: coin.amount,
+                    };
+                    (deposit_asset, vec![coin])
+                }
+                AssetInfo::Token { contract_addr } => {
+                    messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+                        contract_addr: contract_addr.to_string(),
+                        msg: to_binary(&Cw20ExecuteMsg::IncreaseAllowance {
+                            spender: base_pool.contract_addr.to_string(),
+                            amount: offer_asset.amount,
+                            expires: None,
+                        })?,
+                        funds: vec![],
+                    }));
+                    (offer_asset.clone(), vec![])
+                }
+            };
+
+            // the reply swaps the minted LP tokens for the ask asset
+            let lp_token_balance = query_token_balance(
+                &deps.querier,
+                base_pool.liquidity_token.clone(),
+                env.contract.address.clone(),
+            )?;
+            PENDING_EXCHANGE.save(
+                deps.storage,
+                &PendingExchange {
+                    sender,
+                    receiver,
+                    offer_asset,
+                    ask_asset_info: ask_asset.info,
+                    min_out_amount,
+                    lp_token_balance,
+                },
+            )?;
+
+            Ok(Response::new()
+                .add_messages(messages)
+                .add_submessage(SubMsg {
+                    msg: WasmMsg::Execute {
+                        contract_addr: base_pool.contract_addr.to_string(),
+                        msg: to_binary(&ExecuteMsg::ProvideLiquidity {
+                            assets: vec![deposit_asset],
+                            min_out_amount: Uint128::zero(),
+                            receiver: None,
+                            deadline: None,
+                        })?,
+                        funds,
+                    }
+                    .into(),
+                    gas_limit: None,
+                    id: EXCHANGE_UNDERLYING_REPLY_ID,
+                    reply_on: ReplyOn::Success,
+                }))
+        }
+        (None, None) => {
+            let swap_msg = match &offer_asset.info {
+                AssetInfo::NativeToken { .. } => {
+                    let coin = offer_asset.deduct_tax(&deps.querier)?;
+                    WasmMsg::Execute {
+                        contract_addr: base_pool.contract_addr.to_string(),
+                        msg: to_binary(&ExecuteMsg::Swap {
+                            offer_asset: Asset {
+                                info: offer_asset.info.clone(),
+                                amount: coin.amount,
+                            },
+                            ask_asset: ask_asset.clone(),
+                            min_out_amount,
+                            to: Some(receiver.to_string()),
+                            deadline: None,
+                        })?,
+                        funds: vec![coin],
+                    }
+                }
+                AssetInfo::Token { contract_addr } => WasmMsg::Execute {
+                    contract_addr: contract_addr.to_string(),
+                    msg: to_binary(&Cw20ExecuteMsg::Send {
+                        contract: base_pool.contract_addr.to_string(),
+                        amount: offer_asset.amount,
+                        msg: to_binary(&Cw20HookMsg::Swap {
+                            ask_asset: ask_asset.clone(),
+                            min_out_amount,
+                            to: Some(receiver.to_string()),
+                            deadline: None,
+                        })?,
+                    })?,
+                    funds: vec![],
+                },
+            };
+
+            Ok(Response::new()
+                .add_submessage(SubMsg::new(swap_msg))
+                .add_attributes(vec![
+                    ("action", "exchange_underlying"),
+                    ("sender", sender.as_str()),
+                    ("receiver", receiver.as_str()),
+                    ("offer_asset", &offer_asset.info.to_string()),
+                    ("ask_asset", &ask_asset.info.to_string()),
+                    ("offer_amount", &offer_asset.amount.to_string()),
+                ]))
+        }
+    }
+}
+
+/// Swaps the LP tokens the base pool deposit of `exchange_underlying` minted for the ask asset
+fn complete_exchange_underlying(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let pending: PendingExchange = PENDING_EXCHANGE.load(deps.storage)?;
+    PENDING_EXCHANGE.remove(deps.storage);
+
+    let base_pool: BasePool = BASE_POOL.load(deps.storage)?;
+    let lp_amount = query_token_balance(
+        &deps.querier,
+        base_pool.liquidity_token.clone(),
+        env.contract.address.clone(),
+    )?
+    .checked_sub(pending.lp_token_balance)?;
+
+    let pair_info: PairInfoRaw = load_pair_info(deps.storage, &env)?;
+    let pools: Vec<Asset> = load_pools(deps.as_ref(), &pair_info)?;
+    let i = find_pool_index(
+        &pools,
+        &AssetInfo::Token {
+            contract_addr: base_pool.liquidity_token.to_string(),
+        },
+    )?;
+    let j = find_pool_index(&pools, &pending.ask_asset_info)?;
+
+    let curve = get_curve(deps.as_ref(), &pair_info, &CONFIG.load(deps.storage)?)?;
+    update_price_cumulatives(deps.storage, &env, &curve, &pools)?;
+    let (return_amount, commission_amount, admin_fee_amount, fee) =
+        exchange_reserves(deps.storage, &curve, &pools, i, j, lp_amount)?;
+    if return_amount < pending.min_out_amount {
+        return Err(ContractError::SlippageExceeded {
+            min_out_amount: pending.min_out_amount,
+            out_amount: return_amount,
+        });
+    }
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    if !return_amount.is_zero() {
+        let return_asset = Asset {
+            info: pools[j].info.clone(),
+            amount: return_amount,
+        };
+        messages.push(return_asset.into_msg(&deps.querier, pending.receiver.clone())?);
+    }
+
+    Ok(Response::new().add_messages(messages).add_attributes(vec![
+        ("action", "exchange_underlying"),
+        ("sender", pending.sender.as_str()),
+        ("receiver", pending.receiver.as_str()),
+        ("offer_asset", &pending.offer_asset.info.to_string()),
+        ("ask_asset", &pools[j].info.to_string()),
+        ("offer_amount", &pending.offer_asset.amount.to_string()),
+        ("base_lp_amount", &lp_amount.to_string()),
+        ("return_amount", &return_amount.to_string()),
+        ("fee", &fee.to_string()),
+        ("commission_amount", &commission_amount.to_string()),
+        ("admin_fee_amount", &admin_fee_amount.to_string()),
+        ("rates", &format_rates(&curve.rates)),
+    ]))
+}
+
+pub fn ramp_amp(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    future_amp: Uint128,
+    future_time: u64,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.storage, &info.sender)?;
+
+    let block_time = env.block.time.seconds();
+    let ramp = AMP_RAMP.load(deps.storage)?;
+    if block_time < ramp.initial_time + MIN_RAMP_TIME || future_time < block_time + MIN_RAMP_TIME {
+        return Err(ContractError::InvalidRampTime {});
+    }
+
+    if future_amp.is_zero() || future_amp.u128() > MAX_AMP {
+        return Err(ContractError::InvalidAmp {});
+    }
+
+    let initial_amp = ramp.current_amp(block_time);
+    if future_amp > initial_amp {
+        if future_amp > initial_amp.checked_mul(MAX_AMP_CHANGE.into())? {
+            return Err(ContractError::AmpChangeTooLarge {});
+        }
+    } else if future_amp.checked_mul(MAX_AMP_CHANGE.into())? < initial_amp {
+        return Err(ContractError::AmpChangeTooLarge {});
+    }
+
+    AMP_RAMP.save(
+        deps.storage,
+        &AmpRamp {
+            initial_amp,
+            initial_time: block_time,
+            future_amp,
+            future_time,
+        },
+    )?;
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "ramp_amp"),
+        ("initial_amp", &initial_amp.to_string()),
+        ("future_amp", &future_amp.to_string()),
+        ("future_time", &future_time.to_string()),
+    ]))
+}
+
+pub fn stop_ramp_amp(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    assert_owner(deps.storage, &info.sender)?;
+
+    let block_time = env.block.time.seconds();
+    let ramp = AMP_RAMP.load(deps.storage)?;
+    let current_amp = ramp.current_amp(block_time);
+
+    AMP_RAMP.save(
+        deps.storage,
+        &AmpRamp {
+            initial_amp: current_amp,
+            initial_time: block_time,
+            future_amp: current_amp,
+            future_time: block_time,
+        },
+    )?;
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "stop_ramp_amp"),
+        ("amp", &current_amp.to_string()),
+    ]))
+}
+
+pub fn update_fee(
+    deps: DepsMut,
+    info: MessageInfo,
+    fee: Uint128,
+) -> Result<Response, ContractError> {
+    let config = assert_owner(deps.storage, &info.sender)?;
+    assert_swap_fee(fee, config.fee_multiplier)?;
+
+    PAIR_INFO.update(deps.storage, |mut pair_info| -> StdResult<_> {
+        pair_info.fee = fee;
+        Ok(pair_info)
+    })?;
+
+    Ok(Response::new().add_attributes(vec![("action", "update_fee"), ("fee", &fee.to_string())]))
+}
+
+pub fn pause(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    expiry_height: Option<u64>,
+) -> Result<Response, ContractError> {
+    let mut config = assert_owner_or_guardian(deps.storage, &info.sender)?;
+
+    if let Some(expiry_height) = expiry_height {
+        if expiry_height <= env.block.height {
+            return Err(ContractError::InvalidExpiryHeight {});
+        }
+    }
+
+    config.paused = true;
+    config.pause_expiry_height = expiry_height;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "pause"),
+        (
+            "expiry_height",
+            &expiry_height.map_or("none".to_string(), |height| height.to_string()),
+        ),
+    ]))
+}
+
+pub fn unpause(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    let mut config = assert_owner_or_guardian(deps.storage, &info.sender)?;
+
+    config.paused = false;
+    config.pause_expiry_height = None;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new().add_attribute("action", "unpause"))
+}
+
+pub fn update_guardian(
+    deps: DepsMut,
+    info: MessageInfo,
+    guardian: Option<String>,
+) -> Result<Response, ContractError> {
+    let mut config = assert_owner(deps.storage, &info.sender)?;
+
+    config.guardian = guardian
+        .map(|guardian| deps.api.addr_validate(&guardian))
+        .transpose()?;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "update_guardian"),
+        (
+            "guardian",
+            config
+                .guardian
+                .as_ref()
+                .map_or("none", |addr| addr.as_str()),
+        ),
+    ]))
+}
+
+pub fn update_admin_fee(
+    deps: DepsMut,
+    info: MessageInfo,
+    admin_fee: Uint128,
+) -> Result<Response, ContractError> {
+    let mut config = assert_owner(deps.storage, &info.sender)?;
+
+    if admin_fee > Uint128::from(FEE_DENOMINATOR) {
+        return Err(ContractError::InvalidFee {});
+    }
+
+    config.admin_fee = admin_fee;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "update_admin_fee"),
+        ("admin_fee", &admin_fee.to_string()),
+    ]))
+}
+
+pub fn update_fee_collector(
+    deps: DepsMut,
+    info: MessageInfo,
+    fee_collector: String,
+) -> Result<Response, ContractError> {
+    let mut config = assert_owner(deps.storage, &info.sender)?;
+
+    let fee_collector = deps.api.addr_validate(&fee_collector)?;
+    config.fee_collector = Some(fee_collector.clone());
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "update_fee_collector"),
+        ("fee_collector", fee_collector.as_str()),
+    ]))
+}
+
+/// Anyone can trigger the payout, the fees only ever go to the fee collector
+pub fn withdraw_admin_fees(deps: DepsMut) -> Result<Response, ContractError> {
+    assert_no_flash_loan(deps.storage)?;
+
+    let config: Config = CONFIG.load(deps.storage)?;
+    let fee_collector = config
+        .fee_collector
+        .ok_or(ContractError::NoFeeCollector {})?;
+
+    let assets = query_admin_fees(deps.as_ref())?.assets;
+    ADMIN_FEES.save(deps.storage, &vec![Uint128::zero(); assets.len()])?;
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    for asset in assets.iter().filter(|asset| !asset.amount.is_zero()) {
+        messages.push(
+            asset
+                .clone()
+                .into_msg(&deps.querier, fee_collector.clone())?,
+        );
+    }
+
+    let admin_fees: Vec<String> = assets.iter().map(|asset| asset.to_string()).collect();
+    Ok(Response::new().add_messages(messages).add_attributes(vec![
+        ("action", "withdraw_admin_fees"),
+        ("fee_collector", fee_collector.as_str()),
+        ("admin_fees", &admin_fees.join(", ")),
+    ]))
+}
+
+/// Anyone can skim, the surplus is not part of the reserves and does not move the price
+pub fn skim(deps: DepsMut, env: Env, to: String) -> Result<Response, ContractError> {
+    assert_no_flash_loan(deps.storage)?;
+
+    let to = deps.api.addr_validate(&to)?;
+    let pair_info: PairInfoRaw = PAIR_INFO.load(deps.storage)?;
+    let assets = query_surplus(deps.as_ref(), &pair_info, env.contract.address)?;
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    for asset in assets.iter().filter(|asset| !asset.amount.is_zero()) {
+        messages.push(asset.clone().into_msg(&deps.querier, to.clone())?);
+    }
+
+    let surplus: Vec<String> = assets.iter().map(|asset| asset.to_string()).collect();
+    Ok(Response::new().add_messages(messages).add_attributes(vec![
+        ("action", "skim"),
+        ("to", to.as_str()),
+        ("assets", &surplus.join(", ")),
+    ]))
+}
+
+/// Adopts the surplus into the reserves, so donations accrue to the liquidity providers
+pub fn sync(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage, &env)?;
+    assert_no_flash_loan(deps.storage)?;
+
+    let pair_info: PairInfoRaw = load_pair_info(deps.storage, &env)?;
+    let curve = get_curve(deps.as_ref(), &pair_info, &CONFIG.load(deps.storage)?)?;
+    let pools: Vec<Asset> = load_pools(deps.as_ref(), &pair_info)?;
+    update_price_cumulatives(deps.storage, &env, &curve, &pools)?;
+
+    let admin_fees = ADMIN_FEES.load(deps.storage)?;
+    let assets: Vec<Asset> = pair_info
+        .query_pools(&deps.querier, deps.api, env.contract.address)?
+        .into_iter()
+        .zip(admin_fees)
+        .map(|(mut pool, admin_fee)| {
+            pool.amount = pool.amount.saturating_sub(admin_fee);
+            pool
+        })
+        .collect();
+    RESERVES.save(
+        deps.storage,
+        &assets.iter().map(|asset| asset.amount).collect(),
+    )?;
+
+    let reserves: Vec<String> = assets.iter().map(|asset| asset.to_string()).collect();
+    Ok(
+        Response::new()
+            .add_attributes(vec![("action", "sync"), ("reserves", &reserves.join(", "))]),
+    )
+}
+
+/// Lends reserves to the sender for the length of `callback_msg`, the reply checks that the
+/// balances came back with the fee. Native loans are sent in full, so the Terra tax on the
+/// transfer comes out of the pair and is owed back on top of the fee.
+pub fn flash_loan(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    assets: Vec<Asset>,
+    callback_msg: Binary,
+) -> Result<Response, ContractError> {
+    assert_not_paused(deps.storage, &env)?;
+    assert_no_flash_loan(deps.storage)?;
+
+    let pair_info: PairInfoRaw = PAIR_INFO.load(deps.storage)?;
+    let config: Config = CONFIG.load(deps.storage)?;
+    let pools: Vec<Asset> = load_pools(deps.as_ref(), &pair_info)?;
+
+    let amounts = get_deposits(&pools, &assets)?;
+    if amounts.iter().all(|amount| amount.is_zero()) {
+        return Err(ContractError::InvalidZeroAmount {});
+    }
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    let mut fees: Vec<Uint128> = vec![];
+    for (pool, amount) in pools.iter().zip(amounts) {
+        if amount > pool.amount {
+            return Err(ContractError::FlashLoanTooLarge {
+                asset: pool.info.to_string(),
+                amount,
+                reserve: pool.amount,
+            });
+        }
+
+        fees.push(ceil_fee(amount, config.flash_loan_fee)?);
+        if !amount.is_zero() {
+            messages.push(match &pool.info {
+                AssetInfo::NativeToken { denom } => CosmosMsg::Bank(BankMsg::Send {
+                    to_address: info.sender.to_string(),
+                    amount: vec![Coin {
+                        denom: denom.to_string(),
+                        amount,
+                    }],
+                }),
+                AssetInfo::Token { .. } => Asset {
+                    info: pool.info.clone(),
+                    amount,
+                }
+                .into_msg(&deps.querier, info.sender.clone())?,
+            });
+        }
+    }
+
+    let balances: Vec<Uint128> = pair_info
+        .query_pools(&deps.querier, deps.api, env.contract.address)?
+        .iter()
+        .map(|pool| pool.amount)
+        .collect();
+    FLASH_LOAN.save(
+        deps.storage,
+        &FlashLoan {
+            borrower: info.sender.clone(),
+            balances,
+            fees: fees.clone(),
+        },
+    )?;
+
+    let loan_assets: Vec<String> = assets.iter().map(|asset| asset.to_string()).collect();
+    let fee_assets: Vec<String> = pools
+        .iter()
+        .zip(fees)
+        .map(|(pool, fee)| format!("{}{}", fee, pool.info))
+        .collect();
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_submessage(SubMsg {
+            msg: WasmMsg::Execute {
+                contract_addr: info.sender.to_string(),
+                msg: callback_msg,
+                funds: vec![],
+            }
+            .into(),
+            gas_limit: None,
+            id: FLASH_LOAN_REPLY_ID,
+            reply_on: ReplyOn::Success,
+        })
+        .add_attributes(vec![
+            ("action", "flash_loan"),
+            ("borrower", info.sender.as_str()),
+            ("assets", &loan_assets.join(", ")),
+            ("fees", &fee_assets.join(", ")),
+        ]))
+}
+
+/// Fails the whole flash loan unless every balance is back with the fee on top. The fee is
+/// split between the reserves and the admin fees like the swap fee, anything paid beyond it
+/// is left as surplus.
+fn repay_flash_loan(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let flash_loan: FlashLoan = FLASH_LOAN.load(deps.storage)?;
+    let pair_info: PairInfoRaw = load_pair_info(deps.storage, &env)?;
+
+    let balances = pair_info.query_pools(&deps.querier, deps.api, env.contract.address.clone())?;
+    for ((balance, balance_before), fee) in balances
+        .iter()
+        .zip(flash_loan.balances.iter())
+        .zip(flash_loan.fees.iter())
+    {
+        let expected = balance_before.checked_add(*fee)?;
+        if balance.amount < expected {
+            return Err(ContractError::FlashLoanNotRepaid {
+                asset: balance.info.to_string(),
+                expected,
+                amount: balance.amount,
+            });
+        }
+    }
+    FLASH_LOAN.remove(deps.storage);
+
+    let curve = get_curve(deps.as_ref(), &pair_info, &CONFIG.load(deps.storage)?)?;
+    let pools: Vec<Asset> = load_pools(deps.as_ref(), &pair_info)?;
+    update_price_cumulatives(deps.storage, &env, &curve, &pools)?;
+
+    let admin_fees = flash_loan
+        .fees
+        .iter()
+        .map(|fee| curve.admin_fee(*fee))
+        .collect::<Result<Vec<Uint128>, ContractError>>()?;
+    let lp_fees = flash_loan
+        .fees
+        .iter()
+        .zip(admin_fees.iter())
+        .map(|(fee, admin_fee)| fee.checked_sub(*admin_fee))
+        .collect::<Result<Vec<Uint128>, _>>()?;
+    add_admin_fees(deps.storage, &admin_fees)?;
+    update_reserves(deps.storage, &lp_fees, &vec![Uint128::zero(); pools.len()])?;
+
+    let fee_assets: Vec<String> = pools
+        .iter()
+        .zip(flash_loan.fees)
+        .map(|(pool, fee)| format!("{}{}", fee, pool.info))
+        .collect();
+    Ok(Response::new().add_attributes(vec![
+        ("action", "repay_flash_loan"),
+        ("borrower", flash_loan.borrower.as_str()),
+        ("fees", &fee_assets.join(", ")),
+    ]))
+}
+
+pub fn update_flash_loan_fee(
+    deps: DepsMut,
+    info: MessageInfo,
+    flash_loan_fee: Uint128,
+) -> Result<Response, ContractError> {
+    let mut config = assert_owner(deps.storage, &info.sender)?;
+
+    if flash_loan_fee.u128() > MAX_FEE {
+        return Err(ContractError::InvalidFee {});
+    }
+
+    config.flash_loan_fee = flash_loan_fee;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "update_flash_loan_fee"),
+        ("flash_loan_fee", &flash_loan_fee.to_string()),
+    ]))
+}
+
+pub fn update_fee_multiplier(
+    deps: DepsMut,
+    info: MessageInfo,
+    fee_multiplier: Uint128,
+) -> Result<Response, ContractError> {
+    let mut config = assert_owner(deps.storage, &info.sender)?;
+    assert_swap_fee(PAIR_INFO.load(deps.storage)?.fee, fee_multiplier)?;
+
+    config.fee_multiplier = fee_multiplier;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "update_fee_multiplier"),
+        ("fee_multiplier", &fee_multiplier.to_string()),
+    ]))
+}
+
+/// The dynamic fee tops out at the fee times the multiplier, which has to stay within `MAX_FEE`
+fn assert_swap_fee(fee: Uint128, fee_multiplier: Uint128) -> Result<(), ContractError> {
+    let max_fee = if fee_multiplier > Uint128::from(FEE_DENOMINATOR) {
+        fee.multiply_ratio(fee_multiplier, FEE_DENOMINATOR)
+    } else {
+        fee
+    };
+    if max_fee.u128() > MAX_FEE || fee_multiplier.u128() > MAX_FEE_MULTIPLIER {
+        return Err(ContractError::InvalidFee {});
+    }
+
+    Ok(())
+}
+
+pub fn propose_new_owner(
+    deps: DepsMut,
+    info: MessageInfo,
+    owner: String,
+) -> Result<Response, ContractError> {
+    let mut config = assert_owner(deps.storage, &info.sender)?;
+
+    let pending_owner = deps.api.addr_validate(&owner)?;
+    config.pending_owner = Some(pending_owner.clone());
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "propose_new_owner"),
+        ("pending_owner", pending_owner.as_str()),
+    ]))
+}
+
+pub fn accept_ownership(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    let mut config: Config = CONFIG.load(deps.storage)?;
+    if config.pending_owner.as_ref() != Some(&info.sender) {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    config.owner = info.sender;
+    config.pending_owner = None;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::new().add_attributes(vec![
+        ("action", "accept_ownership"),
+        ("owner", config.owner.as_str()),
+    ]))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> Result<Binary, ContractError> {
+    match msg {
+        QueryMsg::Pair {} => Ok(to_binary(&query_pair_info(deps, env)?)?),
+        QueryMsg::Pool {} => Ok(to_binary(&query_pool(deps)?)?),
+        QueryMsg::Simulation {
+            offer_asset,
+            ask_asset_info,
+        } => Ok(to_binary(&query_simulation(
+            deps,
+            env,
+            offer_asset,
+            ask_asset_info,
+        )?)?),
+        QueryMsg::ReverseSimulation {
+            offer_asset_info,
+            ask_asset,
+        } => Ok(to_binary(&query_reverse_simulation(
+            deps,
+            env,
+            offer_asset_info,
+            ask_asset,
+        )?)?),
+        QueryMsg::SimulateProvideLiquidity { assets } => Ok(to_binary(
+            &query_simulate_provide_liquidity(deps, env, assets)?,
+        )?),
+        QueryMsg::SimulateWithdrawLiquidity { share } => {
+            Ok(to_binary(&query_simulate_withdraw_liquidity(deps, share)?)?)
+        }
+        QueryMsg::SimulateWithdrawSingleLiquidity { share, asset_info } => Ok(to_binary(
+            &query_simulate_withdraw_single_liquidity(deps, env, share, asset_info)?,
+        )?),
+        QueryMsg::Amp {} => Ok(to_binary(&query_amp(deps, env)?)?),
+        QueryMsg::Config {} => Ok(to_binary(&query_config(deps, env)?)?),
+        QueryMsg::AdminFees {} => Ok(to_binary(&query_admin_fees(deps)?)?),
+        QueryMsg::VirtualPrice {} => Ok(to_binary(&query_virtual_price(deps, env)?)?),
+        QueryMsg::CumulativePrices {} => Ok(to_binary(&query_cumulative_prices(deps, env)?)?),
+        QueryMsg::Observe { seconds_ago } => {
+            Ok(to_binary(&query_observe(deps, env, seconds_ago)?)?)
+        }
+        QueryMsg::SpotPrice {
+            offer_asset_info,
+            ask_asset_info,
+        } => Ok(to_binary(&query_spot_price(
+            deps,
+            env,
+            offer_asset_info,
+            ask_asset_info,
+        )?)?),
+        QueryMsg::PriceImpact {
+            offer_asset,
+            ask_asset_info,
+        } => Ok(to_binary(&query_price_impact(
+            deps,
+            env,
+            offer_asset,
+            ask_asset_info,
+        )?)?),
+        QueryMsg::TradeToPrice {
+            offer_asset_info,
+            ask_asset_info,
+            target_price,
+        } => Ok(to_binary(&query_trade_to_price(
+            deps,
+            env,
+            offer_asset_info,
+            ask_asset_info,
+            target_price,
+        )?)?),
+    }
+}
+
+pub fn query_pair_info(deps: Deps, env: Env) -> Result<PairInfo, ContractError> {
+    let pair_info: PairInfoRaw = load_pair_info(deps.storage, &env)?;
+    let pair_info = pair_info.to_normal(deps.api)?;
+
+    Ok(pair_info)
+}
+
+pub fn query_amp(deps: Deps, env: Env) -> Result<AmpResponse, ContractError> {
+    let ramp = AMP_RAMP.load(deps.storage)?;
+
+    Ok(AmpResponse {
+        amp: ramp.current_amp(env.block.time.seconds()),
+        future_amp: ramp.future_amp,
+        future_time: ramp.future_time,
+    })
+}
+
+pub fn query_config(deps: Deps, env: Env) -> Result<ConfigResponse, ContractError> {
+    let config: Config = CONFIG.load(deps.storage)?;
+    let pair_info: PairInfoRaw = PAIR_INFO.load(deps.storage)?;
+    let ramp = AMP_RAMP.load(deps.storage)?;
+
+    let mut rate_providers = vec![];
+    let stored_rate_providers = RATE_PROVIDERS.may_load(deps.storage)?.unwrap_or_default();
+    for (asset_info, rate_provider) in pair_info.asset_infos.iter().zip(stored_rate_providers) {
+        if let Some(rate_provider) = rate_provider {
+            rate_providers.push(RateProvider {
+                asset_info: asset_info.to_normal(deps.api)?,
+                contract_addr: rate_provider.to_string(),
+            });
+        }
+    }
+
+    Ok(ConfigResponse {
+        owner: config.owner.to_string(),
+        pending_owner: config.pending_owner.as_ref().map(|addr| addr.to_string()),
+        guardian: config.guardian.as_ref().map(|addr| addr.to_string()),
+        fee: pair_info.fee,
+        admin_fee: config.admin_fee,
+        fee_collector: config.fee_collector.as_ref().map(|addr| addr.to_string()),
+        flash_loan_fee: config.flash_loan_fee,
+        fee_multiplier: config.fee_multiplier,
+        amp: ramp.current_amp(env.block.time.seconds()),
+        future_amp: ramp.future_amp,
+        future_time: ramp.future_time,
+        paused: config.is_paused(env.block.height),
+        pause_expiry_height: config.pause_expiry_height,
+        base_pool: BASE_POOL
+            .may_load(deps.storage)?
+            .map(|base_pool| base_pool.contract_addr.to_string()),
+        rate_providers,
+    })
+}
+
+pub fn query_admin_fees(deps: Deps) -> Result<AdminFeesResponse, ContractError> {
+    let pair_info: PairInfoRaw = PAIR_INFO.load(deps.storage)?;
+    let admin_fees = ADMIN_FEES.load(deps.storage)?;
+
+    let assets = pair_info
+        .asset_infos
+        .iter()
+        .zip(admin_fees)
+        .map(|(asset_info, amount)| {
+            Ok(Asset {
+                info: asset_info.to_normal(deps.api)?,
+                amount,
+            })
+        })
+        .collect::<StdResult<Vec<Asset>>>()?;
+
+    Ok(AdminFeesResponse { assets })
+}
+
+pub fn query_virtual_price(deps: Deps, env: Env) -> Result<VirtualPriceResponse, ContractError> {
+    let pair_info: PairInfoRaw = load_pair_info(deps.storage, &env)?;
+
+    let pools: Vec<Asset> = load_pools(deps, &pair_info)?;
+    let total_share: Uint128 = query_supply(
+        &deps.querier,
+        deps.api.addr_humanize(&pair_info.liquidity_token)?,
+    )?;
+
+    let balances: Vec<Uint128> = pools.iter().map(|a| a.amount).collect();
+    let virtual_price = get_curve(deps, &pair_info, &CONFIG.load(deps.storage)?)?
+        .get_virtual_price(&balances, total_share, VIRTUAL_PRICE_PRECISION)?;
+
+    Ok(VirtualPriceResponse {
+        virtual_price,
+        precision: VIRTUAL_PRICE_PRECISION,
+    })
+}
+
+pub fn query_cumulative_prices(
+    deps: Deps,
+    env: Env,
+) -> Result<CumulativePricesResponse, ContractError> {
+    let pair_info: PairInfoRaw = load_pair_info(deps.storage, &env)?;
+
+    observe(deps, &env, &pair_info, 0)
+}
+
+pub fn query_observe(
+    deps: Deps,
+    env: Env,
+    seconds_ago: Vec<u64>,
+) -> Result<ObserveResponse, ContractError> {
+    let pair_info: PairInfoRaw = load_pair_info(deps.storage, &env)?;
+    let observations = seconds_ago
+        .into_iter()
+        .map(|seconds_ago| observe(deps, &env, &pair_info, seconds_ago))
+        .collect::<Result<Vec<CumulativePricesResponse>, ContractError>>()?;
+
+    Ok(ObserveResponse { observations })
 }
 
 pub fn query_pool(deps: Deps) -> Result<PoolResponse, ContractError> {
     let pair_info: PairInfoRaw = PAIR_INFO.load(deps.storage)?;
-    let contract_addr = deps.api.addr_humanize(&pair_info.contract_addr)?;
-    let assets: Vec<Asset> = pair_info.query_pools(&deps.querier, deps.api, contract_addr)?;
+    let assets: Vec<Asset> = load_pools(deps, &pair_info)?;
     let total_share: Uint128 = query_supply(
         &deps.querier,
         deps.api.addr_humanize(&pair_info.liquidity_token)?,
@@ -594,57 +2082,752 @@ pub fn query_pool(deps: Deps) -> Result<PoolResponse, ContractError> {
     Ok(resp)
 }
 
-// pub fn query_simulation(
-//     deps: Deps,
-//     offer_asset: Asset,
-// ) -> Result<SimulationResponse, ContractError> {
-//     let pair_info: PairInfoRaw = PAIR_INFO.load(deps.storage)?;
-
-//     let contract_addr = deps.api.addr_humanize(&pair_info.contract_addr)?;
-//     let pools: Vec<Asset> = pair_info.query_pools(&deps.querier, deps.api, contract_addr)?;
-
-//     let offer_pool: Asset;
-//     let ask_pool: Asset;
-//     if offer_asset.info.equal(&pools[0].info) {
-//         offer_pool = pools[0].clone();
-//         ask_pool = pools[1].clone();
-//     } else if offer_asset.info.equal(&pools[1].info) {
-//         offer_pool = pools[1].clone();
-//         ask_pool = pools[0].clone();
-//     } else {
-//         return Err(ContractError::AssetMismatch {});
-//     }
-
-//     let return_amount = compute_swap(offer_pool.amount, ask_pool.amount, offer_asset.amount);
-
-//     Ok(SimulationResponse { return_amount })
-// }
-
-// pub fn query_reverse_simulation(
-//     deps: Deps,
-//     ask_asset: Asset,
-// ) -> Result<ReverseSimulationResponse, ContractError> {
-//     let pair_info: PairInfoRaw = PAIR_INFO.load(deps.storage)?;
-
-//     let contract_addr = deps.api.addr_humanize(&pair_info.contract_addr)?;
-//     let pools: [Asset; 2] = pair_info.query_pools(&deps.querier, deps.api, contract_addr)?;
-
-//     let offer_pool: Asset;
-//     let ask_pool: Asset;
-//     if ask_asset.info.equal(&pools[0].info) {
-//         ask_pool = pools[0].clone();
-//         offer_pool = pools[1].clone();
-//     } else if ask_asset.info.equal(&pools[1].info) {
-//         ask_pool = pools[1].clone();
-//         offer_pool = pools[0].clone();
-//     } else {
-//         return Err(ContractError::AssetMismatch {});
-//     }
-
-//     let offer_amount = compute_swap(offer_pool.amount, ask_pool.amount, ask_asset.amount);
-
-//     Ok(ReverseSimulationResponse { offer_amount })
-// }
+pub fn query_simulation(
+    deps: Deps,
+    env: Env,
+    offer_asset: Asset,
+    ask_asset_info: AssetInfo,
+) -> Result<SimulationResponse, ContractError> {
+    let pair_info: PairInfoRaw = load_pair_info(deps.storage, &env)?;
+
+    let pools: Vec<Asset> = load_pools(deps, &pair_info)?;
+
+    let i = find_pool_index(&pools, &offer_asset.info)?;
+    let j = find_pool_index(&pools, &ask_asset_info)?;
+    if i == j {
+        return Err(ContractError::AssetMismatch {});
+    }
+
+    let balances: Vec<Uint128> = pools.iter().map(|a| a.amount).collect();
+    let curve = get_curve(deps, &pair_info, &CONFIG.load(deps.storage)?)?;
+    let (return_amount, commission_amount, fee) =
+        curve.exchange(i, j, offer_asset.amount, &balances)?;
+
+    // the pool is pegged at the asset rates, so anything below the offer value is price impact
+    let spread_amount = curve
+        .value_at_rates(i, j, offer_asset.amount)?
+        .saturating_sub(return_amount + commission_amount);
+
+    Ok(SimulationResponse {
+        return_amount,
+        spread_amount,
+        commission_amount,
+        fee,
+        rates: curve.rates,
+    })
+}
+
+pub fn query_reverse_simulation(
+    deps: Deps,
+    env: Env,
+    offer_asset_info: AssetInfo,
+    ask_asset: Asset,
+) -> Result<ReverseSimulationResponse, ContractError> {
+    let pair_info: PairInfoRaw = load_pair_info(deps.storage, &env)?;
+
+    let pools: Vec<Asset> = load_pools(deps, &pair_info)?;
+
+    let i = find_pool_index(&pools, &offer_asset_info)?;
+    let j = find_pool_index(&pools, &ask_asset.info)?;
+    if i == j {
+        return Err(ContractError::AssetMismatch {});
+    }
+
+    let balances: Vec<Uint128> = pools.iter().map(|a| a.amount).collect();
+    let curve = get_curve(deps, &pair_info, &CONFIG.load(deps.storage)?)?;
+    let (offer_amount, commission_amount, fee) =
+        curve.reverse_exchange(j, i, ask_asset.amount, &balances)?;
+
+    // the pool is pegged at the asset rates, so anything above the ask value is price impact
+    let spread_amount = offer_amount.saturating_sub(curve.value_at_rates(
+        j,
+        i,
+        ask_asset.amount + commission_amount,
+    )?);
+
+    Ok(ReverseSimulationResponse {
+        offer_amount,
+        spread_amount,
+        commission_amount,
+        fee,
+        rates: curve.rates,
+    })
+}
+
+pub fn query_simulate_provide_liquidity(
+    deps: Deps,
+    env: Env,
+    assets: Vec<Asset>,
+) -> Result<SimulateProvideLiquidityResponse, ContractError> {
+    let pair_info: PairInfoRaw = load_pair_info(deps.storage, &env)?;
+
+    let pools: Vec<Asset> = load_pools(deps, &pair_info)?;
+    let total_share: Uint128 = query_supply(
+        &deps.querier,
+        deps.api.addr_humanize(&pair_info.liquidity_token)?,
+    )?;
+
+    let deposits = get_deposits(&pools, &assets)?;
+    let curve = get_curve(deps, &pair_info, &CONFIG.load(deps.storage)?)?;
+    let (share, _) = compute_mint_amount(&curve, &pools, &deposits, total_share)?;
+
+    Ok(SimulateProvideLiquidityResponse { share })
+}
+
+pub fn query_simulate_withdraw_liquidity(
+    deps: Deps,
+    share: Uint128,
+) -> Result<SimulateWithdrawLiquidityResponse, ContractError> {
+    let pair_info: PairInfoRaw = PAIR_INFO.load(deps.storage)?;
+
+    let pools: Vec<Asset> = load_pools(deps, &pair_info)?;
+    let total_share: Uint128 = query_supply(
+        &deps.querier,
+        deps.api.addr_humanize(&pair_info.liquidity_token)?,
+    )?;
+
+    let assets = compute_withdraw_amounts(&pools, share, total_share)?;
+
+    Ok(SimulateWithdrawLiquidityResponse { assets })
+}
+
+pub fn query_simulate_withdraw_single_liquidity(
+    deps: Deps,
+    env: Env,
+    share: Uint128,
+    asset_info: AssetInfo,
+) -> Result<SimulateWithdrawSingleLiquidityResponse, ContractError> {
+    let pair_info: PairInfoRaw = load_pair_info(deps.storage, &env)?;
+
+    let pools: Vec<Asset> = load_pools(deps, &pair_info)?;
+    let total_share: Uint128 = query_supply(
+        &deps.querier,
+        deps.api.addr_humanize(&pair_info.liquidity_token)?,
+    )?;
+
+    let i = find_pool_index(&pools, &asset_info)?;
+    let curve = get_curve(deps, &pair_info, &CONFIG.load(deps.storage)?)?;
+    let (amount, _) = compute_withdraw_single_amount(&curve, &pools, i, share, total_share)?;
+
+    Ok(SimulateWithdrawSingleLiquidityResponse {
+        asset: Asset {
+            info: asset_info,
+            amount,
+        },
+    })
+}
+
+fn assert_owner(storage: &dyn Storage, sender: &Addr) -> Result<Config, ContractError> {
+    let config: Config = CONFIG.load(storage)?;
+    if *sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    Ok(config)
+}
+
+fn assert_owner_or_guardian(storage: &dyn Storage, sender: &Addr) -> Result<Config, ContractError> {
+    let config: Config = CONFIG.load(storage)?;
+    if *sender != config.owner && config.guardian.as_ref() != Some(sender) {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    Ok(config)
+}
+
+/// Balanced withdrawals are left out so LPs can always exit a paused pair
+fn assert_not_paused(storage: &dyn Storage, env: &Env) -> Result<(), ContractError> {
+    if CONFIG.load(storage)?.is_paused(env.block.height) {
+        return Err(ContractError::Paused {});
+    }
+
+    Ok(())
+}
+
+fn assert_deadline(env: &Env, deadline: Option<Expiration>) -> Result<(), ContractError> {
+    if let Some(deadline) = deadline {
+        if deadline.is_expired(&env.block) {
+            return Err(ContractError::DeadlineExpired {});
+        }
+    }
+
+    Ok(())
+}
+
+/// Swaps, liquidity changes and payouts would move the balances a flash loan is checked against
+fn assert_no_flash_loan(storage: &dyn Storage) -> Result<(), ContractError> {
+    if FLASH_LOAN.may_load(storage)?.is_some() {
+        return Err(ContractError::FlashLoanInProgress {});
+    }
+
+    Ok(())
+}
+
+/// Loads the pair info with the amplification interpolated at the current block time
+fn load_pair_info(storage: &dyn Storage, env: &Env) -> StdResult<PairInfoRaw> {
+    let mut pair_info = PAIR_INFO.load(storage)?;
+    pair_info.amplification = AMP_RAMP
+        .load(storage)?
+        .current_amp(env.block.time.seconds());
+
+    Ok(pair_info)
+}
+
+/// Decimals of every asset, those of native assets come from `native_decimals`
+fn query_decimals(
+    deps: Deps,
+    asset_infos: &[AssetInfo],
+    native_decimals: &[NativeDecimals],
+) -> StdResult<Vec<u8>> {
+    let mut decimals = Vec::with_capacity(asset_infos.len());
+    for asset_info in asset_infos.iter() {
+        decimals.push(match asset_info {
+            AssetInfo::Token { contract_addr } => {
+                query_token_decimals(&deps.querier, deps.api.addr_validate(contract_addr)?)?
+            }
+            AssetInfo::NativeToken { denom } => native_decimals
+                .iter()
+                .find(|native| &native.denom == denom)
+                .map(|native| native.decimals)
+                .ok_or_else(|| {
+                    StdError::generic_err(format!("Missing decimals for native denom {}", denom))
+                })?,
+        });
+    }
+
+    Ok(decimals)
+}
+
+/// Scales every asset up to the largest decimals in the pair
+fn compute_precision_multipliers(decimals: &[u8]) -> StdResult<Vec<Uint128>> {
+    let max_decimals = decimals.iter().max().cloned().unwrap_or_default();
+    decimals
+        .iter()
+        .map(|decimals| {
+            10u128
+                .checked_pow((max_decimals - decimals) as u32)
+                .map(Uint128::from)
+                .ok_or_else(|| StdError::generic_err("Asset decimals are too far apart"))
+        })
+        .collect()
+}
+
+/// LP token name and symbol made of the asset symbols, or the denoms of native assets, e.g.
+/// "uusd-ANC-LP" and "UUSD-ANC-LP". The symbol keeps the letters of each asset only and
+/// shortens them evenly to fit. Takes at least two assets.
+fn query_lp_token_name(deps: Deps, asset_infos: &[AssetInfo]) -> StdResult<(String, String)> {
+    let labels = asset_infos
+        .iter()
+        .map(|asset_info| match asset_info {
+            AssetInfo::Token { contract_addr } => {
+                query_token_symbol(&deps.querier, deps.api.addr_validate(contract_addr)?)
+            }
+            AssetInfo::NativeToken { denom } => Ok(denom.clone()),
+        })
+        .collect::<StdResult<Vec<String>>>()?;
+
+    let mut name = labels.join("-");
+    while name.len() > MAX_LP_TOKEN_NAME_LEN - LP_TOKEN_SUFFIX.len() {
+        name.pop();
+    }
+    name.push_str(LP_TOKEN_SUFFIX);
+
+    let n_coins = labels.len();
+    let label_len =
+        (MAX_LP_TOKEN_SYMBOL_LEN - LP_TOKEN_SUFFIX.len()).saturating_sub(n_coins - 1) / n_coins;
+    let mut symbol = labels
+        .iter()
+        .map(|label| {
+            let mut letters = label
+                .chars()
+                .filter(|c| c.is_ascii_alphabetic())
+                .collect::<String>();
+            if letters.is_empty() {
+                letters = LP_TOKEN_FALLBACK_LABEL.to_string();
+            }
+            letters
+                .chars()
+                .take(label_len)
+                .collect::<String>()
+                .to_ascii_uppercase()
+        })
+        .collect::<Vec<String>>()
+        .join("-");
+    symbol.push_str(LP_TOKEN_SUFFIX);
+
+    Ok((name, symbol))
+}
+
+/// Pool reserves as accounted by the pair, the admin fees and any surplus are not included
+fn load_pools(deps: Deps, pair_info: &PairInfoRaw) -> StdResult<Vec<Asset>> {
+    let reserves = RESERVES.load(deps.storage)?;
+    pair_info
+        .asset_infos
+        .iter()
+        .zip(reserves)
+        .map(|(info, amount)| {
+            Ok(Asset {
+                info: info.to_normal(deps.api)?,
+                amount,
+            })
+        })
+        .collect()
+}
+
+/// Pool balances beyond the reserves and the admin fees, e.g. direct transfers to the pair
+fn query_surplus(
+    deps: Deps,
+    pair_info: &PairInfoRaw,
+    contract_addr: Addr,
+) -> StdResult<Vec<Asset>> {
+    let reserves = RESERVES.load(deps.storage)?;
+    let admin_fees = ADMIN_FEES.load(deps.storage)?;
+    pair_info
+        .query_pools(&deps.querier, deps.api, contract_addr)?
+        .into_iter()
+        .zip(reserves.into_iter().zip(admin_fees))
+        .map(|(mut pool, (reserve, admin_fee))| {
+            pool.amount = pool.amount.saturating_sub(reserve.checked_add(admin_fee)?);
+            Ok(pool)
+        })
+        .collect()
+}
+
+/// Moves the reserves by the amounts paid in and out, in the order of the pair assets
+fn update_reserves(
+    storage: &mut dyn Storage,
+    inflows: &[Uint128],
+    outflows: &[Uint128],
+) -> StdResult<()> {
+    RESERVES.update(storage, |reserves| -> StdResult<_> {
+        reserves
+            .iter()
+            .zip(inflows.iter().zip(outflows.iter()))
+            .map(|(reserve, (inflow, outflow))| {
+                Ok(reserve.checked_add(*inflow)?.checked_sub(*outflow)?)
+            })
+            .collect()
+    })?;
+
+    Ok(())
+}
+
+fn add_admin_fees(storage: &mut dyn Storage, amounts: &[Uint128]) -> StdResult<()> {
+    ADMIN_FEES.update(storage, |admin_fees| -> StdResult<_> {
+        admin_fees
+            .iter()
+            .zip(amounts.iter())
+            .map(|(admin_fee, amount)| Ok(admin_fee.checked_add(*amount)?))
+            .collect()
+    })?;
+
+    Ok(())
+}
+
+/// Marginal prices of every ordered asset pair, zero while the pool is empty
+fn compute_prices(curve: &Curve, pools: &[Asset]) -> Result<Vec<Uint128>, ContractError> {
+    let n_coins = pools.len();
+    if pools.iter().any(|pool| pool.amount.is_zero()) {
+        return Ok(vec![Uint128::zero(); n_coins * (n_coins - 1)]);
+    }
+
+    let balances: Vec<Uint128> = pools.iter().map(|a| a.amount).collect();
+    let mut prices = vec![];
+    for i in 0..n_coins {
+        for j in (0..n_coins).filter(|j| *j != i) {
+            prices.push(curve.marginal_price(i, j, &balances, PRICE_PRECISION)?);
+        }
+    }
+
+    Ok(prices)
+}
+
+/// Accumulates the prices held since the latest observation, on the first action of a block
+fn update_price_cumulatives(
+    storage: &mut dyn Storage,
+    env: &Env,
+    curve: &Curve,
+    pools: &[Asset],
+) -> Result<(), ContractError> {
+    let latest = load_latest_observation(storage)?;
+    let timestamp = env.block.time.seconds();
+    if timestamp <= latest.timestamp {
+        return Ok(());
+    }
+
+    let prices = compute_prices(curve, pools)?;
+    store_observation(storage, &latest.accumulate(timestamp, &prices))?;
+
+    Ok(())
+}
+
+/// Price accumulators `seconds_ago` before the current block, past the latest observation
+/// they are extrapolated with the current prices
+fn observe(
+    deps: Deps,
+    env: &Env,
+    pair_info: &PairInfoRaw,
+    seconds_ago: u64,
+) -> Result<CumulativePricesResponse, ContractError> {
+    let timestamp = env
+        .block
+        .time
+        .seconds()
+        .checked_sub(seconds_ago)
+        .ok_or(ContractError::ObservationUnavailable { seconds_ago })?;
+    let pools: Vec<Asset> = load_pools(deps, pair_info)?;
+
+    let latest = load_latest_observation(deps.storage)?;
+    let observation = if timestamp >= latest.timestamp {
+        let curve = get_curve(deps, pair_info, &CONFIG.load(deps.storage)?)?;
+        latest.accumulate(timestamp, &compute_prices(&curve, &pools)?)
+    } else {
+        load_observation_at(deps.storage, timestamp)?
+            .ok_or(ContractError::ObservationUnavailable { seconds_ago })?
+    };
+
+    let asset_pairs = pools.iter().enumerate().flat_map(|(i, offer_pool)| {
+        pools
+            .iter()
+            .enumerate()
+            .filter(move |(j, _)| *j != i)
+            .map(move |(_, ask_pool)| (offer_pool, ask_pool))
+    });
+    let price_cumulatives = asset_pairs
+        .zip(observation.price_cumulatives)
+        .map(
+            |((offer_pool, ask_pool), price_cumulative)| PriceCumulative {
+                offer_asset_info: offer_pool.info.clone(),
+                ask_asset_info: ask_pool.info.clone(),
+                price_cumulative,
+            },
+        )
+        .collect();
+
+    Ok(CumulativePricesResponse {
+        timestamp,
+        price_cumulatives,
+        precision: PRICE_PRECISION,
+    })
+}
+
+pub fn query_spot_price(
+    deps: Deps,
+    env: Env,
+    offer_asset_info: AssetInfo,
+    ask_asset_info: AssetInfo,
+) -> Result<SpotPriceResponse, ContractError> {
+    let pair_info: PairInfoRaw = load_pair_info(deps.storage, &env)?;
+    let pools: Vec<Asset> = load_pools(deps, &pair_info)?;
+
+    let i = find_pool_index(&pools, &offer_asset_info)?;
+    let j = find_pool_index(&pools, &ask_asset_info)?;
+    if i == j {
+        return Err(ContractError::AssetMismatch {});
+    }
+
+    let balances: Vec<Uint128> = pools.iter().map(|a| a.amount).collect();
+    let curve = get_curve(deps, &pair_info, &CONFIG.load(deps.storage)?)?;
+
+    Ok(SpotPriceResponse {
+        price: curve.marginal_price(i, j, &balances, PRICE_PRECISION)?,
+        precision: PRICE_PRECISION,
+    })
+}
+
+pub fn query_price_impact(
+    deps: Deps,
+    env: Env,
+    offer_asset: Asset,
+    ask_asset_info: AssetInfo,
+) -> Result<PriceImpactResponse, ContractError> {
+    if offer_asset.amount.is_zero() {
+        return Err(ContractError::InvalidZeroAmount {});
+    }
+
+    let pair_info: PairInfoRaw = load_pair_info(deps.storage, &env)?;
+    let pools: Vec<Asset> = load_pools(deps, &pair_info)?;
+
+    let i = find_pool_index(&pools, &offer_asset.info)?;
+    let j = find_pool_index(&pools, &ask_asset_info)?;
+    if i == j {
+        return Err(ContractError::AssetMismatch {});
+    }
+
+    let balances: Vec<Uint128> = pools.iter().map(|a| a.amount).collect();
+    let curve = get_curve(deps, &pair_info, &CONFIG.load(deps.storage)?)?;
+    let spot_price = curve.marginal_price(i, j, &balances, PRICE_PRECISION)?;
+    let (return_amount, commission_amount, _) =
+        curve.exchange(i, j, offer_asset.amount, &balances)?;
+
+    // the impact compares the price before fees with the spot price, which is before fees too
+    let precision = Uint128::from(10u128.pow(PRICE_PRECISION as u32));
+    let price_before_fee = precision.multiply_ratio(
+        return_amount.checked_add(commission_amount)?,
+        offer_asset.amount,
+    );
+
+    Ok(PriceImpactResponse {
+        spot_price,
+        effective_price: precision.multiply_ratio(return_amount, offer_asset.amount),
+        price_impact: Decimal::from_ratio(spot_price.saturating_sub(price_before_fee), spot_price),
+        precision: PRICE_PRECISION,
+    })
+}
+
+pub fn query_trade_to_price(
+    deps: Deps,
+    env: Env,
+    offer_asset_info: AssetInfo,
+    ask_asset_info: AssetInfo,
+    target_price: Uint128,
+) -> Result<TradeToPriceResponse, ContractError> {
+    if target_price.is_zero() {
+        return Err(ContractError::InvalidZeroAmount {});
+    }
+
+    let pair_info: PairInfoRaw = load_pair_info(deps.storage, &env)?;
+    let pools: Vec<Asset> = load_pools(deps, &pair_info)?;
+
+    let i = find_pool_index(&pools, &offer_asset_info)?;
+    let j = find_pool_index(&pools, &ask_asset_info)?;
+    if i == j {
+        return Err(ContractError::AssetMismatch {});
+    }
+
+    let balances: Vec<Uint128> = pools.iter().map(|a| a.amount).collect();
+    let curve = get_curve(deps, &pair_info, &CONFIG.load(deps.storage)?)?;
+    let offer_amount = curve.trade_to_price(i, j, &balances, target_price, PRICE_PRECISION)?;
+    let (return_amount, commission_amount) = if offer_amount.is_zero() {
+        (Uint128::zero(), Uint128::zero())
+    } else {
+        let (return_amount, commission_amount, _) =
+            curve.exchange(i, j, offer_amount, &balances)?;
+        (return_amount, commission_amount)
+    };
+
+    Ok(TradeToPriceResponse {
+        offer_amount,
+        return_amount,
+        commission_amount,
+    })
+}
+
+fn get_curve(deps: Deps, pair_info: &PairInfoRaw, config: &Config) -> StdResult<Curve> {
+    Ok(Curve {
+        amp: pair_info.amplification,
+        fee_numerator: pair_info.fee,
+        admin_fee_numerator: config.admin_fee,
+        precision_multipliers: pair_info.precision_multipliers.clone(),
+        rates: query_rates(deps, pair_info)?,
+        fee_multiplier: config.fee_multiplier,
+    })
+}
+
+/// Rates of the pair assets as their rate providers report them, the LP leg of a metapool is
+/// worth the base pool virtual price
+fn query_rates(deps: Deps, pair_info: &PairInfoRaw) -> StdResult<Vec<Uint128>> {
+    let unit = Uint128::from(10u128.pow(RATE_PRECISION as u32));
+    let mut rates = vec![unit; pair_info.asset_infos.len()];
+    let rate_providers = RATE_PROVIDERS.may_load(deps.storage)?.unwrap_or_default();
+    for (rate, rate_provider) in rates.iter_mut().zip(rate_providers) {
+        if let Some(rate_provider) = rate_provider {
+            let res = query_rate(&deps.querier, rate_provider.clone())?;
+            *rate = res
+                .rate
+                .multiply_ratio(unit, 10u128.pow(res.precision as u32));
+            if rate.is_zero() {
+                return Err(StdError::generic_err(format!(
+                    "Rate provider {} returned a zero rate",
+                    rate_provider
+                )));
+            }
+        }
+    }
+    if let Some(base_pool) = BASE_POOL.may_load(deps.storage)? {
+        let lp_token_info = AssetInfo::Token {
+            contract_addr: base_pool.liquidity_token.to_string(),
+        };
+        for (rate, asset_info) in rates.iter_mut().zip(pair_info.asset_infos.iter()) {
+            if asset_info.to_normal(deps.api)?.equal(&lp_token_info) {
+                let res = query_base_virtual_price(&deps.querier, base_pool.contract_addr.clone())?;
+                *rate = res
+                    .virtual_price
+                    .multiply_ratio(unit, 10u128.pow(res.precision as u32));
+            }
+        }
+    }
+
+    Ok(rates)
+}
+
+fn find_pool_index(pools: &[Asset], asset_info: &AssetInfo) -> Result<usize, ContractError> {
+    pools
+        .iter()
+        .position(|pool| pool.info.equal(asset_info))
+        .ok_or_else(|| ContractError::UnknownAsset {
+            asset: asset_info.to_string(),
+        })
+}
+
+/// Rates as a comma separated list in the order of the pair assets
+fn format_rates(rates: &[Uint128]) -> String {
+    rates
+        .iter()
+        .map(|rate| rate.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Index of an underlying asset among the pair assets, `None` for a base pool asset
+fn find_underlying_index(
+    pools: &[Asset],
+    base_pool: &BasePool,
+    asset_info: &AssetInfo,
+) -> Result<Option<usize>, ContractError> {
+    if let Ok(index) = find_pool_index(pools, asset_info) {
+        return Ok(Some(index));
+    }
+
+    if base_pool
+        .asset_infos
+        .iter()
+        .any(|base_asset_info| base_asset_info.equal(asset_info))
+    {
+        Ok(None)
+    } else {
+        Err(ContractError::UnknownAsset {
+            asset: asset_info.to_string(),
+        })
+    }
+}
+
+/// Swaps `offer_amount` of asset `i` for asset `j` in the reserves, setting the admin fee
+/// aside. Returns `(return_amount, commission_amount, admin_fee_amount, fee)` where `fee` is the
+/// fee rate the swap was charged, paying the return amount out is up to the caller.
+fn exchange_reserves(
+    storage: &mut dyn Storage,
+    curve: &Curve,
+    pools: &[Asset],
+    i: usize,
+    j: usize,
+    offer_amount: Uint128,
+) -> Result<(Uint128, Uint128, Uint128, Uint128), ContractError> {
+    let balances: Vec<Uint128> = pools.iter().map(|a| a.amount).collect();
+    let (return_amount, commission_amount, fee) = curve.exchange(i, j, offer_amount, &balances)?;
+    let admin_fee_amount = curve.admin_fee(commission_amount)?;
+
+    let mut admin_fees = vec![Uint128::zero(); pools.len()];
+    admin_fees[j] = admin_fee_amount;
+    add_admin_fees(storage, &admin_fees)?;
+
+    let mut inflows = vec![Uint128::zero(); pools.len()];
+    inflows[i] = offer_amount;
+    let mut outflows = admin_fees;
+    outflows[j] = outflows[j].checked_add(return_amount)?;
+    update_reserves(storage, &inflows, &outflows)?;
+
+    Ok((return_amount, commission_amount, admin_fee_amount, fee))
+}
+
+/// Lines `assets` up with the pool assets in any order, counting missing ones as zero
+fn get_deposits(pools: &[Asset], assets: &[Asset]) -> Result<Vec<Uint128>, ContractError> {
+    let mut deposits: Vec<Option<Uint128>> = vec![None; pools.len()];
+    for asset in assets.iter() {
+        let i = find_pool_index(pools, &asset.info)?;
+        if deposits[i].is_some() {
+            return Err(ContractError::DuplicateAsset {
+                asset: asset.info.to_string(),
+            });
+        }
+        deposits[i] = Some(asset.amount);
+    }
+
+    Ok(deposits
+        .into_iter()
+        .map(|deposit| deposit.unwrap_or_else(Uint128::zero))
+        .collect())
+}
+
+fn assert_min_assets(assets: &[Asset], min_assets: &[Asset]) -> Result<(), ContractError> {
+    for min_asset in min_assets.iter() {
+        let asset = &assets[find_pool_index(assets, &min_asset.info)?];
+        if asset.amount < min_asset.amount {
+            return Err(ContractError::MinAssetAssertion {
+                asset: asset.info.to_string(),
+                min_amount: min_asset.amount,
+                amount: asset.amount,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn compute_mint_amount(
+    curve: &Curve,
+    pools: &[Asset],
+    deposits: &[Uint128],
+    total_share: Uint128,
+) -> Result<(Uint128, Vec<Uint128>), ContractError> {
+    // the first deposit sets the price, so it has to bring every asset
+    if total_share.is_zero() && deposits.iter().any(|deposit| deposit.is_zero()) {
+        return Err(ContractError::InvalidZeroAmount {});
+    }
+
+    let mut old_balances = Vec::with_capacity(pools.len());
+    let mut new_balances = Vec::with_capacity(pools.len());
+    for (pool, deposit) in pools.iter().zip(deposits.iter()) {
+        old_balances.push(pool.amount);
+        new_balances.push(pool.amount.checked_add(*deposit)?);
+    }
+
+    let (share, admin_fees) = curve.deposit(&old_balances, &new_balances, total_share)?;
+    if !total_share.is_zero() {
+        return Ok((share, admin_fees));
+    }
+
+    // the first deposit keeps the locked share out of what it mints to the depositor
+    let min_share = Uint128::from(MINIMUM_INITIAL_SHARE);
+    if share < min_share {
+        return Err(ContractError::MinimumLiquidity { min_share, share });
+    }
+
+    Ok((
+        share.checked_sub(Uint128::from(MINIMUM_LIQUIDITY_AMOUNT))?,
+        admin_fees,
+    ))
+}
+
+fn compute_withdraw_amounts(
+    pools: &[Asset],
+    share: Uint128,
+    total_share: Uint128,
+) -> Result<Vec<Asset>, ContractError> {
+    let old_balances: Vec<Uint128> = pools.iter().map(|a| a.amount).collect();
+    let amounts = Curve::remove_balanced_liquidity(&old_balances, share, total_share)?;
+
+    Ok(pools
+        .iter()
+        .zip(amounts)
+        .map(|(pool, amount)| Asset {
+            info: pool.info.clone(),
+            amount,
+        })
+        .collect())
+}
+
+fn compute_withdraw_single_amount(
+    curve: &Curve,
+    pools: &[Asset],
+    i: usize,
+    share: Uint128,
+    total_share: Uint128,
+) -> Result<(Uint128, Uint128), ContractError> {
+    let old_balances: Vec<Uint128> = pools.iter().map(|a| a.amount).collect();
+
+    curve.remove_liquidity_single_token(&old_balances, share, i, total_share)
+}
 
 pub fn amount_of(coins: &[Coin], denom: String) -> Uint128 {
     match coins.iter().find(|x| x.denom == denom) {
@@ -653,22 +2836,65 @@ pub fn amount_of(coins: &[Coin], denom: String) -> Uint128 {
     }
 }
 
-// fn compute_swap(offer_pool: Uint128, ask_pool: Uint128, offer_amount: Uint128) -> Uint128 {
-//     let out_amount = Curve {
-//         amp: AMPLIFICATION,
-//         fee_numerator: FEE_NUMERATOR,
-//     }
-//     .exchange(
-//         0,
-//         1,
-//         offer_amount.u128() as u64,
-//         &[offer_pool.u128(), ask_pool.u128()],
-//     )
-//     .unwrap();
-//     Uint128::from(out_amount)
-// }
-
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn migrate(_deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+pub fn migrate(deps: DepsMut, env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+    // pairs instantiated before any of the state below was added lack it, each item is
+    // seeded as instantiate would have, so migrating a current pair changes nothing
+    let mut pair_info = PAIR_INFO.load(deps.storage)?;
+    let n_coins = pair_info.asset_infos.len();
+    if pair_info.precision_multipliers.is_empty() {
+        let mut asset_infos = vec![];
+        for asset_info in pair_info.asset_infos.iter() {
+            asset_infos.push(asset_info.to_normal(deps.api)?);
+        }
+        pair_info.precision_multipliers = compute_precision_multipliers(&query_decimals(
+            deps.as_ref(),
+            &asset_infos,
+            &msg.native_decimals,
+        )?)?;
+        PAIR_INFO.save(deps.storage, &pair_info)?;
+    }
+
+    if CONFIG.may_load(deps.storage)?.is_none() {
+        let owner = msg
+            .owner
+            .ok_or_else(|| StdError::generic_err("Owner is required to migrate this pair"))?;
+        CONFIG.save(deps.storage, &Config::new(deps.api.addr_validate(&owner)?))?;
+    }
+    if AMP_RAMP.may_load(deps.storage)?.is_none() {
+        AMP_RAMP.save(
+            deps.storage,
+            &AmpRamp {
+                initial_amp: pair_info.amplification,
+                initial_time: 0,
+                future_amp: pair_info.amplification,
+                future_time: 0,
+            },
+        )?;
+    }
+    if RATE_PROVIDERS.may_load(deps.storage)?.is_none() {
+        RATE_PROVIDERS.save(deps.storage, &vec![None; n_coins])?;
+    }
+    if ADMIN_FEES.may_load(deps.storage)?.is_none() {
+        ADMIN_FEES.save(deps.storage, &vec![Uint128::zero(); n_coins])?;
+    }
+    // the whole balance of a pair without reserves belongs to its liquidity providers
+    if RESERVES.may_load(deps.storage)?.is_none() {
+        let pools = pair_info.query_pools(&deps.querier, deps.api, env.contract.address.clone())?;
+        RESERVES.save(
+            deps.storage,
+            &pools.into_iter().map(|pool| pool.amount).collect(),
+        )?;
+    }
+    if OBSERVATION_INDEX.may_load(deps.storage)?.is_none() {
+        store_observation(
+            deps.storage,
+            &Observation {
+                timestamp: env.block.time.seconds(),
+                price_cumulatives: vec![Uint128::zero(); n_coins * (n_coins - 1)],
+            },
+        )?;
+    }
+
     Ok(Response::default())
 }
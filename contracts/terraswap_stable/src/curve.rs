@@ -1,78 +1,137 @@
-use std::convert::TryInto;
+use cosmwasm_bignumber::Uint256;
+use cosmwasm_std::Uint128;
+
+use crate::error::ContractError;
 
 const ITERATIONS: u8 = 32;
 pub const FEE_DENOMINATOR: u64 = 10_000;
+/// Decimals of the asset rates
+pub const RATE_PRECISION: u8 = 18;
 
 pub struct Curve {
-    pub amp: u64,
-    pub fee_numerator: u64,
+    pub amp: Uint128,
+    pub fee_numerator: Uint128,
+    /// Protocol share of every fee, over `FEE_DENOMINATOR`
+    pub admin_fee_numerator: Uint128,
+    /// Scales each balance to the same decimals before it enters the invariant
+    pub precision_multipliers: Vec<Uint128>,
+    /// Value of each asset in units of the invariant, scaled by `10^RATE_PRECISION`. Plain
+    /// assets are worth one, the LP leg of a metapool is worth the base pool virtual price.
+    pub rates: Vec<Uint128>,
+    /// Cap of the dynamic swap fee as a multiple of `fee_numerator`, over `FEE_DENOMINATOR`.
+    /// Up to `FEE_DENOMINATOR` every trade pays the flat `fee_numerator`.
+    pub fee_multiplier: Uint128,
 }
 
 impl Curve {
+    /// Get the amount of asset `j` required to receive `out_amount` of asset `i`
+    /// Returns `(offer_amount, fee_amount, fee_numerator)` where the fee is denominated in
+    /// asset `i` and `fee_numerator` is the fee rate applied, over `FEE_DENOMINATOR`.
+    /// Rounds against the trader: the pool also keeps back the unit `exchange` withholds, and
+    /// the offer is rounded up by one after downscaling, so `exchange` of `offer_amount` never
+    /// returns less than `out_amount`.
     pub fn reverse_exchange(
         &self,
         i: usize,
         j: usize,
-        out_amount: u128,
-        balances: &[u128],
-    ) -> Option<u128> {
-        let out_amount_after_fee = u128::from(out_amount)
-            .checked_mul(FEE_DENOMINATOR as u128)?
-            .checked_div(FEE_DENOMINATOR as u128 - self.fee_numerator as u128)?;
+        out_amount: Uint128,
+        balances: &[Uint128],
+    ) -> Result<(Uint128, Uint128, Uint128), ContractError> {
+        let xp = self.xp(balances)?;
+        let mut fee = Uint256::from(self.fee_numerator);
+        if self.has_dynamic_fee() {
+            // the fee depends on where the trade leaves the pool, estimate it at the
+            // balances the base fee would leave
+            let (_, x, y) = self.reverse_balances(i, j, out_amount.into(), fee, &xp)?;
+            fee = self.swap_fee((xp[i], xp[j]), (x, y))?;
+        }
+        let (out_amount_before_fee, _, y) =
+            self.reverse_balances(i, j, out_amount.into(), fee, &xp)?;
+        let dy_fee = out_amount_before_fee.checked_sub(out_amount.into())?;
+
+        let dx = self
+            .downscale(j, y.checked_sub(xp[j])?)?
+            .checked_add(Uint256::one())?;
+
+        Ok((to_uint128(dx)?, to_uint128(dy_fee)?, to_uint128(fee)?))
+    }
 
-        let x = balances[i as usize].checked_sub(out_amount_after_fee)?;
+    /// Returns `(out_amount_before_fee, x, y)`, the gross output of asset `i` and the
+    /// normalized balances of `i` and `j` after paying out `out_amount` at `fee`
+    fn reverse_balances(
+        &self,
+        i: usize,
+        j: usize,
+        out_amount: Uint256,
+        fee: Uint256,
+        xp: &[Uint256],
+    ) -> Result<(Uint256, Uint256, Uint256), ContractError> {
+        let fee_denominator = Uint256::from(FEE_DENOMINATOR);
+        let out_amount_before_fee = mul_div(
+            out_amount,
+            fee_denominator,
+            fee_denominator.checked_sub(fee)?,
+        )?;
 
-        let y: u128 = self.get_y(i, j, x, &balances)?;
+        // mirrors the unit `exchange` keeps back from its output
+        let x = xp[i]
+            .checked_sub(self.upscale(i, out_amount_before_fee)?)?
+            .checked_sub(Uint256::one())?;
 
-        let dy = y
-            .checked_sub(balances[j as usize].try_into().ok()?)?
-            .checked_add(1u128);
+        let y = self.get_y(i, j, x, xp)?;
 
-        return dy;
+        Ok((out_amount_before_fee, x, y))
     }
 
-    pub fn get_d(&self, amounts: &[u128], d_suggest: Option<u128>) -> Option<u128> {
-        let n_coins = amounts.len();
-        let sum_x: u128 = amounts.iter().sum();
-        if sum_x == 0 {
-            return Some(0u128);
+    pub fn get_d(
+        &self,
+        xp: &[Uint256],
+        d_suggest: Option<Uint256>,
+    ) -> Result<Uint256, ContractError> {
+        let n_coins = Uint256::from(xp.len() as u64);
+        let mut sum_x = Uint256::zero();
+        for x in xp.iter() {
+            sum_x = sum_x.checked_add(*x)?;
+        }
+        if sum_x.is_zero() {
+            return Ok(Uint256::zero());
         }
 
-        let amounts_times_coin: Vec<u128> = amounts
+        let amounts_times_coin: Vec<Uint256> = xp
             .iter()
-            .map(|amount| amount.checked_mul(n_coins as u128).unwrap())
-            .collect();
-
-        let ann: u64 = self.amp.checked_mul(n_coins as u64)?;
-        let ann_mul_sum_x = (ann as u128).checked_mul(sum_x)?;
-        let ann_sub_one = (ann as u128).checked_sub(1)?;
-
-        let mut d_prev: u128;
-        let mut d = d_suggest.unwrap_or(sum_x.into());
-        for i in 0..ITERATIONS {
-            let d_u256 = d;
-            let mut d_prod = d_u256;
+            .map(|x| x.checked_mul(n_coins))
+            .collect::<Result<Vec<Uint256>, ContractError>>()?;
+
+        let ann = Uint256::from(self.amp).checked_mul(n_coins)?;
+        let ann_mul_sum_x = ann.checked_mul(sum_x)?;
+        let ann_sub_one = ann.checked_sub(Uint256::one())?;
+
+        let mut d_prev: Uint256;
+        let mut d = d_suggest.unwrap_or(sum_x);
+        for _ in 0..ITERATIONS {
+            let mut d_prod = d;
             for amount_time_coin in amounts_times_coin.iter() {
-                d_prod = d_prod.checked_mul(d_u256)?.checked_div(*amount_time_coin)?;
+                d_prod = mul_div(d_prod, d, *amount_time_coin)?;
             }
             d_prev = d;
             // d = (ann * sum_x + d_prod * n_coins) * d / ((d * (ann - 1)) + (d_prod * (n_coins + 1)))
-            let d_prod_mul_n_coins = d_prod.checked_mul((n_coins as u64).into())?;
-            let numerator = (ann_mul_sum_x.checked_add(d_prod_mul_n_coins)?).checked_mul(d_u256)?;
-            let denominator = (d_u256.checked_mul(ann_sub_one)?)
+            let d_prod_mul_n_coins = d_prod.checked_mul(n_coins)?;
+            let numerator = ann_mul_sum_x.checked_add(d_prod_mul_n_coins)?;
+            let denominator = (d.checked_mul(ann_sub_one)?)
                 .checked_add(d_prod.checked_add(d_prod_mul_n_coins)?)?;
-            d = numerator.checked_div(denominator)?.try_into().ok()?;
+            d = mul_div(numerator, d, denominator)?;
 
             // Equality with the precision of 1
             if d > d_prev {
-                if d.checked_sub(d_prev)? <= 1u128 {
-                    break;
+                if d.checked_sub(d_prev)? <= Uint256::one() {
+                    return Ok(d);
                 }
-            } else if d_prev.checked_sub(d)? <= 1u128 {
-                break;
+            } else if d_prev.checked_sub(d)? <= Uint256::one() {
+                return Ok(d);
             }
         }
-        Some(d)
+
+        Err(ContractError::CurveNotConverged {})
     }
 
     /// Get x[i] if one reduces D from being calculated for xp to D
@@ -80,259 +139,1203 @@ impl Curve {
     /// x_1**2 + x_1 * (sum' - (A*n**n - 1) * D / (A * n**n)) = D ** (n + 1) / (n ** (2 * n) * prod' * A)
     /// x_1**2 + b*x_1 = c
     /// x_1 = (x_1**2 + c) / (2*x_1 + b)
-    pub fn get_y_d(&self, i: u8, amounts: &[u128], d: u128) -> Option<u128> {
-        let n_coins = amounts.len();
-        let ann: u64 = self.amp.checked_mul(n_coins as u64)?;
+    pub fn get_y_d(&self, i: usize, xp: &[Uint256], d: Uint256) -> Result<Uint256, ContractError> {
+        if d.is_zero() {
+            return Err(ContractError::EmptyPool {});
+        }
+        let n_coins = Uint256::from(xp.len() as u64);
+        let ann = Uint256::from(self.amp).checked_mul(n_coins)?;
 
         let mut c = d;
-        let mut s = 0u128;
+        let mut s = Uint256::zero();
 
-        for k in 0..n_coins {
-            let x_temp = if k != i as usize {
-                amounts[k]
-            } else {
+        for (k, x_temp) in xp.iter().enumerate() {
+            if k == i {
                 continue;
-            };
-
-            s = s.checked_add(x_temp.into())?;
-            c = c
-                .checked_mul(d.into())?
-                .checked_div(x_temp.checked_mul(n_coins as u128)?.into())?;
-        }
-        c = c
-            .checked_mul(d.into())?
-            .checked_div(ann.checked_mul(n_coins as u64)?.into())?;
-
-        // TODO: Refactor to share with get_y, as this is identical
-        let b: u128 = s.checked_add(d.checked_div(ann.into())?)?;
-        let mut y = d;
-        for _ in 0..ITERATIONS {
-            let y_prev = y;
-            let y_numerator = y.checked_mul(y)?.checked_add(c.try_into().ok()?)?;
-            let y_denominator = y.checked_mul(2u128)?.checked_add(b)?.checked_sub(d)?;
-            y = y_numerator.checked_div(y_denominator)?;
-            if y > y_prev {
-                if y.checked_sub(y_prev)? <= 1u128 {
-                    break;
-                }
-            } else {
-                if y_prev.checked_sub(y)? <= 1u128 {
-                    break;
-                }
             }
+
+            s = s.checked_add(*x_temp)?;
+            c = mul_div(c, d, x_temp.checked_mul(n_coins)?)?;
         }
+        c = mul_div(c, d, ann.checked_mul(n_coins)?)?;
 
-        Some(y)
+        let b = s.checked_add(d.checked_div(ann)?)?;
+        solve_y(b, c, d)
     }
 
     /// Get swap amount `y` in proportion to `x`
     /// Solve for y:
     /// y**2 + y * (sum' - (A*n**n - 1) * D / (A * n**n)) = D ** (n + 1) / (n ** (2 * n) * prod' * A)
     /// y**2 + b*y = c
-    pub fn get_y(&self, i: usize, j: usize, x: u128, balances: &[u128]) -> Option<u128> {
-        let n_coins = balances.len();
-        let d = self.get_d(balances, None)?;
-        let ann: u64 = self.amp.checked_mul(n_coins as u64)?;
+    pub fn get_y(
+        &self,
+        i: usize,
+        j: usize,
+        x: Uint256,
+        xp: &[Uint256],
+    ) -> Result<Uint256, ContractError> {
+        let n_coins = Uint256::from(xp.len() as u64);
+        let d = self.get_d(xp, None)?;
+        if d.is_zero() {
+            return Err(ContractError::EmptyPool {});
+        }
+        let ann = Uint256::from(self.amp).checked_mul(n_coins)?;
 
         let mut c = d;
-        let mut s: u128 = 0u128;
+        let mut s = Uint256::zero();
 
-        for (k, balance) in balances.iter().enumerate() {
-            let x_temp = if k == i as usize {
+        for (k, balance) in xp.iter().enumerate() {
+            let x_temp = if k == i {
                 x
-            } else if k != j as usize {
-                balance.clone()
+            } else if k != j {
+                *balance
             } else {
                 continue;
             };
 
-            s = s.checked_add(x_temp.into())?;
-            c = c
-                .checked_mul(d.into())?
-                .checked_div(x_temp.checked_mul(n_coins as u128)?.into())?;
+            s = s.checked_add(x_temp)?;
+            c = mul_div(c, d, x_temp.checked_mul(n_coins)?)?;
         }
-        c = c
-            .checked_mul(d.into())?
-            .checked_div(ann.checked_mul(n_coins as u64)?.into())?;
+        c = mul_div(c, d, ann.checked_mul(n_coins)?)?;
 
-        let b: u128 = s.checked_add(d.checked_div(ann.into())?)?;
-        let mut y = d;
-        for _ in 0..ITERATIONS {
-            let y_prev = y;
-            let y_numerator = y.checked_mul(y)?.checked_add(c.try_into().ok()?)?;
-            let y_denominator = y.checked_mul(2u128)?.checked_add(b)?.checked_sub(d)?;
-            y = y_numerator.checked_div(y_denominator)?;
-            if y > y_prev {
-                if y.checked_sub(y_prev)? <= 1u128 {
-                    break;
-                }
+        let b = s.checked_add(d.checked_div(ann)?)?;
+        solve_y(b, c, d)
+    }
+
+    /// Invariant per LP token, scaled by `10^precision_factor`
+    pub fn get_virtual_price(
+        &self,
+        balances: &[Uint128],
+        lp_token_total: Uint128,
+        precision_factor: u8,
+    ) -> Result<Uint128, ContractError> {
+        let d = self.get_d(&self.xp(balances)?, None)?;
+
+        to_uint128(mul_div(d, pow10(precision_factor)?, lp_token_total.into())?)
+    }
+
+    /// Marginal price of asset `i` in units of asset `j` before fees, scaled by `10^precision_factor`
+    /// Differentiating the invariant at a constant D gives
+    /// dx_j / dx_i = (Ann + D_P / x_i) / (Ann + D_P / x_j) where D_P = D**(n+1) / (n**n * prod(x))
+    pub fn marginal_price(
+        &self,
+        i: usize,
+        j: usize,
+        balances: &[Uint128],
+        precision_factor: u8,
+    ) -> Result<Uint128, ContractError> {
+        let xp = self.xp(balances)?;
+        let d = self.get_d(&xp, None)?;
+        if d.is_zero() {
+            return Err(ContractError::EmptyPool {});
+        }
+
+        // (Ann * x_i + D_P) * x_j / ((Ann * x_j + D_P) * x_i), back in the units of each asset
+        let price = self.upscale(i, self.price_factor(i, j, &xp, d, precision_factor)?)?;
+
+        to_uint128(self.downscale(j, mul_div(price, xp[j], xp[i])?)?)
+    }
+
+    /// Get the amount of asset `i` to sell for asset `j` that brings the marginal price of `i`
+    /// in units of `j` down to `target_price`, scaled by `10^precision_factor`. Bisects the
+    /// balance of `i` along the invariant through the current balances, so the fee the pool
+    /// keeps leaves the price a little above the target.
+    /// Returns zero when the price is at or below the target already.
+    pub fn trade_to_price(
+        &self,
+        i: usize,
+        j: usize,
+        balances: &[Uint128],
+        target_price: Uint128,
+        precision_factor: u8,
+    ) -> Result<Uint128, ContractError> {
+        let xp = self.xp(balances)?;
+        let d = self.get_d(&xp, None)?;
+        if d.is_zero() {
+            return Err(ContractError::EmptyPool {});
+        }
+
+        // the target in units of the invariant, undoing the conversion of `marginal_price`
+        let target = self.downscale(i, self.upscale(j, target_price.into())?)?;
+        let price_at = |x: Uint256| -> Result<Uint256, ContractError> {
+            let mut xp_after = xp.clone();
+            xp_after[i] = x;
+            xp_after[j] = self.get_y(i, j, x, &xp)?;
+            let price_factor = self.price_factor(i, j, &xp_after, d, precision_factor)?;
+            mul_div(price_factor, xp_after[j], x)
+        };
+
+        let mut low = xp[i];
+        if price_at(low)? <= target {
+            return Ok(Uint128::zero());
+        }
+
+        // selling drives the price towards zero, double the balance until it passes the target
+        let mut high = low.checked_mul(2u64.into())?;
+        let mut doublings = 0;
+        while price_at(high)? > target {
+            doublings += 1;
+            if doublings > ITERATIONS {
+                return Err(ContractError::CurveNotConverged {});
+            }
+            low = high;
+            high = high.checked_mul(2u64.into())?;
+        }
+
+        // down to a single unit of the asset, or of the invariant if that is coarser
+        while high.checked_sub(low)? > Uint256::one()
+            && !self.downscale(i, high.checked_sub(low)?)?.is_zero()
+        {
+            let mid = low.checked_add(high)?.checked_div(2u64.into())?;
+            if price_at(mid)? > target {
+                low = mid;
             } else {
-                if y_prev.checked_sub(y)? <= 1u128 {
-                    break;
-                }
+                high = mid;
             }
         }
 
-        Some(y)
+        to_uint128(self.downscale(i, high.checked_sub(xp[i])?)?)
     }
 
-    pub fn get_virtual_price(
+    /// `(Ann * x_i + D_P) / (Ann * x_j + D_P)` scaled by `10^precision_factor`, the marginal price
+    /// of asset `i` in units of `j` is this times `x_j / x_i`
+    fn price_factor(
         &self,
-        balances: &[u128],
-        lp_token_total: u64,
+        i: usize,
+        j: usize,
+        xp: &[Uint256],
+        d: Uint256,
         precision_factor: u8,
-    ) -> Option<u64> {
-        let d = self.get_d(balances, None)?;
-        let virtual_price = d
-            .checked_mul(10u128.checked_pow(precision_factor as u32)?)?
-            .checked_div(lp_token_total.into())?;
+    ) -> Result<Uint256, ContractError> {
+        let n_coins = Uint256::from(xp.len() as u64);
+        let ann = Uint256::from(self.amp).checked_mul(n_coins)?;
 
-        virtual_price.try_into().ok()
+        let mut d_prod = d;
+        for x in xp.iter() {
+            d_prod = mul_div(d_prod, d, x.checked_mul(n_coins)?)?;
+        }
+
+        let numerator = ann.checked_mul(xp[i])?.checked_add(d_prod)?;
+        let denominator = ann.checked_mul(xp[j])?.checked_add(d_prod)?;
+        mul_div(numerator, pow10(precision_factor)?, denominator)
     }
 
+    /// Returns `(mint_amount, admin_fees)` where the admin fees are the protocol share
+    /// of the imbalance fee charged on each asset
     pub fn deposit(
         &self,
-        old_balances: &[u128],
-        new_balances: &[u128],
-        lp_token_total: u64,
-    ) -> Option<u64> {
-        let n_coins = old_balances.len();
-        let d_0 = self.get_d(old_balances, None)?;
-        let d_1 = self.get_d(new_balances, None)?;
+        old_balances: &[Uint128],
+        new_balances: &[Uint128],
+        lp_token_total: Uint128,
+    ) -> Result<(Uint128, Vec<Uint128>), ContractError> {
+        let old_xp = self.xp(old_balances)?;
+        let new_xp = self.xp(new_balances)?;
+        let d_0 = self.get_d(&old_xp, None)?;
+        let d_1 = self.get_d(&new_xp, None)?;
 
         if d_1 <= d_0 {
-            return None;
-        }
-
-        if lp_token_total <= 0 {
-            return Some(d_1.try_into().ok()?);
-        }
-        let fee = self
-            .fee_numerator
-            .checked_mul(n_coins as u64)?
-            .checked_div(4u64)?
-            .checked_div(n_coins as u64 - 1)?;
-        let mut new_balances_after_deducted_fee = Vec::with_capacity(n_coins);
-        for i in 0..n_coins {
-            let old_balance = old_balances[i];
-            let new_balance = new_balances[i];
-            let ideal_balance: u128 = d_1
-                .checked_mul(old_balance.into())?
-                .checked_div(d_0)?
-                .try_into()
-                .ok()?;
-            let difference = if ideal_balance < new_balance {
+            return Err(ContractError::InvalidZeroAmount {});
+        }
+
+        if lp_token_total.is_zero() {
+            return Ok((to_uint128(d_1)?, vec![Uint128::zero(); old_xp.len()]));
+        }
+        let fee = self.imbalance_fee(old_xp.len())?;
+        let mut admin_fees = Vec::with_capacity(old_xp.len());
+        let mut new_xp_after_deducted_fee = Vec::with_capacity(old_xp.len());
+        let mut new_sum_x = Uint256::zero();
+        let mut fee_sum_x = Uint256::zero();
+        for (i, (old_balance, new_balance)) in old_xp.iter().zip(new_xp.iter()).enumerate() {
+            let ideal_balance = mul_div(d_1, *old_balance, d_0)?;
+            let difference = if ideal_balance < *new_balance {
                 new_balance.checked_sub(ideal_balance)?
             } else {
-                ideal_balance.checked_sub(new_balance)?
+                ideal_balance.checked_sub(*new_balance)?
             };
-            let fee_for_token = u128::from(fee)
-                .checked_mul(difference.into())?
-                .checked_div(FEE_DENOMINATOR.into())?
-                .try_into()
-                .ok()?;
-            new_balances_after_deducted_fee.push(new_balance.checked_sub(fee_for_token)?);
-        }
+            let fee_for_token = mul_div(fee, difference, FEE_DENOMINATOR.into())?;
+            let new_balance_after_deducted_fee = new_balance.checked_sub(fee_for_token)?;
+            admin_fees.push(self.admin_fee(to_uint128(self.downscale(i, fee_for_token)?)?)?);
 
-        let new_sum_x: u128 = new_balances.iter().sum();
-        let fee_sum_x: u128 = new_balances_after_deducted_fee.iter().sum();
+            new_sum_x = new_sum_x.checked_add(*new_balance)?;
+            fee_sum_x = fee_sum_x.checked_add(new_balance_after_deducted_fee)?;
+            new_xp_after_deducted_fee.push(new_balance_after_deducted_fee);
+        }
 
-        let d_suggest = u128::from(fee_sum_x)
-            .checked_mul(d_1)?
-            .checked_div(new_sum_x.into())?;
-        let d_2 = self.get_d(&new_balances_after_deducted_fee, Some(d_suggest))?;
+        let d_suggest = mul_div(fee_sum_x, d_1, new_sum_x)?;
+        let d_2 = self.get_d(&new_xp_after_deducted_fee, Some(d_suggest))?;
         let d_diff = d_2.checked_sub(d_0)?;
-        let mint_amount = u128::from(lp_token_total)
-            .checked_mul(d_diff)?
-            .checked_div(d_0)?;
+        let mint_amount = mul_div(lp_token_total.into(), d_diff, d_0)?;
 
-        Some(mint_amount.try_into().ok()?)
+        Ok((to_uint128(mint_amount)?, admin_fees))
     }
 
     pub fn remove_balanced_liquidity(
-        old_balances: &[u128],
-        unmint_amount: u64,
-        lp_total_supply: u64,
-    ) -> Option<Vec<u128>> {
+        old_balances: &[Uint128],
+        unmint_amount: Uint128,
+        lp_total_supply: Uint128,
+    ) -> Result<Vec<Uint128>, ContractError> {
         let mut amounts = Vec::with_capacity(old_balances.len());
 
         for old_balance in old_balances.iter() {
-            let amount: u128 = u128::from(*old_balance)
-                .checked_mul(unmint_amount.into())?
-                .checked_div(lp_total_supply.into())?
-                .try_into()
-                .ok()?;
-            amounts.push(amount);
+            let amount = mul_div(
+                (*old_balance).into(),
+                unmint_amount.into(),
+                lp_total_supply.into(),
+            )?;
+            amounts.push(to_uint128(amount)?);
         }
 
-        Some(amounts)
+        Ok(amounts)
     }
 
+    /// Returns `(out_amount, admin_fee)` where the admin fee is the protocol share of the
+    /// imbalance fee, denominated in asset `i`
     pub fn remove_liquidity_single_token(
         &self,
-        old_balances: &[u128],
-        unmint_amount: u64,
-        i: u8,
-        lp_total_supply: u64,
-    ) -> Option<u64> {
-        let n_coins = old_balances.len();
-
-        let d0 = self.get_d(&old_balances, None)?;
-        let d1 = d0.checked_sub(
-            u128::from(unmint_amount)
-                .checked_mul(d0)?
-                .checked_div(lp_total_supply.into())?,
-        )?;
-        let new_y = self.get_y_d(i, &old_balances, d1)?;
-        let mut xp_reduced = Vec::with_capacity(n_coins);
-
-        let fee = self
-            .fee_numerator
-            .checked_mul(n_coins as u64)?
-            .checked_div(4u64)?
-            .checked_div(n_coins as u64 - 1)?;
-
-        for j in 0..n_coins {
-            let old_balance_u128: u128 = old_balances[j].into();
-            let dx_expected = if j == i as usize {
-                old_balance_u128
-                    .checked_mul(d1)?
-                    .checked_div(d0)?
-                    .checked_sub(new_y)?
+        old_balances: &[Uint128],
+        unmint_amount: Uint128,
+        i: usize,
+        lp_total_supply: Uint128,
+    ) -> Result<(Uint128, Uint128), ContractError> {
+        let old_xp = self.xp(old_balances)?;
+        let d0 = self.get_d(&old_xp, None)?;
+        let d1 = d0.checked_sub(mul_div(unmint_amount.into(), d0, lp_total_supply.into())?)?;
+        let new_y = self.get_y_d(i, &old_xp, d1)?;
+        let mut xp_reduced = Vec::with_capacity(old_xp.len());
+
+        let fee = self.imbalance_fee(old_xp.len())?;
+
+        for (j, old_balance) in old_xp.iter().enumerate() {
+            let dx_expected = if j == i {
+                mul_div(*old_balance, d1, d0)?.checked_sub(new_y)?
             } else {
-                old_balance_u128.checked_sub(old_balance_u128.checked_mul(d1)?.checked_div(d0)?)?
+                old_balance.checked_sub(mul_div(*old_balance, d1, d0)?)?
             };
-            xp_reduced.push(
-                old_balances[j].checked_sub(
-                    u128::from(fee)
-                        .checked_mul(dx_expected.into())?
-                        .checked_div(FEE_DENOMINATOR.into())?
-                        .try_into()
-                        .ok()?,
-                )?,
-            );
-        }
-
-        let mut dy = xp_reduced[i as usize].checked_sub(self.get_y_d(i, &xp_reduced, d1)?)?;
+            xp_reduced.push(old_balance.checked_sub(mul_div(
+                fee,
+                dx_expected,
+                FEE_DENOMINATOR.into(),
+            )?)?);
+        }
+
+        let dy = xp_reduced[i].checked_sub(self.get_y_d(i, &xp_reduced, d1)?)?;
         // Curve minuses 1 from the final amount. This helps to solve 0 to 1 withdrawal.
         // Source: https://github.com/curvefi/curve-contract/blob/master/contracts/pools/3pool/StableSwap3Pool.vy#L657
-        dy = dy.checked_sub(1u128)?;
+        let dy = self.downscale(i, dy.checked_sub(Uint256::one())?)?;
+        let dy_0 = self.downscale(i, old_xp[i].checked_sub(new_y)?)?;
+        let dy_fee = to_uint128(dy_0)?.saturating_sub(to_uint128(dy)?);
+
+        Ok((to_uint128(dy)?, self.admin_fee(dy_fee)?))
+    }
+
+    /// Get the LP amount to burn for withdrawing exactly `amounts`, charged the same
+    /// imbalance fee as `deposit`
+    /// Returns `(burn_amount, admin_fees)`
+    pub fn remove_liquidity_imbalance(
+        &self,
+        old_balances: &[Uint128],
+        amounts: &[Uint128],
+        lp_total_supply: Uint128,
+    ) -> Result<(Uint128, Vec<Uint128>), ContractError> {
+        if lp_total_supply.is_zero() {
+            return Err(ContractError::EmptyPool {});
+        }
+
+        let old_xp = self.xp(old_balances)?;
+        let new_balances = old_balances
+            .iter()
+            .zip(amounts.iter())
+            .map(|(balance, amount)| {
+                balance
+                    .checked_sub(*amount)
+                    .map_err(|_| ContractError::MathOverflow {})
+            })
+            .collect::<Result<Vec<Uint128>, ContractError>>()?;
+        let new_xp = self.xp(&new_balances)?;
+        let d_0 = self.get_d(&old_xp, None)?;
+        let d_1 = self.get_d(&new_xp, None)?;
+
+        if d_1 >= d_0 {
+            return Err(ContractError::InvalidZeroAmount {});
+        }
+
+        let fee = self.imbalance_fee(old_xp.len())?;
+        let mut admin_fees = Vec::with_capacity(old_xp.len());
+        let mut new_xp_after_deducted_fee = Vec::with_capacity(old_xp.len());
+        for (i, (old_balance, new_balance)) in old_xp.iter().zip(new_xp.iter()).enumerate() {
+            let ideal_balance = mul_div(d_1, *old_balance, d_0)?;
+            let difference = if ideal_balance < *new_balance {
+                new_balance.checked_sub(ideal_balance)?
+            } else {
+                ideal_balance.checked_sub(*new_balance)?
+            };
+            let fee_for_token = mul_div(fee, difference, FEE_DENOMINATOR.into())?;
+
+            admin_fees.push(self.admin_fee(to_uint128(self.downscale(i, fee_for_token)?)?)?);
+            new_xp_after_deducted_fee.push(new_balance.checked_sub(fee_for_token)?);
+        }
 
-        Some(dy.try_into().ok()?)
+        let d_2 = self.get_d(&new_xp_after_deducted_fee, Some(d_1))?;
+        // Curve adds 1 to the burn amount, rounding it up in favor of the pool
+        // Source: https://github.com/curvefi/curve-contract/blob/master/contracts/pools/3pool/StableSwap3Pool.vy#L580
+        let burn_amount = mul_div(d_0.checked_sub(d_2)?, lp_total_supply.into(), d_0)?
+            .checked_add(Uint256::one())?;
+
+        Ok((to_uint128(burn_amount)?, admin_fees))
     }
 
-    pub fn exchange(&self, i: usize, j: usize, in_amount: u64, balances: &[u128]) -> Option<u128> {
-        let x = balances[i as usize].checked_add(in_amount.try_into().ok()?)?;
-        let y = self.get_y(i, j, x, &balances)?;
+    /// Get the amount of asset `j` received for `in_amount` of asset `i`
+    /// Returns `(return_amount, fee_amount, fee_numerator)` where the fee is denominated in
+    /// asset `j` and `fee_numerator` is the fee rate applied, over `FEE_DENOMINATOR`
+    pub fn exchange(
+        &self,
+        i: usize,
+        j: usize,
+        in_amount: Uint128,
+        balances: &[Uint128],
+    ) -> Result<(Uint128, Uint128, Uint128), ContractError> {
+        let xp = self.xp(balances)?;
+        let x = xp[i].checked_add(self.upscale(i, in_amount.into())?)?;
+        let y = self.get_y(i, j, x, &xp)?;
         // Curve minuses 1 from the final amount. This helps to solve 0 to 1 swapping.
         // Source: https://github.com/curvefi/curve-contract/blob/master/contracts/pools/3pool/StableSwap3Pool.vy#L465
-        let mut dy: u128 = balances[j as usize]
-            .checked_sub(y.try_into().ok()?)?
-            .checked_sub(1u128)?;
-        let dy_fee: u128 = u128::from(dy)
-            .checked_mul(self.fee_numerator.into())?
-            .checked_div(FEE_DENOMINATOR.into())?;
+        let dy = xp[j].checked_sub(y)?.checked_sub(Uint256::one())?;
+        let fee = self.swap_fee((xp[i], xp[j]), (x, y))?;
+        let mut dy = self.downscale(j, dy)?;
+        let dy_fee = mul_div(dy, fee, FEE_DENOMINATOR.into())?;
         dy = dy.checked_sub(dy_fee)?;
-        Some(dy)
+        Ok((to_uint128(dy)?, to_uint128(dy_fee)?, to_uint128(fee)?))
+    }
+
+    /// Fee rate of a trade moving the normalized balances of the traded pair from `before`
+    /// to `after`. A trade leaving the pair further off balance pays Curve's dynamic fee at
+    /// the average of both, `fee * m / ((m - 1) * 4 * x_i * x_j / (x_i + x_j)**2 + 1)`,
+    /// which grows from the base fee in a balanced pool towards `fee * m` in a drained one.
+    /// A trade restoring the balance pays the base fee.
+    /// Source: https://github.com/curvefi/stableswap-ng/blob/main/contracts/main/CurveStableSwapNG.vy#L1075
+    fn swap_fee(
+        &self,
+        before: (Uint256, Uint256),
+        after: (Uint256, Uint256),
+    ) -> Result<Uint256, ContractError> {
+        let fee = Uint256::from(self.fee_numerator);
+        if !self.has_dynamic_fee() || balance_ratio(after)? >= balance_ratio(before)? {
+            return Ok(fee);
+        }
+
+        let two = Uint256::from(2u64);
+        let average = (
+            before.0.checked_add(after.0)?.checked_div(two)?,
+            before.1.checked_add(after.1)?.checked_div(two)?,
+        );
+        let fee_denominator = Uint256::from(FEE_DENOMINATOR);
+        let multiplier = Uint256::from(self.fee_multiplier);
+        let denominator = mul_div(
+            multiplier.checked_sub(fee_denominator)?,
+            balance_ratio(average)?,
+            pow10(RATE_PRECISION)?,
+        )?
+        .checked_add(fee_denominator)?;
+
+        mul_div(fee, multiplier, denominator)
+    }
+
+    fn has_dynamic_fee(&self) -> bool {
+        self.fee_multiplier.u128() > FEE_DENOMINATOR as u128
+    }
+
+    /// Amount of asset `j` worth `amount` of asset `i` at the asset rates, which is what a swap
+    /// without price impact or fees returns
+    pub fn value_at_rates(
+        &self,
+        i: usize,
+        j: usize,
+        amount: Uint128,
+    ) -> Result<Uint128, ContractError> {
+        to_uint128(self.downscale(j, self.upscale(i, amount.into())?)?)
+    }
+
+    /// Protocol share of `fee_amount`
+    pub fn admin_fee(&self, fee_amount: Uint128) -> Result<Uint128, ContractError> {
+        to_uint128(mul_div(
+            fee_amount.into(),
+            self.admin_fee_numerator.into(),
+            FEE_DENOMINATOR.into(),
+        )?)
+    }
+
+    /// Fee charged on the imbalanced part of a deposit or withdrawal
+    /// fee * n_coins / 4 / (n_coins - 1)
+    fn imbalance_fee(&self, n_coins: usize) -> Result<Uint256, ContractError> {
+        let n_coins = n_coins as u64;
+        Uint256::from(self.fee_numerator)
+            .checked_mul(n_coins.into())?
+            .checked_div(4u64.into())?
+            .checked_div(
+                n_coins
+                    .checked_sub(1)
+                    .ok_or(ContractError::MathOverflow {})?
+                    .into(),
+            )
+    }
+
+    /// Balances normalized to the same decimals and valued at their rates
+    fn xp(&self, balances: &[Uint128]) -> Result<Vec<Uint256>, ContractError> {
+        if balances.len() != self.precision_multipliers.len() || balances.len() != self.rates.len()
+        {
+            return Err(ContractError::AssetMismatch {});
+        }
+
+        balances
+            .iter()
+            .enumerate()
+            .map(|(i, balance)| self.upscale(i, (*balance).into()))
+            .collect()
+    }
+
+    fn upscale(&self, i: usize, amount: Uint256) -> Result<Uint256, ContractError> {
+        mul_div(
+            amount.checked_mul(self.precision_multipliers[i].into())?,
+            self.rates[i].into(),
+            pow10(RATE_PRECISION)?,
+        )
+    }
+
+    fn downscale(&self, i: usize, amount: Uint256) -> Result<Uint256, ContractError> {
+        mul_div(amount, pow10(RATE_PRECISION)?, self.rates[i].into())?
+            .checked_div(self.precision_multipliers[i].into())
+    }
+}
+
+/// Solve y**2 + b*y = c iteratively with y = (y**2 + c) / (2*y + b - d)
+fn solve_y(b: Uint256, c: Uint256, d: Uint256) -> Result<Uint256, ContractError> {
+    let mut y = d;
+    for _ in 0..ITERATIONS {
+        let y_prev = y;
+        let y_denominator = y.checked_mul(2u64.into())?.checked_add(b)?.checked_sub(d)?;
+        if y_denominator.is_zero() {
+            return Err(ContractError::CurveNotConverged {});
+        }
+        y = mul_add_div(y, y, c, y_denominator)?;
+        if y > y_prev {
+            if y.checked_sub(y_prev)? <= Uint256::one() {
+                return Ok(y);
+            }
+        } else if y_prev.checked_sub(y)? <= Uint256::one() {
+            return Ok(y);
+        }
+    }
+
+    Err(ContractError::CurveNotConverged {})
+}
+
+/// How close a pair of balances is to an even split, `4 * x_i * x_j / (x_i + x_j)**2`
+/// scaled by `10^RATE_PRECISION`, one when balanced and falling to zero as one side drains
+fn balance_ratio((x_i, x_j): (Uint256, Uint256)) -> Result<Uint256, ContractError> {
+    let sum = x_i.checked_add(x_j)?;
+    mul_div(
+        mul_div(x_i.checked_mul(4u64.into())?, x_j, sum)?,
+        pow10(RATE_PRECISION)?,
+        sum,
+    )
+}
+
+/// Takes `fee_numerator` over `FEE_DENOMINATOR` of `amount`, rounded up in favor of the pool so
+/// that a nonzero rate never charges nothing
+pub fn ceil_fee(amount: Uint128, fee_numerator: Uint128) -> Result<Uint128, ContractError> {
+    let fee_denominator = Uint256::from(FEE_DENOMINATOR);
+    to_uint128(mul_add_div(
+        amount.into(),
+        fee_numerator.into(),
+        fee_denominator - Uint256::one(),
+        fee_denominator,
+    )?)
+}
+
+fn pow10(exponent: u8) -> Result<Uint256, ContractError> {
+    (0..exponent).try_fold(Uint256::one(), |value, _| value.checked_mul(10u64.into()))
+}
+
+/// Computes `a * b / c` with a 512 bit intermediate product, so it only fails
+/// when `c` is zero or the quotient itself does not fit in 256 bits
+fn mul_div(a: Uint256, b: Uint256, c: Uint256) -> Result<Uint256, ContractError> {
+    mul_add_div(a, b, Uint256::zero(), c)
+}
+
+/// Computes `(a * b + addend) / c` in 512 bits. `full_mul` on the words of `Uint256` widens
+/// the product, and multiplying by one widens `addend` and `c` the same way.
+fn mul_add_div(
+    a: Uint256,
+    b: Uint256,
+    addend: Uint256,
+    c: Uint256,
+) -> Result<Uint256, ContractError> {
+    if c.is_zero() {
+        return Err(ContractError::EmptyPool {});
+    }
+
+    let one = Uint256::one().0;
+    let quotient = (a.0.full_mul(b.0) + addend.0.full_mul(one)) / c.0.full_mul(one);
+    if quotient.bits() > 256 {
+        return Err(ContractError::MathOverflow {});
+    }
+
+    Ok(Uint256(quotient.into()))
+}
+
+fn to_uint128(value: Uint256) -> Result<Uint128, ContractError> {
+    if value.0.bits() > 128 {
+        return Err(ContractError::MathOverflow {});
+    }
+
+    Ok(Uint128::from(u128::from(value)))
+}
+
+/// Checked arithmetic for `Uint256`, whose operators panic on overflow
+trait CheckedUint256: Sized {
+    fn checked_add(self, rhs: Self) -> Result<Self, ContractError>;
+    fn checked_sub(self, rhs: Self) -> Result<Self, ContractError>;
+    fn checked_mul(self, rhs: Self) -> Result<Self, ContractError>;
+    fn checked_div(self, rhs: Self) -> Result<Self, ContractError>;
+}
+
+impl CheckedUint256 for Uint256 {
+    fn checked_add(self, rhs: Self) -> Result<Self, ContractError> {
+        match self.0.overflowing_add(rhs.0) {
+            (value, false) => Ok(Uint256(value)),
+            (_, true) => Err(ContractError::MathOverflow {}),
+        }
+    }
+
+    fn checked_sub(self, rhs: Self) -> Result<Self, ContractError> {
+        match self.0.overflowing_sub(rhs.0) {
+            (value, false) => Ok(Uint256(value)),
+            (_, true) => Err(ContractError::MathOverflow {}),
+        }
+    }
+
+    fn checked_mul(self, rhs: Self) -> Result<Self, ContractError> {
+        match self.0.overflowing_mul(rhs.0) {
+            (value, false) => Ok(Uint256(value)),
+            (_, true) => Err(ContractError::MathOverflow {}),
+        }
+    }
+
+    fn checked_div(self, rhs: Self) -> Result<Self, ContractError> {
+        if rhs.is_zero() {
+            return Err(ContractError::EmptyPool {});
+        }
+
+        Ok(Uint256(self.0 / rhs.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const MAX: u128 = u128::MAX;
+
+    fn curve(amp: u128, fee: u128) -> Curve {
+        Curve {
+            amp: Uint128::from(amp),
+            fee_numerator: Uint128::from(fee),
+            admin_fee_numerator: Uint128::zero(),
+            precision_multipliers: vec![Uint128::from(1u128); 2],
+            rates: vec![Uint128::from(10u128.pow(RATE_PRECISION as u32)); 2],
+            fee_multiplier: Uint128::zero(),
+        }
+    }
+
+    fn xp(balances: &[Uint128]) -> Vec<Uint256> {
+        balances.iter().map(|balance| (*balance).into()).collect()
+    }
+
+    #[test]
+    fn get_d_at_max_balances() {
+        let curve = curve(100, 4);
+        let balances = [Uint128::from(MAX), Uint128::from(MAX)];
+
+        let d = curve.get_d(&xp(&balances), None).unwrap();
+        assert_eq!(d, Uint256::from(MAX) + Uint256::from(MAX));
+    }
+
+    #[test]
+    fn typed_errors() {
+        let balances = [Uint128::from(1_000u128), Uint128::from(1_000u128)];
+
+        // amp of zero underflows A * n - 1
+        assert_eq!(
+            curve(0, 4).get_d(&xp(&balances), None),
+            Err(ContractError::MathOverflow {})
+        );
+        assert_eq!(
+            curve(100, 4).exchange(
+                0,
+                1,
+                Uint128::from(1u128),
+                &[Uint128::zero(), Uint128::zero()]
+            ),
+            Err(ContractError::EmptyPool {})
+        );
+        assert_eq!(
+            Curve::remove_balanced_liquidity(&balances, Uint128::from(1u128), Uint128::zero()),
+            Err(ContractError::EmptyPool {})
+        );
+        // the output amount can not exceed the pool
+        assert_eq!(
+            curve(100, 4).reverse_exchange(0, 1, Uint128::from(1_001u128), &balances),
+            Err(ContractError::MathOverflow {})
+        );
+    }
+
+    #[test]
+    fn exchange_between_decimals() {
+        // 6 decimals against 18 decimals, one million of each
+        let curve = Curve {
+            precision_multipliers: vec![Uint128::from(1_000_000_000_000u128), Uint128::from(1u128)],
+            ..curve(100, 4)
+        };
+        let balances = [
+            Uint128::from(1_000_000_000_000u128),
+            Uint128::from(1_000_000_000_000_000_000_000_000u128),
+        ];
+
+        let (dy, dy_fee, _) = curve
+            .exchange(0, 1, Uint128::from(1_000_000u128), &balances)
+            .unwrap();
+        // one unit in, one unit less a small price impact out
+        assert!(dy + dy_fee > Uint128::from(999_999_900_000_000_000u128));
+        assert!(dy + dy_fee < Uint128::from(1_000_000_000_000_000_000u128));
+        assert_eq!(dy_fee, (dy + dy_fee).multiply_ratio(4u128, 10_000u128));
+
+        // asking for the same output takes the same offer, rounded up in favor of the pool
+        let (dx, _, _) = curve.reverse_exchange(1, 0, dy, &balances).unwrap();
+        assert_eq!(dx, Uint128::from(1_000_001u128));
+
+        let (dy, dy_fee, _) = curve
+            .exchange(
+                1,
+                0,
+                Uint128::from(1_000_000_000_000_000_000u128),
+                &balances,
+            )
+            .unwrap();
+        assert_eq!(dy + dy_fee, Uint128::from(999_999u128));
+
+        // a balanced deposit counts both assets at the same value
+        let (minted, _) = curve
+            .deposit(
+                &balances,
+                &[
+                    Uint128::from(1_001_000_000_000u128),
+                    Uint128::from(1_001_000_000_000_000_000_000_000u128),
+                ],
+                Uint128::from(2_000_000_000_000_000_000_000_000u128),
+            )
+            .unwrap();
+        assert_eq!(minted, Uint128::from(2_000_000_000_000_000_000_000u128));
+
+        let (single, _) = curve
+            .remove_liquidity_single_token(
+                &balances,
+                Uint128::from(2_000_000_000_000_000_000u128),
+                0,
+                Uint128::from(2_000_000_000_000_000_000_000_000u128),
+            )
+            .unwrap();
+        assert!(single > Uint128::from(1_998_000u128) && single < Uint128::from(2_000_000u128));
+    }
+
+    #[test]
+    fn exchange_at_rates() {
+        // the second asset is worth 1.5 of the first, the pool holds the same value of each
+        let curve = Curve {
+            rates: vec![
+                Uint128::from(1_000_000_000_000_000_000u128),
+                Uint128::from(1_500_000_000_000_000_000u128),
+            ],
+            ..curve(100, 0)
+        };
+        let balances = [
+            Uint128::from(1_500_000_000u128),
+            Uint128::from(1_000_000_000u128),
+        ];
+
+        assert_eq!(
+            curve.marginal_price(1, 0, &balances, 18).unwrap(),
+            Uint128::from(1_500_000_000_000_000_000u128)
+        );
+
+        let (dy, _, _) = curve
+            .exchange(1, 0, Uint128::from(1_000_000u128), &balances)
+            .unwrap();
+        assert!(dy > Uint128::from(1_499_900u128) && dy < Uint128::from(1_500_000u128));
+
+        let (dy, _, _) = curve
+            .exchange(0, 1, Uint128::from(1_500_000u128), &balances)
+            .unwrap();
+        assert!(dy > Uint128::from(999_900u128) && dy < Uint128::from(1_000_000u128));
+
+        // the reverse quote covers the same output
+        let (dx, _, _) = curve.reverse_exchange(1, 0, dy, &balances).unwrap();
+        let (covered, _, _) = curve.exchange(0, 1, dx, &balances).unwrap();
+        assert!(covered >= dy);
+
+        // a deposit is valued at the rates as well
+        let (minted, _) = curve
+            .deposit(
+                &[Uint128::zero(), Uint128::zero()],
+                &balances,
+                Uint128::zero(),
+            )
+            .unwrap();
+        assert_eq!(minted, Uint128::from(3_000_000_000u128));
+    }
+
+    #[test]
+    fn dynamic_fee() {
+        // a 1% fee growing up to five times as much
+        let curve = Curve {
+            fee_multiplier: Uint128::from(50_000u128),
+            ..curve(100, 100)
+        };
+        let base_fee = Uint128::from(100u128);
+        let max_fee = Uint128::from(500u128);
+        let balanced = [Uint128::from(1_000_000_000u128); 2];
+
+        // a small trade barely moves a balanced pool and pays the base fee
+        let (_, _, fee) = curve
+            .exchange(0, 1, Uint128::from(1_000u128), &balanced)
+            .unwrap();
+        assert_eq!(fee, base_fee);
+
+        // the further a trade takes the pool off balance the more it pays, below the cap
+        let mut last_fee = base_fee;
+        for in_amount in [500_000_000u128, 1_000_000_000, 2_000_000_000] {
+            let (dy, dy_fee, fee) = curve
+                .exchange(0, 1, Uint128::from(in_amount), &balanced)
+                .unwrap();
+            assert!(fee > last_fee && fee < max_fee);
+            assert_eq!(dy_fee, (dy + dy_fee).multiply_ratio(fee, 10_000u128));
+            last_fee = fee;
+        }
+
+        // restoring the balance pays the base fee, moving further off balance pays more than
+        // the same trade on a balanced pool
+        let imbalanced = [
+            Uint128::from(3_000_000_000u128),
+            Uint128::from(1_000_000_000u128),
+        ];
+        let in_amount = Uint128::from(500_000_000u128);
+        let (_, _, fee) = curve.exchange(1, 0, in_amount, &imbalanced).unwrap();
+        assert_eq!(fee, base_fee);
+        let (_, _, fee) = curve.exchange(0, 1, in_amount, &imbalanced).unwrap();
+        let (_, _, balanced_fee) = curve.exchange(0, 1, in_amount, &balanced).unwrap();
+        assert!(fee > balanced_fee && fee < max_fee);
+
+        // the reverse quote charges about the fee of the trade it quotes
+        let in_amount = Uint128::from(1_000_000_000u128);
+        let (dy, _, fee) = curve.exchange(0, 1, in_amount, &balanced).unwrap();
+        let (dx, _, reverse_fee) = curve.reverse_exchange(1, 0, dy, &balanced).unwrap();
+        assert!(reverse_fee > base_fee && reverse_fee.u128().abs_diff(fee.u128()) <= 1);
+        assert!(dx.u128().abs_diff(in_amount.u128()) < 1_000_000);
+        let (_, _, reverse_fee) = curve
+            .reverse_exchange(0, 1, Uint128::from(500_000_000u128), &imbalanced)
+            .unwrap();
+        assert_eq!(reverse_fee, base_fee);
+
+        // a multiplier of one keeps the fee flat
+        let curve = Curve {
+            fee_multiplier: Uint128::from(10_000u128),
+            ..curve
+        };
+        let (_, _, fee) = curve
+            .exchange(0, 1, Uint128::from(2_000_000_000u128), &balanced)
+            .unwrap();
+        assert_eq!(fee, base_fee);
+    }
+
+    #[test]
+    fn exchange_above_u64() {
+        let curve = curve(100, 4);
+        let balance = Uint128::from(u64::MAX as u128 * 1_000);
+        let in_amount = Uint128::from(u64::MAX as u128 * 10);
+
+        let (dy, dy_fee, _) = curve
+            .exchange(0, 1, in_amount, &[balance, balance])
+            .unwrap();
+        assert!(dy.u128() > u64::MAX as u128);
+        assert!(dy + dy_fee <= in_amount);
+    }
+
+    #[test]
+    fn marginal_price() {
+        let curve = curve(100, 0);
+        let one = Uint128::from(10u128.pow(18));
+
+        // a balanced pool trades at par
+        let balances = [Uint128::from(1_000_000_000u128); 2];
+        assert_eq!(curve.marginal_price(0, 1, &balances, 18).unwrap(), one);
+
+        // the abundant asset is cheaper, at the rate a small trade gets
+        let balances = [
+            Uint128::from(2_000_000_000_000u128),
+            Uint128::from(500_000_000_000u128),
+        ];
+        let price = curve.marginal_price(0, 1, &balances, 18).unwrap();
+        let reverse_price = curve.marginal_price(1, 0, &balances, 18).unwrap();
+        assert!(price < one && reverse_price > one);
+        let product = price.multiply_ratio(reverse_price, one);
+        assert!(one.u128().abs_diff(product.u128()) < 1_000_000);
+
+        let offer_amount = Uint128::from(1_000_000u128);
+        let (return_amount, _, _) = curve.exchange(0, 1, offer_amount, &balances).unwrap();
+        assert_eq!(return_amount, price.multiply_ratio(offer_amount, one));
+
+        // prices are quoted in the decimals of each asset
+        let curve = Curve {
+            precision_multipliers: vec![Uint128::from(1u128), Uint128::from(100u128)],
+            ..curve
+        };
+        let balances = [Uint128::from(100_000_000u128), Uint128::from(1_000_000u128)];
+        assert_eq!(
+            curve.marginal_price(0, 1, &balances, 18).unwrap(),
+            Uint128::from(10u128.pow(16))
+        );
+        assert_eq!(
+            curve.marginal_price(1, 0, &balances, 18).unwrap(),
+            Uint128::from(10u128.pow(20))
+        );
+        assert_eq!(
+            curve.marginal_price(0, 1, &[Uint128::zero(), Uint128::zero()], 18),
+            Err(ContractError::EmptyPool {})
+        );
+    }
+
+    #[test]
+    fn trade_to_price() {
+        let curve = curve(100, 0);
+        let one = Uint128::from(10u128.pow(18));
+
+        // the scarce asset is dear, selling it brings the price down to any target in between
+        let balances = [
+            Uint128::from(1_000_000_000u128),
+            Uint128::from(3_000_000_000u128),
+        ];
+        let price = curve.marginal_price(0, 1, &balances, 18).unwrap();
+        assert!(price > one);
+        for target in [price.multiply_ratio(9u128, 10u128), one] {
+            let in_amount = curve.trade_to_price(0, 1, &balances, target, 18).unwrap();
+            let (dy, _, _) = curve.exchange(0, 1, in_amount, &balances).unwrap();
+            let balances_after = [balances[0] + in_amount, balances[1] - dy];
+            let price_after = curve.marginal_price(0, 1, &balances_after, 18).unwrap();
+            assert!(price_after.u128().abs_diff(target.u128()) < target.u128() / 1_000_000);
+        }
+
+        // trading to par takes about the half of the difference
+        let in_amount = curve.trade_to_price(0, 1, &balances, one, 18).unwrap();
+        assert!(in_amount.u128().abs_diff(1_000_000_000) < 10_000_000);
+
+        // selling can only lower the price
+        assert_eq!(
+            curve.trade_to_price(0, 1, &balances, price, 18),
+            Ok(Uint128::zero())
+        );
+        assert_eq!(
+            curve.trade_to_price(1, 0, &balances, one, 18),
+            Ok(Uint128::zero())
+        );
+
+        // the target is quoted in the decimals of each asset, 6 against 18 decimals here
+        let curve = Curve {
+            precision_multipliers: vec![Uint128::from(1_000_000_000_000u128), Uint128::from(1u128)],
+            ..curve
+        };
+        let balances = [
+            Uint128::from(1_000_000_000u128),
+            Uint128::from(3_000_000_000_000_000_000_000u128),
+        ];
+        let target = Uint128::from(10u128.pow(30));
+        let in_amount = curve.trade_to_price(0, 1, &balances, target, 18).unwrap();
+        assert!(in_amount.u128().abs_diff(1_000_000_000) < 10_000_000);
+        let (dy, _, _) = curve.exchange(0, 1, in_amount, &balances).unwrap();
+        let balances_after = [balances[0] + in_amount, balances[1] - dy];
+        let price_after = curve.marginal_price(0, 1, &balances_after, 18).unwrap();
+        assert!(price_after.u128().abs_diff(target.u128()) < target.u128() / 1_000_000);
+    }
+
+    #[test]
+    fn admin_fee_split() {
+        let curve = Curve {
+            admin_fee_numerator: Uint128::from(5_000u128),
+            ..curve(100, 4)
+        };
+        assert_eq!(
+            curve.admin_fee(Uint128::from(1_001u128)).unwrap(),
+            Uint128::from(500u128)
+        );
+
+        let balances = [
+            Uint128::from(1_000_000_000u128),
+            Uint128::from(1_000_000_000u128),
+        ];
+        let supply = Uint128::from(2_000_000_000u128);
+
+        // the first deposit pays no imbalance fee
+        let (_, admin_fees) = curve
+            .deposit(
+                &[Uint128::zero(), Uint128::zero()],
+                &balances,
+                Uint128::zero(),
+            )
+            .unwrap();
+        assert_eq!(admin_fees, vec![Uint128::zero(); 2]);
+
+        // a one sided deposit is charged on both sides of the ideal balance
+        let (_, admin_fees) = curve
+            .deposit(
+                &balances,
+                &[
+                    Uint128::from(1_100_000_000u128),
+                    Uint128::from(1_000_000_000u128),
+                ],
+                supply,
+            )
+            .unwrap();
+        assert!(admin_fees.iter().all(|fee| !fee.is_zero()));
+
+        let (out_amount, admin_fee) = curve
+            .remove_liquidity_single_token(&balances, Uint128::from(100_000_000u128), 0, supply)
+            .unwrap();
+        assert!(!admin_fee.is_zero());
+        // the fee is 0.03% of the withdrawal, half of it goes to the protocol
+        assert!(admin_fee < out_amount.multiply_ratio(4u128, 10_000u128));
+
+        let curve = Curve {
+            admin_fee_numerator: Uint128::zero(),
+            ..curve
+        };
+        let (_, admin_fee) = curve
+            .remove_liquidity_single_token(&balances, Uint128::from(100_000_000u128), 0, supply)
+            .unwrap();
+        assert!(admin_fee.is_zero());
+    }
+
+    #[test]
+    fn remove_liquidity_imbalance() {
+        let curve_without_fee = curve(100, 0);
+        let curve = curve(100, 4);
+        let balances = [
+            Uint128::from(1_000_000_000u128),
+            Uint128::from(1_000_000_000u128),
+        ];
+        let supply = Uint128::from(2_000_000_000u128);
+
+        // a balanced withdrawal pays no fee and burns its proportional share
+        let (burn_amount, _) = curve
+            .remove_liquidity_imbalance(
+                &balances,
+                &[
+                    Uint128::from(100_000_000u128),
+                    Uint128::from(100_000_000u128),
+                ],
+                supply,
+            )
+            .unwrap();
+        assert_eq!(burn_amount, Uint128::from(200_000_001u128));
+
+        // a one sided withdrawal burns more than its value, and about as much as a
+        // single token withdrawal paying out the same amount
+        let (burn_amount, _) = curve
+            .remove_liquidity_imbalance(
+                &balances,
+                &[Uint128::from(100_000_000u128), Uint128::zero()],
+                supply,
+            )
+            .unwrap();
+        assert!(burn_amount > Uint128::from(100_000_000u128));
+        let (out_amount, _) = curve
+            .remove_liquidity_single_token(&balances, burn_amount, 0, supply)
+            .unwrap();
+        assert!(out_amount >= Uint128::from(99_999_000u128));
+        assert!(out_amount <= Uint128::from(100_001_000u128));
+
+        // the fee makes it cost more than without
+        let (burn_amount_without_fee, _) = curve_without_fee
+            .remove_liquidity_imbalance(
+                &balances,
+                &[Uint128::from(100_000_000u128), Uint128::zero()],
+                supply,
+            )
+            .unwrap();
+        assert!(burn_amount_without_fee < burn_amount);
+
+        assert_eq!(
+            curve.remove_liquidity_imbalance(&balances, &[Uint128::zero(); 2], supply),
+            Err(ContractError::InvalidZeroAmount {})
+        );
+        assert_eq!(
+            curve.remove_liquidity_imbalance(
+                &balances,
+                &[Uint128::from(1_000_000_001u128), Uint128::zero()],
+                supply
+            ),
+            Err(ContractError::MathOverflow {})
+        );
+        assert_eq!(
+            curve.remove_liquidity_imbalance(&balances, &balances, Uint128::zero()),
+            Err(ContractError::EmptyPool {})
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn balanced_d_is_sum(
+            amp in 1u128..=10_000,
+            balance in 1u128..=MAX,
+            n_coins in 2usize..=3,
+        ) {
+            let curve = curve(amp, 4);
+            let balances = vec![Uint128::from(balance); n_coins];
+
+            let d = curve.get_d(&xp(&balances), None).unwrap();
+            prop_assert_eq!(d, Uint256::from(balance) * Uint256::from(n_coins as u64));
+        }
+
+        #[test]
+        fn exchange_scales_with_balances(
+            amp in 1u128..=1_000,
+            balance_0 in 1_000_000u128..=1_000_000_000_000,
+            balance_1 in 1_000_000u128..=1_000_000_000_000,
+            in_amount in 1_000u128..=1_000_000_000,
+            scale_exp in 0u32..=80,
+        ) {
+            let curve = curve(amp, 4);
+            // keep each pool within 4x of the other so the invariant converges
+            prop_assume!(balance_0 <= balance_1 * 4 && balance_1 <= balance_0 * 4);
+
+            let scale = 2u128.pow(scale_exp);
+            let (dy, _, _) = curve
+                .exchange(0, 1, Uint128::from(in_amount), &[Uint128::from(balance_0), Uint128::from(balance_1)])
+                .unwrap();
+            let (scaled_dy, _, _) = curve
+                .exchange(
+                    0,
+                    1,
+                    Uint128::from(in_amount * scale),
+                    &[Uint128::from(balance_0 * scale), Uint128::from(balance_1 * scale)],
+                )
+                .unwrap();
+
+            // a truncating implementation diverges as soon as values leave the u64 range
+            let expected = dy.u128() * scale;
+            let tolerance = 2 * scale + expected / 1_000_000;
+            prop_assert!(scaled_dy.u128() + tolerance >= expected);
+            prop_assert!(scaled_dy.u128() <= expected + tolerance);
+        }
+
+        #[test]
+        fn exchange_never_panics(
+            amp in 1u128..=10_000,
+            fee in 0u128..=100,
+            balance_0 in 1u128..=MAX,
+            balance_1 in 1u128..=MAX,
+            in_amount in 0u128..=MAX,
+        ) {
+            let curve = curve(amp, fee);
+            let balances = [Uint128::from(balance_0), Uint128::from(balance_1)];
+
+            if let Ok((dy, dy_fee, _)) = curve.exchange(0, 1, Uint128::from(in_amount), &balances) {
+                prop_assert!(dy + dy_fee < Uint128::from(balance_1));
+            }
+        }
+
+        #[test]
+        fn reverse_exchange_covers_output(
+            amp in 1u128..=1_000,
+            fee in 0u128..=100,
+            balance_0 in 1_000_000u128..=1_000_000_000_000,
+            balance_1 in 1_000_000u128..=1_000_000_000_000,
+            out_amount in 1u128..=500_000,
+        ) {
+            let curve = curve(amp, fee);
+            prop_assume!(balance_0 <= balance_1 * 4 && balance_1 <= balance_0 * 4);
+            let balances = [Uint128::from(balance_0), Uint128::from(balance_1)];
+
+            // the offer quoted for an output buys at least that output, fee included
+            let (dx, dy_fee, _) = curve
+                .reverse_exchange(1, 0, Uint128::from(out_amount), &balances)
+                .unwrap();
+            let (dy, exchange_fee, _) = curve.exchange(0, 1, dx, &balances).unwrap();
+            prop_assert!(dy.u128() >= out_amount);
+            prop_assert!(dy_fee <= exchange_fee);
+        }
+
+        #[test]
+        fn dynamic_fee_within_bounds(
+            amp in 1u128..=1_000,
+            fee in 1u128..=100,
+            fee_multiplier in 10_001u128..=100_000,
+            balance_0 in 1_000_000u128..=1_000_000_000_000,
+            balance_1 in 1_000_000u128..=1_000_000_000_000,
+            in_amount in 1u128..=1_000_000_000_000,
+        ) {
+            let curve = Curve {
+                fee_multiplier: Uint128::from(fee_multiplier),
+                ..curve(amp, fee)
+            };
+            prop_assume!(balance_0 <= balance_1 * 4 && balance_1 <= balance_0 * 4);
+            let balances = [Uint128::from(balance_0), Uint128::from(balance_1)];
+
+            if let Ok((_, _, applied_fee)) = curve.exchange(0, 1, Uint128::from(in_amount), &balances) {
+                prop_assert!(applied_fee.u128() >= fee);
+                prop_assert!(applied_fee.u128() <= fee * fee_multiplier / 10_000);
+                // offering the scarce asset restores the balance as long as it does not
+                // overshoot, the return is worth at most four times the offer here
+                if balance_0 + 5 * in_amount <= balance_1 {
+                    prop_assert_eq!(applied_fee.u128(), fee);
+                }
+            }
+        }
+
+        #[test]
+        fn deposit_and_withdraw_at_max_balances(
+            amp in 1u128..=1_000,
+            balance in (MAX / 4)..=(MAX / 2),
+            supply in 1u128..=MAX,
+            share in 1u128..=MAX,
+        ) {
+            prop_assume!(share <= supply);
+            let curve = curve(amp, 4);
+            let old_balances = [Uint128::from(balance), Uint128::from(balance)];
+            let new_balances = [Uint128::from(balance * 2), Uint128::from(balance * 2)];
+
+            // balanced deposit doubling the pool doubles the supply, if it fits
+            let minted = curve.deposit(&old_balances, &new_balances, Uint128::from(supply));
+            if supply <= MAX / 2 {
+                let minted = minted.unwrap().0.u128();
+                prop_assert!(minted <= supply && minted + 2 >= supply);
+            }
+
+            let amounts = Curve::remove_balanced_liquidity(
+                &old_balances,
+                Uint128::from(share),
+                Uint128::from(supply),
+            )
+            .unwrap();
+            let expected = mul_div(balance.into(), share.into(), supply.into()).unwrap();
+            prop_assert_eq!(Uint256::from(amounts[0]), expected);
+
+            if let Ok((single, _)) = curve.remove_liquidity_single_token(
+                &old_balances,
+                Uint128::from(share),
+                0,
+                Uint128::from(supply),
+            ) {
+                prop_assert!(single.u128() <= balance);
+            }
+        }
     }
 }
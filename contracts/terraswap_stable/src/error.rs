@@ -1,4 +1,4 @@
-use cosmwasm_std::{OverflowError, StdError};
+use cosmwasm_std::{OverflowError, StdError, Uint128};
 use thiserror::Error;
 
 #[derive(Error, Debug, PartialEq)]
@@ -18,12 +18,99 @@ pub enum ContractError {
     #[error("Max spread assertion")]
     MaxSpreadAssertion {},
 
-    #[error("Max slippage assertion")]
-    MaxSlippageAssertion {},
+    #[error("Slippage exceeded: expected at least {min_out_amount}, got {out_amount}")]
+    SlippageExceeded {
+        min_out_amount: Uint128,
+        out_amount: Uint128,
+    },
+
+    #[error("Offer amount {offer_amount} exceeds the maximum {max_offer_amount}")]
+    MaxOfferExceeded {
+        max_offer_amount: Uint128,
+        offer_amount: Uint128,
+    },
+
+    #[error("First deposit has to mint at least {min_share} LP tokens, got {share}")]
+    MinimumLiquidity { min_share: Uint128, share: Uint128 },
+
+    #[error("Deadline expired")]
+    DeadlineExpired {},
 
     #[error("Asset mismatch")]
     AssetMismatch {},
 
     #[error("Too small offer amount")]
     TooSmallOfferAmount {},
+
+    #[error("Duplicate asset: {asset}")]
+    DuplicateAsset { asset: String },
+
+    #[error("Unknown asset: {asset}")]
+    UnknownAsset { asset: String },
+
+    #[error("Curve invariant did not converge")]
+    CurveNotConverged {},
+
+    #[error("Math overflow")]
+    MathOverflow {},
+
+    #[error("Pool is empty")]
+    EmptyPool {},
+
+    #[error("Amp ramp is too short or started too soon after the last one")]
+    InvalidRampTime {},
+
+    #[error("Invalid amp")]
+    InvalidAmp {},
+
+    #[error("Amp change is too large")]
+    AmpChangeTooLarge {},
+
+    #[error("Invalid fee")]
+    InvalidFee {},
+
+    #[error("Pair is paused")]
+    Paused {},
+
+    #[error("Pause expiry height must be in the future")]
+    InvalidExpiryHeight {},
+
+    #[error("Fee collector is not set")]
+    NoFeeCollector {},
+
+    #[error("Withdrawn amount {amount} of {asset} is below the minimum {min_amount}")]
+    MinAssetAssertion {
+        asset: String,
+        min_amount: Uint128,
+        amount: Uint128,
+    },
+
+    #[error("Burn amount {burn_amount} exceeds the maximum {max_burn_amount}")]
+    MaxBurnExceeded {
+        max_burn_amount: Uint128,
+        burn_amount: Uint128,
+    },
+
+    #[error("Pair is locked by a flash loan in progress")]
+    FlashLoanInProgress {},
+
+    #[error("Flash loan of {amount} {asset} exceeds the reserve {reserve}")]
+    FlashLoanTooLarge {
+        asset: String,
+        amount: Uint128,
+        reserve: Uint128,
+    },
+
+    #[error("Flash loan is not repaid: expected a balance of {expected} {asset}, got {amount}")]
+    FlashLoanNotRepaid {
+        asset: String,
+        expected: Uint128,
+        amount: Uint128,
+    },
+
+    #[error("Pair is not a metapool")]
+    NotMetapool {},
+
+    #[error("No observation is kept from {seconds_ago} seconds ago")]
+    ObservationUnavailable { seconds_ago: u64 },
 }
@@ -7,6 +7,9 @@ use std::collections::HashMap;
 
 use cw20::{BalanceResponse as Cw20BalanceResponse, Cw20QueryMsg, TokenInfoResponse};
 use terra_cosmwasm::{TaxCapResponse, TaxRateResponse, TerraQuery, TerraQueryWrapper, TerraRoute};
+use terraswap::asset::PairInfo;
+use terraswap::pair::{QueryMsg as PairQueryMsg, VirtualPriceResponse};
+use terraswap::rate_provider::{QueryMsg as RateProviderQueryMsg, RateResponse};
 
 /// mock_dependencies is a drop-in replacement for cosmwasm_std::testing::mock_dependencies
 /// this uses our CustomQuerier.
@@ -27,6 +30,10 @@ pub struct WasmMockQuerier {
     base: MockQuerier<TerraQueryWrapper>,
     token_querier: TokenQuerier,
     tax_querier: TaxQuerier,
+    token_decimals: HashMap<String, u8>,
+    // pair info and virtual price of the pairs this one can query
+    pairs: HashMap<String, (PairInfo, Uint128)>,
+    rates: HashMap<String, RateResponse>,
 }
 
 #[derive(Clone, Default)]
@@ -126,6 +133,32 @@ impl WasmMockQuerier {
                     panic!("DO NOT ENTER HERE")
                 }
             }
+            QueryRequest::Wasm(WasmQuery::Smart { contract_addr, msg })
+                if self.rates.contains_key(contract_addr) =>
+            {
+                match from_binary(msg).unwrap() {
+                    RateProviderQueryMsg::Rate {} => SystemResult::Ok(ContractResult::from(
+                        to_binary(&self.rates[contract_addr]),
+                    )),
+                }
+            }
+            QueryRequest::Wasm(WasmQuery::Smart { contract_addr, msg })
+                if self.pairs.contains_key(contract_addr) =>
+            {
+                let (pair_info, virtual_price) = &self.pairs[contract_addr];
+                match from_binary(msg).unwrap() {
+                    PairQueryMsg::Pair {} => {
+                        SystemResult::Ok(ContractResult::from(to_binary(pair_info)))
+                    }
+                    PairQueryMsg::VirtualPrice {} => {
+                        SystemResult::Ok(ContractResult::from(to_binary(&VirtualPriceResponse {
+                            virtual_price: *virtual_price,
+                            precision: 18,
+                        })))
+                    }
+                    _ => panic!("DO NOT ENTER HERE"),
+                }
+            }
             QueryRequest::Wasm(WasmQuery::Smart { contract_addr, msg }) => {
                 match from_binary(msg).unwrap() {
                     Cw20QueryMsg::TokenInfo {} => {
@@ -153,7 +186,11 @@ impl WasmMockQuerier {
                             to_binary(&TokenInfoResponse {
                                 name: "mAAPL".to_string(),
                                 symbol: "mAAPL".to_string(),
-                                decimals: 6,
+                                decimals: self
+                                    .token_decimals
+                                    .get(contract_addr)
+                                    .cloned()
+                                    .unwrap_or(6),
                                 total_supply,
                             })
                             .unwrap(),
@@ -204,6 +241,9 @@ impl WasmMockQuerier {
             base,
             token_querier: TokenQuerier::default(),
             tax_querier: TaxQuerier::default(),
+            token_decimals: HashMap::new(),
+            pairs: HashMap::new(),
+            rates: HashMap::new(),
         }
     }
 
@@ -217,6 +257,23 @@ impl WasmMockQuerier {
         self.tax_querier = TaxQuerier::new(rate, caps);
     }
 
+    pub fn with_token_decimals(&mut self, decimals: &[(&String, u8)]) {
+        for (contract_addr, decimals) in decimals {
+            self.token_decimals
+                .insert(contract_addr.to_string(), *decimals);
+        }
+    }
+
+    pub fn with_pair(&mut self, pair_info: PairInfo, virtual_price: Uint128) {
+        self.pairs
+            .insert(pair_info.contract_addr.clone(), (pair_info, virtual_price));
+    }
+
+    pub fn with_rate(&mut self, rate_provider: &str, rate: Uint128, precision: u8) {
+        self.rates
+            .insert(rate_provider.to_string(), RateResponse { rate, precision });
+    }
+
     pub fn with_balance(&mut self, balances: &[(&String, Vec<Coin>)]) {
         for (addr, balance) in balances {
             self.base.update_balance(addr.to_string(), balance.clone());
@@ -1,10 +1,249 @@
-use cosmwasm_std::Uint128;
-use cw_storage_plus::Item;
-use terraswap::asset::PairInfoRaw;
+use cosmwasm_std::{Addr, StdResult, Storage, Uint128};
+use cw_storage_plus::{Item, Map, U64Key};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use terraswap::asset::{Asset, AssetInfo, PairInfoRaw};
 
 // put the length bytes at the first for compatibility with legacy singleton store
 pub const PAIR_INFO: Item<PairInfoRaw> = Item::new("\u{0}\u{9}pair_info");
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Config {
+    pub owner: Addr,
+    pub pending_owner: Option<Addr>,
+    /// Guardian can pause and unpause the pair next to the owner
+    pub guardian: Option<Addr>,
+    pub paused: bool,
+    /// Block height at which a pause lifts by itself
+    pub pause_expiry_height: Option<u64>,
+    /// Protocol share of every fee, over the fee denominator
+    pub admin_fee: Uint128,
+    pub fee_collector: Option<Addr>,
+    /// Fee charged on every asset lent by a flash loan, over the fee denominator
+    pub flash_loan_fee: Uint128,
+    /// Cap of the dynamic swap fee as a multiple of the fee, over the fee denominator
+    pub fee_multiplier: Uint128,
+}
+
+impl Config {
+    /// Config of a new pair, unpaused and charging nothing beyond the swap fee
+    pub fn new(owner: Addr) -> Self {
+        Config {
+            owner,
+            pending_owner: None,
+            guardian: None,
+            paused: false,
+            pause_expiry_height: None,
+            admin_fee: Uint128::zero(),
+            fee_collector: None,
+            flash_loan_fee: Uint128::zero(),
+            fee_multiplier: Uint128::zero(),
+        }
+    }
+
+    pub fn is_paused(&self, block_height: u64) -> bool {
+        match self.pause_expiry_height {
+            Some(expiry_height) => self.paused && block_height < expiry_height,
+            None => self.paused,
+        }
+    }
+}
+
+pub const CONFIG: Item<Config> = Item::new("config");
+
+/// Admin fees held by the pair, in the order of the pair assets
+pub const ADMIN_FEES: Item<Vec<Uint128>> = Item::new("admin_fees");
+
+/// Pool reserves accounted by the pair, in the order of the pair assets. Balances beyond
+/// the reserves and the admin fees are surplus, which `Skim` pays out and `Sync` adopts.
+pub const RESERVES: Item<Vec<Uint128>> = Item::new("reserves");
+
+/// Flash loan in progress, its presence locks the pair until the callback reply
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FlashLoan {
+    pub borrower: Addr,
+    /// Balances of the pair before the loan, in the order of the pair assets
+    pub balances: Vec<Uint128>,
+    /// Fees owed on top of the lent amounts, in the order of the pair assets
+    pub fees: Vec<Uint128>,
+}
+
+pub const FLASH_LOAN: Item<FlashLoan> = Item::new("flash_loan");
+
+/// Base pool of a metapool, whose LP token is one of the pair assets
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BasePool {
+    pub contract_addr: Addr,
+    pub liquidity_token: Addr,
+    /// Assets of the base pool, which `ExchangeUnderlying` reaches through it
+    pub asset_infos: Vec<AssetInfo>,
+}
+
+pub const BASE_POOL: Item<BasePool> = Item::new("base_pool");
+
+/// Rate provider of each asset, in the order of the pair assets
+pub const RATE_PROVIDERS: Item<Vec<Option<Addr>>> = Item::new("rate_providers");
+
+/// `ExchangeUnderlying` from a base pool asset, waiting for the reply of its base pool deposit
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingExchange {
+    pub sender: Addr,
+    pub receiver: Addr,
+    pub offer_asset: Asset,
+    pub ask_asset_info: AssetInfo,
+    pub min_out_amount: Uint128,
+    /// Base pool LP tokens held by the pair before the deposit
+    pub lp_token_balance: Uint128,
+}
+
+pub const PENDING_EXCHANGE: Item<PendingExchange> = Item::new("pending_exchange");
+
+/// Linear ramp of the amplification coefficient from `initial_amp` to `future_amp`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AmpRamp {
+    pub initial_amp: Uint128,
+    pub initial_time: u64,
+    pub future_amp: Uint128,
+    pub future_time: u64,
+}
+
+impl AmpRamp {
+    pub fn current_amp(&self, block_time: u64) -> Uint128 {
+        if block_time >= self.future_time {
+            return self.future_amp;
+        }
+
+        let elapsed = block_time.saturating_sub(self.initial_time);
+        let duration = self.future_time - self.initial_time;
+        if self.future_amp > self.initial_amp {
+            self.initial_amp
+                + (self.future_amp - self.initial_amp).multiply_ratio(elapsed, duration)
+        } else {
+            self.initial_amp
+                - (self.initial_amp - self.future_amp).multiply_ratio(elapsed, duration)
+        }
+    }
+}
+
+pub const AMP_RAMP: Item<AmpRamp> = Item::new("amp_ramp");
+
+/// Observations kept for `Observe`, at most one per block, the oldest are overwritten first
+pub const OBSERVATION_CAPACITY: u64 = 1_000;
+
+/// Price accumulators at `timestamp` in seconds. Each ordered asset pair accumulates its
+/// marginal price times the seconds it held, wrapping around on overflow.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Observation {
+    pub timestamp: u64,
+    pub price_cumulatives: Vec<Uint128>,
+}
+
+impl Observation {
+    /// Accumulates `prices`, held from this observation until `timestamp`
+    pub fn accumulate(&self, timestamp: u64, prices: &[Uint128]) -> Observation {
+        let elapsed = Uint128::from(timestamp - self.timestamp);
+        Observation {
+            timestamp,
+            price_cumulatives: self
+                .price_cumulatives
+                .iter()
+                .zip(prices.iter())
+                .map(|(cumulative, price)| cumulative.wrapping_add(price.wrapping_mul(elapsed)))
+                .collect(),
+        }
+    }
+
+    /// Interpolates between this observation and the later `next` one
+    pub fn interpolate(&self, next: &Observation, timestamp: u64) -> Observation {
+        let elapsed = timestamp - self.timestamp;
+        let duration = next.timestamp - self.timestamp;
+        Observation {
+            timestamp,
+            price_cumulatives: self
+                .price_cumulatives
+                .iter()
+                .zip(next.price_cumulatives.iter())
+                .map(|(cumulative, next_cumulative)| {
+                    cumulative.wrapping_add(
+                        next_cumulative
+                            .wrapping_sub(*cumulative)
+                            .multiply_ratio(elapsed, duration),
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Slot of the latest observation and the number of slots in use
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ObservationIndex {
+    pub latest: u64,
+    pub length: u64,
+}
+
+/// Ring buffer of observations keyed by slot
+pub const OBSERVATIONS: Map<U64Key, Observation> = Map::new("observations");
+pub const OBSERVATION_INDEX: Item<ObservationIndex> = Item::new("observation_index");
+
+pub fn store_observation(storage: &mut dyn Storage, observation: &Observation) -> StdResult<()> {
+    let index = match OBSERVATION_INDEX.may_load(storage)? {
+        Some(index) => ObservationIndex {
+            latest: (index.latest + 1) % OBSERVATION_CAPACITY,
+            length: (index.length + 1).min(OBSERVATION_CAPACITY),
+        },
+        None => ObservationIndex {
+            latest: 0,
+            length: 1,
+        },
+    };
+    OBSERVATIONS.save(storage, U64Key::new(index.latest), observation)?;
+    OBSERVATION_INDEX.save(storage, &index)
+}
+
+pub fn load_latest_observation(storage: &dyn Storage) -> StdResult<Observation> {
+    let index = OBSERVATION_INDEX.load(storage)?;
+    OBSERVATIONS.load(storage, U64Key::new(index.latest))
+}
+
+/// Finds the accumulators at `timestamp`, which must not be after the latest observation.
+/// Returns `None` when `timestamp` is before the oldest observation kept.
+pub fn load_observation_at(
+    storage: &dyn Storage,
+    timestamp: u64,
+) -> StdResult<Option<Observation>> {
+    let index = OBSERVATION_INDEX.load(storage)?;
+    let oldest = (index.latest + 1 + OBSERVATION_CAPACITY - index.length) % OBSERVATION_CAPACITY;
+    let load = |position: u64| {
+        OBSERVATIONS.load(
+            storage,
+            U64Key::new((oldest + position) % OBSERVATION_CAPACITY),
+        )
+    };
+
+    if timestamp < load(0)?.timestamp {
+        return Ok(None);
+    }
+
+    // binary search the last observation at or before `timestamp`
+    let (mut low, mut high) = (0, index.length - 1);
+    while low < high {
+        let mid = (low + high).div_ceil(2);
+        if load(mid)?.timestamp <= timestamp {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+
+    let before = load(low)?;
+    if before.timestamp == timestamp || low == index.length - 1 {
+        return Ok(Some(before));
+    }
+
+    Ok(Some(before.interpolate(&load(low + 1)?, timestamp)))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -38,6 +277,7 @@ mod test {
                 ],
                 amplification: Uint128::from(60u128),
                 fee: Uint128::from(4u128),
+                precision_multipliers: vec![Uint128::from(1u128), Uint128::from(1u128)],
                 contract_addr: deps.api.addr_canonicalize("pair0000").unwrap(),
                 liquidity_token: deps.api.addr_canonicalize("liquidity0000").unwrap(),
             },
@@ -49,4 +289,79 @@ mod test {
             read_pair_info(&deps.storage).unwrap()
         );
     }
+
+    #[test]
+    fn amp_ramp_interpolation() {
+        let ramp = AmpRamp {
+            initial_amp: Uint128::from(100u128),
+            initial_time: 1_000,
+            future_amp: Uint128::from(200u128),
+            future_time: 2_000,
+        };
+        assert_eq!(ramp.current_amp(1_000), Uint128::from(100u128));
+        assert_eq!(ramp.current_amp(1_500), Uint128::from(150u128));
+        assert_eq!(ramp.current_amp(2_000), Uint128::from(200u128));
+        assert_eq!(ramp.current_amp(5_000), Uint128::from(200u128));
+
+        let ramp = AmpRamp {
+            initial_amp: Uint128::from(200u128),
+            initial_time: 1_000,
+            future_amp: Uint128::from(100u128),
+            future_time: 2_000,
+        };
+        assert_eq!(ramp.current_amp(1_250), Uint128::from(175u128));
+    }
+
+    #[test]
+    fn observation_ring_buffer() {
+        let mut deps = mock_dependencies(&[]);
+        let observation = |timestamp: u64, cumulative: u128| Observation {
+            timestamp,
+            price_cumulatives: vec![Uint128::from(cumulative)],
+        };
+
+        store_observation(&mut deps.storage, &observation(1_000, 0)).unwrap();
+        let latest = load_latest_observation(&deps.storage)
+            .unwrap()
+            .accumulate(1_010, &[Uint128::from(3u128)]);
+        assert_eq!(latest, observation(1_010, 30));
+        store_observation(&mut deps.storage, &latest).unwrap();
+
+        assert_eq!(
+            load_observation_at(&deps.storage, 1_004).unwrap(),
+            Some(observation(1_004, 12))
+        );
+        assert_eq!(
+            load_observation_at(&deps.storage, 1_010).unwrap(),
+            Some(observation(1_010, 30))
+        );
+        assert_eq!(load_observation_at(&deps.storage, 999).unwrap(), None);
+
+        // the oldest observations are overwritten once the buffer is full
+        for k in 2..=OBSERVATION_CAPACITY {
+            let timestamp = 1_000 + 10 * k;
+            store_observation(&mut deps.storage, &observation(timestamp, timestamp.into()))
+                .unwrap();
+        }
+        assert_eq!(load_observation_at(&deps.storage, 1_005).unwrap(), None);
+        assert_eq!(
+            load_observation_at(&deps.storage, 1_015).unwrap(),
+            Some(observation(1_015, 525))
+        );
+        assert_eq!(
+            load_latest_observation(&deps.storage).unwrap(),
+            observation(
+                1_000 + 10 * OBSERVATION_CAPACITY,
+                (1_000 + 10 * OBSERVATION_CAPACITY).into()
+            )
+        );
+
+        // accumulators wrap around on overflow
+        let wrapped = observation(0, u128::MAX).accumulate(2, &[Uint128::from(1u128)]);
+        assert_eq!(wrapped, observation(2, 1));
+        assert_eq!(
+            observation(0, u128::MAX).interpolate(&wrapped, 1),
+            observation(1, 0)
+        );
+    }
 }
@@ -1,24 +1,53 @@
-use crate::contract::{execute, instantiate, query_pair_info, query_pool, reply};
+use crate::contract::{
+    execute, instantiate, migrate, query_admin_fees, query_amp, query_config,
+    query_cumulative_prices, query_observe, query_pair_info, query_pool, query_price_impact,
+    query_reverse_simulation, query_simulate_provide_liquidity, query_simulate_withdraw_liquidity,
+    query_simulate_withdraw_single_liquidity, query_simulation, query_spot_price,
+    query_trade_to_price, query_virtual_price, reply,
+};
 use crate::error::ContractError;
 use crate::mock_querier::mock_dependencies;
 
 use cosmwasm_std::testing::{mock_env, mock_info, MOCK_CONTRACT_ADDR};
 use cosmwasm_std::{
-    attr, to_binary, BankMsg, Coin, ContractResult, CosmosMsg, Decimal, Reply, ReplyOn, Response,
-    StdError, SubMsg, SubMsgExecutionResponse, Uint128, WasmMsg,
+    attr, from_binary, to_binary, BankMsg, Coin, ContractResult, CosmosMsg, Decimal, DepsMut,
+    Reply, ReplyOn, Response, StdError, SubMsg, SubMsgExecutionResponse, Uint128, WasmMsg,
 };
-use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg, MinterResponse};
-use terraswap::asset::{Asset, AssetInfo, PairInfo};
+use cosmwasm_std::{Api, CanonicalAddr};
+use cosmwasm_storage::singleton;
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg, Expiration, MinterResponse};
+use serde::{Deserialize, Serialize};
+use terraswap::asset::{Asset, AssetInfo, AssetInfoRaw, PairInfo};
 use terraswap::pair::{
-    Cw20HookMsg, ExecuteMsg, InstantiateMsg, PoolResponse, ReverseSimulationResponse,
-    SimulationResponse,
+    AmpResponse, ConfigResponse, CumulativePricesResponse, Cw20HookMsg, ExecuteMsg, InstantiateMsg,
+    MigrateMsg, NativeDecimals, PoolResponse, PriceCumulative, RateProvider,
+    ReverseSimulationResponse, SimulateProvideLiquidityResponse, SimulateWithdrawLiquidityResponse,
+    SimulateWithdrawSingleLiquidityResponse, SimulationResponse, VirtualPriceResponse,
 };
 use terraswap::token::InstantiateMsg as TokenInstantiateMsg;
 
+/// Adopts the mocked balances of the pair as its reserves
+fn sync_reserves(deps: DepsMut) {
+    execute(
+        deps,
+        mock_env(),
+        mock_info("addr0000", &[]),
+        ExecuteMsg::Sync {},
+    )
+    .unwrap();
+}
+
 #[test]
 fn proper_initialization() {
     let mut deps = mock_dependencies(&[]);
 
+    deps.querier.with_token_balances(&[(
+        &"asset0000".to_string(),
+        &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::zero())],
+    )]);
+    deps.querier
+        .with_token_decimals(&[(&"asset0000".to_string(), 8u8)]);
+
     let msg = InstantiateMsg {
         asset_infos: vec![
             AssetInfo::NativeToken {
@@ -31,6 +60,16 @@ fn proper_initialization() {
         amplification: Uint128::from(60u128),
         fee: Uint128::from(4u128),
         token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
     };
 
     // we can just call .unwrap() to assert this was a success
@@ -43,9 +82,9 @@ fn proper_initialization() {
             msg: WasmMsg::Instantiate {
                 code_id: 10u64,
                 msg: to_binary(&TokenInstantiateMsg {
-                    name: "terraswap liquidity token".to_string(),
-                    symbol: "uLP".to_string(),
-                    decimals: 6,
+                    name: "uusd-mAAPL-LP".to_string(),
+                    symbol: "UUSD-MAAP-LP".to_string(),
+                    decimals: 8,
                     initial_balances: vec![],
                     mint: Some(MinterResponse {
                         minter: MOCK_CONTRACT_ADDR.to_string(),
@@ -81,7 +120,7 @@ fn proper_initialization() {
     let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
 
     // it worked, let's query the state
-    let pair_info: PairInfo = query_pair_info(deps.as_ref()).unwrap();
+    let pair_info: PairInfo = query_pair_info(deps.as_ref(), mock_env()).unwrap();
     assert_eq!("liquidity0000", pair_info.liquidity_token.as_str());
     assert_eq!(
         pair_info.asset_infos,
@@ -94,6 +133,121 @@ fn proper_initialization() {
             }
         ]
     );
+    // uusd is scaled up to the 8 decimals of asset0000
+    assert_eq!(
+        pair_info.precision_multipliers,
+        vec![Uint128::from(100u128), Uint128::from(1u128)]
+    );
+
+    // native decimals must be given
+    let msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uluna".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::from(60u128),
+        fee: Uint128::from(4u128),
+        token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+    let res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg);
+    assert_eq!(
+        res,
+        Err(ContractError::Std(StdError::generic_err(
+            "Missing decimals for native denom uluna"
+        )))
+    );
+
+    // the amplification and the fee have to be within their bounds
+    let bounded_msg = |amplification: u128, fee: u128| InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::from(amplification),
+        fee: Uint128::from(fee),
+        token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+    for (amplification, fee, err) in [
+        (0u128, 4u128, ContractError::InvalidAmp {}),
+        (1_000_001, 4, ContractError::InvalidAmp {}),
+        (60, 5_001, ContractError::InvalidFee {}),
+    ] {
+        let res = instantiate(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("addr0000", &[]),
+            bounded_msg(amplification, fee),
+        );
+        assert_eq!(res, Err(err));
+    }
+
+    // a pair takes at least two distinct assets
+    let uusd = AssetInfo::NativeToken {
+        denom: "uusd".to_string(),
+    };
+    for (asset_infos, err) in [
+        (
+            vec![],
+            ContractError::Std(StdError::generic_err("A pair takes at least two assets")),
+        ),
+        (
+            vec![uusd.clone()],
+            ContractError::Std(StdError::generic_err("A pair takes at least two assets")),
+        ),
+        (
+            vec![uusd.clone(), uusd],
+            ContractError::DuplicateAsset {
+                asset: "uusd".to_string(),
+            },
+        ),
+    ] {
+        let res = instantiate(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("addr0000", &[]),
+            InstantiateMsg {
+                asset_infos,
+                ..bounded_msg(60, 4)
+            },
+        );
+        assert_eq!(res, Err(err));
+    }
+
+    let _res = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        bounded_msg(1_000_000, 5_000),
+    )
+    .unwrap();
 }
 
 #[test]
@@ -123,6 +277,16 @@ fn provide_liquidity() {
         amplification: Uint128::from(60u128),
         fee: Uint128::from(4u128),
         token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
     };
 
     let env = mock_env();
@@ -153,17 +317,18 @@ fn provide_liquidity() {
                 info: AssetInfo::Token {
                     contract_addr: "asset0000".to_string(),
                 },
-                amount: Uint128::from(100u128),
+                amount: Uint128::from(2000000u128),
             },
             Asset {
                 info: AssetInfo::NativeToken {
                     denom: "uusd".to_string(),
                 },
-                amount: Uint128::from(100u128),
+                amount: Uint128::from(2000000u128),
             },
         ],
         min_out_amount: Uint128::from(0u128),
         receiver: None,
+        deadline: None,
     };
 
     let env = mock_env();
@@ -171,12 +336,13 @@ fn provide_liquidity() {
         "addr0000",
         &[Coin {
             denom: "uusd".to_string(),
-            amount: Uint128::from(100u128),
+            amount: Uint128::from(2000000u128),
         }],
     );
     let res = execute(deps.as_mut(), env, info, msg).unwrap();
-    let transfer_from_msg = res.messages.get(0).expect("no message");
-    let mint_msg = res.messages.get(1).expect("no message");
+    let transfer_from_msg = res.messages.first().expect("no message");
+    let lock_msg = res.messages.get(1).expect("no message");
+    let mint_msg = res.messages.get(2).expect("no message");
     assert_eq!(
         transfer_from_msg,
         &SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
@@ -184,7 +350,20 @@ fn provide_liquidity() {
             msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
                 owner: "addr0000".to_string(),
                 recipient: MOCK_CONTRACT_ADDR.to_string(),
-                amount: Uint128::from(100u128),
+                amount: Uint128::from(2000000u128),
+            })
+            .unwrap(),
+            funds: vec![],
+        }))
+    );
+    // the first deposit locks the minimum liquidity in the pair
+    assert_eq!(
+        lock_msg,
+        &SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "liquidity0000".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Mint {
+                recipient: MOCK_CONTRACT_ADDR.to_string(),
+                amount: Uint128::from(1000u128),
             })
             .unwrap(),
             funds: vec![],
@@ -196,7 +375,7 @@ fn provide_liquidity() {
             contract_addr: "liquidity0000".to_string(),
             msg: to_binary(&Cw20ExecuteMsg::Mint {
                 recipient: "addr0000".to_string(),
-                amount: Uint128::from(200u128),
+                amount: Uint128::from(3999000u128),
             })
             .unwrap(),
             funds: vec![],
@@ -209,9 +388,7 @@ fn provide_liquidity() {
         &MOCK_CONTRACT_ADDR.to_string(),
         vec![Coin {
             denom: "uusd".to_string(),
-            amount: Uint128::from(
-                200u128 + 200u128, /* user deposit must be pre-applied */
-            ),
+            amount: Uint128::from(200u128),
         }],
     )]);
 
@@ -243,6 +420,7 @@ fn provide_liquidity() {
         ],
         min_out_amount: Uint128::from(0u128),
         receiver: Some("staking0000".to_string()), // try changing receiver
+        deadline: None,
     };
 
     let env = mock_env();
@@ -254,35 +432,57 @@ fn provide_liquidity() {
         }],
     );
 
-    // only accept 100, then 50 share will be generated with 100 * (100 / 200)
-    // let res: Response = execute(deps.as_mut(), env, info, msg).unwrap();
-    // let transfer_from_msg = res.messages.get(0).expect("no message");
-    // let mint_msg = res.messages.get(1).expect("no message");
-    // assert_eq!(
-    //     transfer_from_msg,
-    //     &SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
-    //         contract_addr: "asset0000".to_string(),
-    //         msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
-    //             owner: "addr0000".to_string(),
-    //             recipient: MOCK_CONTRACT_ADDR.to_string(),
-    //             amount: Uint128::from(100u128),
-    //         })
-    //         .unwrap(),
-    //         funds: vec![],
-    //     }))
-    // );
-    // assert_eq!(
-    //     mint_msg,
-    //     &SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
-    //         contract_addr: "liquidity0000".to_string(),
-    //         msg: to_binary(&Cw20ExecuteMsg::Mint {
-    //             recipient: "staking0000".to_string(), // LP tokens sent to specified receiver
-    //             amount: Uint128::from(50u128),
-    //         })
-    //         .unwrap(),
-    //         funds: vec![],
-    //     }))
-    // );
+    // unbalanced deposits are charged the imbalance fee
+    sync_reserves(deps.as_mut());
+    let simulation_res: SimulateProvideLiquidityResponse = query_simulate_provide_liquidity(
+        deps.as_ref(),
+        mock_env(),
+        vec![
+            Asset {
+                info: AssetInfo::Token {
+                    contract_addr: "asset0000".to_string(),
+                },
+                amount: Uint128::from(100u128),
+            },
+            Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+                amount: Uint128::from(200u128),
+            },
+        ],
+    )
+    .unwrap();
+    assert_eq!(simulation_res.share, Uint128::from(74u128));
+
+    let res: Response = execute(deps.as_mut(), env, info, msg).unwrap();
+    let transfer_from_msg = res.messages.first().expect("no message");
+    let mint_msg = res.messages.get(1).expect("no message");
+    assert_eq!(
+        transfer_from_msg,
+        &SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "asset0000".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
+                owner: "addr0000".to_string(),
+                recipient: MOCK_CONTRACT_ADDR.to_string(),
+                amount: Uint128::from(100u128),
+            })
+            .unwrap(),
+            funds: vec![],
+        }))
+    );
+    assert_eq!(
+        mint_msg,
+        &SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "liquidity0000".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Mint {
+                recipient: "staking0000".to_string(), // LP tokens sent to specified receiver
+                amount: Uint128::from(74u128),
+            })
+            .unwrap(),
+            funds: vec![],
+        }))
+    );
 
     // check wrong argument
     let msg = ExecuteMsg::ProvideLiquidity {
@@ -302,6 +502,7 @@ fn provide_liquidity() {
         ],
         min_out_amount: Uint128::from(0u128),
         receiver: None,
+        deadline: None,
     };
 
     let env = mock_env();
@@ -326,9 +527,7 @@ fn provide_liquidity() {
         &MOCK_CONTRACT_ADDR.to_string(),
         vec![Coin {
             denom: "uusd".to_string(),
-            amount: Uint128::from(
-                100u128 + 100u128, /* user deposit must be pre-applied */
-            ),
+            amount: Uint128::from(100u128),
         }],
     )]);
 
@@ -342,6 +541,7 @@ fn provide_liquidity() {
             &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::from(100u128))],
         ),
     ]);
+    sync_reserves(deps.as_mut());
 
     // failed because the price is under slippage_tolerance
     let msg = ExecuteMsg::ProvideLiquidity {
@@ -361,6 +561,7 @@ fn provide_liquidity() {
         ],
         min_out_amount: Uint128::from(200u128),
         receiver: None,
+        deadline: None,
     };
 
     let env = mock_env();
@@ -373,7 +574,7 @@ fn provide_liquidity() {
     );
     let res = execute(deps.as_mut(), env, info, msg).unwrap_err();
     match res {
-        ContractError::MaxSlippageAssertion {} => {}
+        ContractError::SlippageExceeded { .. } => {}
         _ => panic!("DO NOT ENTER HERE"),
     }
 
@@ -382,9 +583,10 @@ fn provide_liquidity() {
         &MOCK_CONTRACT_ADDR.to_string(),
         vec![Coin {
             denom: "uusd".to_string(),
-            amount: Uint128::from(100u128 + 98u128 /* user deposit must be pre-applied */),
+            amount: Uint128::from(100u128),
         }],
     )]);
+    sync_reserves(deps.as_mut());
 
     // failed because the price is under slippage_tolerance
     let msg = ExecuteMsg::ProvideLiquidity {
@@ -404,6 +606,7 @@ fn provide_liquidity() {
         ],
         min_out_amount: Uint128::from(200u128),
         receiver: None,
+        deadline: None,
     };
 
     let env = mock_env();
@@ -416,7 +619,7 @@ fn provide_liquidity() {
     );
     let res = execute(deps.as_mut(), env, info, msg).unwrap_err();
     match res {
-        ContractError::MaxSlippageAssertion {} => {}
+        ContractError::SlippageExceeded { .. } => {}
         _ => panic!("DO NOT ENTER HERE"),
     }
 
@@ -425,11 +628,10 @@ fn provide_liquidity() {
         &MOCK_CONTRACT_ADDR.to_string(),
         vec![Coin {
             denom: "uusd".to_string(),
-            amount: Uint128::from(
-                100u128 + 100u128, /* user deposit must be pre-applied */
-            ),
+            amount: Uint128::from(100u128),
         }],
     )]);
+    sync_reserves(deps.as_mut());
 
     // successfully provides
     let msg = ExecuteMsg::ProvideLiquidity {
@@ -449,6 +651,7 @@ fn provide_liquidity() {
         ],
         min_out_amount: Uint128::from(0u128),
         receiver: None,
+        deadline: None,
     };
 
     let env = mock_env();
@@ -466,9 +669,10 @@ fn provide_liquidity() {
         &MOCK_CONTRACT_ADDR.to_string(),
         vec![Coin {
             denom: "uusd".to_string(),
-            amount: Uint128::from(100u128 + 99u128 /* user deposit must be pre-applied */),
+            amount: Uint128::from(100u128),
         }],
     )]);
+    sync_reserves(deps.as_mut());
 
     // successfully provides
     let msg = ExecuteMsg::ProvideLiquidity {
@@ -488,6 +692,7 @@ fn provide_liquidity() {
         ],
         min_out_amount: Uint128::from(0u128),
         receiver: None,
+        deadline: None,
     };
 
     let env = mock_env();
@@ -501,129 +706,23 @@ fn provide_liquidity() {
     let _res = execute(deps.as_mut(), env, info, msg).unwrap();
 }
 
-// #[test]
-// fn withdraw_single_liquidity() {
-//     let mut deps = mock_dependencies(&[Coin {
-//         denom: "uusd".to_string(),
-//         amount: Uint128::from(100u128),
-//     }]);
-
-//     deps.querier.with_tax(
-//         Decimal::zero(),
-//         &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
-//     );
-//     deps.querier.with_token_balances(&[
-//         (
-//             &"liquidity0000".to_string(),
-//             &[(&"addr0000".to_string(), &Uint128::from(100u128))],
-//         ),
-//         (
-//             &"asset0000".to_string(),
-//             &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::from(100u128))],
-//         ),
-//     ]);
-
-//     let msg = InstantiateMsg {
-//         asset_infos: vec![
-//             AssetInfo::NativeToken {
-//                 denom: "uusd".to_string(),
-//             },
-//             AssetInfo::Token {
-//                 contract_addr: "asset0000".to_string(),
-//             },
-//         ],
-//         amplification: Uint128::from(60u128),
-//         fee: Uint128::from(4u128),
-//         token_code_id: 10u64,
-//     };
-
-//     let env = mock_env();
-//     let info = mock_info("addr0000", &[]);
-//     // we can just call .unwrap() to assert this was a success
-//     let _res = instantiate(deps.as_mut(), env, info, msg).unwrap();
-
-//     // store liquidity token
-//     let reply_msg = Reply {
-//         id: 1,
-//         result: ContractResult::Ok(SubMsgExecutionResponse {
-//             events: vec![],
-//             data: Some(
-//                 vec![
-//                     10, 13, 108, 105, 113, 117, 105, 100, 105, 116, 121, 48, 48, 48, 48,
-//                 ]
-//                 .into(),
-//             ),
-//         }),
-//     };
-
-//     let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
-
-//     let msg = ExecuteMsg::WithdrawSingleLiquidity {
-//         asset: Asset {
-//             info: AssetInfo::NativeToken {
-//                 denom: "uusd".to_string(),
-//             },
-//             amount: Uint128::from(0u128),
-//         },
-//         unmint_amount: Uint128::from(100u128),
-//         min_out_amount: Uint128::from(0u128),
-//     };
-
-//     let env = mock_env();
-//     let info = mock_info("liquidity0000", &[]);
-//     let res = execute(deps.as_mut(), env, info, msg).unwrap();
-//     let log_withdrawn_share = res.attributes.get(2).expect("no log");
-//     let log_refund_assets = res.attributes.get(3).expect("no log");
-//     let msg_refund_0 = res.messages.get(0).expect("no message");
-//     let msg_burn_liquidity = res.messages.get(1).expect("no message");
-//     assert_eq!(
-//         msg_refund_0,
-//         &SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
-//             to_address: "liquidity0000".to_string(),
-//             amount: vec![Coin {
-//                 denom: "uusd".to_string(),
-//                 amount: Uint128::from(99u128),
-//             }],
-//         }))
-//     );
-//     assert_eq!(
-//         msg_burn_liquidity,
-//         &SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
-//             contract_addr: "liquidity0000".to_string(),
-//             msg: to_binary(&Cw20ExecuteMsg::Burn {
-//                 amount: Uint128::from(100u128),
-//             })
-//             .unwrap(),
-//             funds: vec![],
-//         }))
-//     );
-
-//     assert_eq!(
-//         log_withdrawn_share,
-//         &attr("withdrawn_share", 100u128.to_string())
-//     );
-//     assert_eq!(log_refund_assets, &attr("refund_asset", "99uusd"));
-// }
-
 #[test]
-fn withdraw_liquidity() {
+fn provide_liquidity_partial() {
+    let pool_amount = Uint128::from(1000000000u128);
+    let deposit_amount = Uint128::from(100000000u128);
     let mut deps = mock_dependencies(&[Coin {
         denom: "uusd".to_string(),
-        amount: Uint128::from(100u128),
+        amount: pool_amount,
     }]);
 
-    deps.querier.with_tax(
-        Decimal::zero(),
-        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
-    );
     deps.querier.with_token_balances(&[
         (
             &"liquidity0000".to_string(),
-            &[(&"addr0000".to_string(), &Uint128::from(100u128))],
+            &[(&"addr0000".to_string(), &Uint128::from(2000000000u128))],
         ),
         (
             &"asset0000".to_string(),
-            &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::from(100u128))],
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &pool_amount)],
         ),
     ]);
 
@@ -636,15 +735,21 @@ fn withdraw_liquidity() {
                 contract_addr: "asset0000".to_string(),
             },
         ],
-        amplification: Uint128::from(60u128),
+        amplification: Uint128::from(100u128),
         fee: Uint128::from(4u128),
         token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
     };
-
-    let env = mock_env();
-    let info = mock_info("addr0000", &[]);
-    // we can just call .unwrap() to assert this was a success
-    let _res = instantiate(deps.as_mut(), env, info, msg).unwrap();
+    let _res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
 
     // store liquidity token
     let reply_msg = Reply {
@@ -659,94 +764,157 @@ fn withdraw_liquidity() {
             ),
         }),
     };
-
     let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+    sync_reserves(deps.as_mut());
 
-    // withdraw single liquidity
-    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
-        sender: "addr0000".to_string(),
-        msg: to_binary(&Cw20HookMsg::WithdrawLiquidity {}).unwrap(),
-        amount: Uint128::from(100u128),
-    });
+    let uusd = |amount: Uint128| Asset {
+        info: AssetInfo::NativeToken {
+            denom: "uusd".to_string(),
+        },
+        amount,
+    };
+    let provide_msg = |assets: Vec<Asset>| ExecuteMsg::ProvideLiquidity {
+        assets,
+        min_out_amount: Uint128::zero(),
+        receiver: None,
+        deadline: None,
+    };
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: deposit_amount,
+        }],
+    );
 
-    let env = mock_env();
-    let info = mock_info("liquidity0000", &[]);
-    let res = execute(deps.as_mut(), env, info, msg).unwrap();
-    let log_withdrawn_share = res.attributes.get(2).expect("no log");
-    // let log_refund_assets = res.attributes.get(3).expect("no log");
-    let msg_refund_0 = res.messages.get(0).expect("no message");
-    let msg_refund_1 = res.messages.get(1).expect("no message");
-    let msg_burn_liquidity = res.messages.get(2).expect("no message");
+    let msg = provide_msg(vec![uusd(deposit_amount), uusd(deposit_amount)]);
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
     assert_eq!(
-        msg_refund_0,
-        &SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
-            to_address: "addr0000".to_string(),
-            amount: vec![Coin {
-                denom: "uusd".to_string(),
-                amount: Uint128::from(100u128),
-            }],
-        }))
+        res,
+        ContractError::DuplicateAsset {
+            asset: "uusd".to_string(),
+        }
     );
+
+    let msg = provide_msg(vec![
+        uusd(deposit_amount),
+        Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uluna".to_string(),
+            },
+            amount: Uint128::zero(),
+        },
+    ]);
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
     assert_eq!(
-        msg_refund_1,
-        &SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+        res,
+        ContractError::UnknownAsset {
+            asset: "uluna".to_string(),
+        }
+    );
+
+    // coins sent along have to be among the deposited assets
+    let msg = provide_msg(vec![Asset {
+        info: AssetInfo::Token {
             contract_addr: "asset0000".to_string(),
-            msg: to_binary(&Cw20ExecuteMsg::Transfer {
-                recipient: "addr0000".to_string(),
-                amount: Uint128::from(100u128),
-            })
-            .unwrap(),
-            funds: vec![],
-        }))
+        },
+        amount: deposit_amount,
+    }]);
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+    assert_eq!(
+        res,
+        ContractError::UnknownAsset {
+            asset: "uusd".to_string(),
+        }
     );
+
+    // a single sided deposit pays the imbalance fee, the missing asset counts as zero
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        info,
+        provide_msg(vec![uusd(deposit_amount)]),
+    )
+    .unwrap();
+    let share = match res.messages.as_slice() {
+        [SubMsg {
+            msg: CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, .. }),
+            ..
+        }] => {
+            assert_eq!(contract_addr, "liquidity0000");
+            Uint128::from(res.attributes[3].value.parse::<u128>().unwrap())
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    };
+    assert!(share > Uint128::from(99900000u128) && share < deposit_amount);
+
+    // token only deposits in any order skip the native side
+    deps.querier.with_balance(&[(
+        &MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: pool_amount,
+        }],
+    )]);
+    sync_reserves(deps.as_mut());
+    let msg = provide_msg(vec![
+        Asset {
+            info: AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+            amount: deposit_amount,
+        },
+        uusd(Uint128::zero()),
+    ]);
+    let res = execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
     assert_eq!(
-        msg_burn_liquidity,
+        res.messages.first().expect("no message"),
         &SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
-            contract_addr: "liquidity0000".to_string(),
-            msg: to_binary(&Cw20ExecuteMsg::Burn {
-                amount: Uint128::from(100u128),
+            contract_addr: "asset0000".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
+                owner: "addr0000".to_string(),
+                recipient: MOCK_CONTRACT_ADDR.to_string(),
+                amount: deposit_amount,
             })
             .unwrap(),
             funds: vec![],
         }))
     );
+    assert_eq!(res.attributes[3], attr("share", share.to_string()));
 
-    assert_eq!(
-        log_withdrawn_share,
-        &attr("withdrawn_share", 100u128.to_string())
-    );
-    // assert_eq!(
-    //     log_refund_assets,
-    //     &attr("refund_assets", "100uusd, 100asset0000")
-    // );
+    // the first deposit has to bring every asset
+    deps.querier.with_token_balances(&[
+        (&"liquidity0000".to_string(), &[]),
+        (
+            &"asset0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &pool_amount)],
+        ),
+    ]);
+    let res =
+        query_simulate_provide_liquidity(deps.as_ref(), mock_env(), vec![uusd(deposit_amount)])
+            .unwrap_err();
+    assert_eq!(res, ContractError::InvalidZeroAmount {});
 }
 
 #[test]
-fn try_native_to_token() {
-    let total_share = Uint128::from(30000000000u128);
-    let asset_pool_amount = Uint128::from(20000000000u128);
-    let collateral_pool_amount = Uint128::from(30000000000u128);
-    let exchange_rate: Decimal = Decimal::from_ratio(asset_pool_amount, collateral_pool_amount);
-    let offer_amount = Uint128::from(1500000000u128);
-
+fn withdraw_single_liquidity() {
     let mut deps = mock_dependencies(&[Coin {
         denom: "uusd".to_string(),
-        amount: collateral_pool_amount + offer_amount, /* user deposit must be pre-applied */
+        amount: Uint128::from(100u128),
     }]);
 
     deps.querier.with_tax(
         Decimal::zero(),
         &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
     );
-
     deps.querier.with_token_balances(&[
         (
             &"liquidity0000".to_string(),
-            &[(&MOCK_CONTRACT_ADDR.to_string(), &total_share)],
+            &[(&"addr0000".to_string(), &Uint128::from(200u128))],
         ),
         (
             &"asset0000".to_string(),
-            &[(&MOCK_CONTRACT_ADDR.to_string(), &asset_pool_amount)],
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::from(100u128))],
         ),
     ]);
 
@@ -762,6 +930,16 @@ fn try_native_to_token() {
         amplification: Uint128::from(60u128),
         fee: Uint128::from(4u128),
         token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
     };
 
     let env = mock_env();
@@ -784,123 +962,4446 @@ fn try_native_to_token() {
     };
 
     let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+    sync_reserves(deps.as_mut());
 
-    // normal swap
-    let msg = ExecuteMsg::Swap {
-        offer_asset: Asset {
-            info: AssetInfo::NativeToken {
+    let simulation_res: SimulateWithdrawSingleLiquidityResponse =
+        query_simulate_withdraw_single_liquidity(
+            deps.as_ref(),
+            mock_env(),
+            Uint128::from(100u128),
+            AssetInfo::NativeToken {
                 denom: "uusd".to_string(),
             },
-            amount: offer_amount,
-        },
+        )
+        .unwrap();
+    assert_eq!(simulation_res.asset.amount, Uint128::from(95u128));
+
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0000".to_string(),
+        msg: to_binary(&Cw20HookMsg::WithdrawSingleLiquidity {
+            asset: Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+                amount: Uint128::zero(),
+            },
+            min_out_amount: Uint128::zero(),
+            receiver: None,
+            deadline: None,
+        })
+        .unwrap(),
+        amount: Uint128::from(100u128),
+    });
+
+    let env = mock_env();
+    let info = mock_info("liquidity0000", &[]);
+    let res = execute(deps.as_mut(), env, info, msg).unwrap();
+    let log_withdrawn_share = res.attributes.get(3).expect("no log");
+    let log_refund_assets = res.attributes.get(4).expect("no log");
+    let msg_refund_0 = res.messages.first().expect("no message");
+    let msg_burn_liquidity = res.messages.get(1).expect("no message");
+    assert_eq!(
+        msg_refund_0,
+        &SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0000".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: simulation_res.asset.amount,
+            }],
+        }))
+    );
+    assert_eq!(
+        msg_burn_liquidity,
+        &SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "liquidity0000".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Burn {
+                amount: Uint128::from(100u128),
+            })
+            .unwrap(),
+            funds: vec![],
+        }))
+    );
+
+    assert_eq!(
+        log_withdrawn_share,
+        &attr("withdrawn_share", 100u128.to_string())
+    );
+    assert_eq!(log_refund_assets, &attr("refund_asset", "95uusd"));
+}
+
+#[test]
+fn withdraw_liquidity() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(100u128),
+    }]);
+
+    deps.querier.with_tax(
+        Decimal::zero(),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&"addr0000".to_string(), &Uint128::from(100u128))],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::from(100u128))],
+        ),
+    ]);
+
+    let msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::from(60u128),
+        fee: Uint128::from(4u128),
+        token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+
+    let env = mock_env();
+    let info = mock_info("addr0000", &[]);
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env, info, msg).unwrap();
+
+    // store liquidity token
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(
+                vec![
+                    10, 13, 108, 105, 113, 117, 105, 100, 105, 116, 121, 48, 48, 48, 48,
+                ]
+                .into(),
+            ),
+        }),
+    };
+
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+    sync_reserves(deps.as_mut());
+
+    let simulation_res: SimulateWithdrawLiquidityResponse =
+        query_simulate_withdraw_liquidity(deps.as_ref(), Uint128::from(100u128)).unwrap();
+    assert_eq!(
+        simulation_res.assets,
+        vec![
+            Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+                amount: Uint128::from(100u128),
+            },
+            Asset {
+                info: AssetInfo::Token {
+                    contract_addr: "asset0000".to_string(),
+                },
+                amount: Uint128::from(100u128),
+            },
+        ]
+    );
+
+    let withdraw_msg = |min_assets: Vec<Asset>, receiver: Option<String>| {
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: "addr0000".to_string(),
+            msg: to_binary(&Cw20HookMsg::WithdrawLiquidity {
+                min_assets,
+                receiver,
+                deadline: None,
+            })
+            .unwrap(),
+            amount: Uint128::from(100u128),
+        })
+    };
+
+    // minimums are checked per asset, in any order
+    let msg = withdraw_msg(
+        vec![
+            Asset {
+                info: AssetInfo::Token {
+                    contract_addr: "asset0000".to_string(),
+                },
+                amount: Uint128::from(100u128),
+            },
+            Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+                amount: Uint128::from(101u128),
+            },
+        ],
+        None,
+    );
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("liquidity0000", &[]),
+        msg,
+    )
+    .unwrap_err();
+    assert_eq!(
+        res,
+        ContractError::MinAssetAssertion {
+            asset: "uusd".to_string(),
+            min_amount: Uint128::from(101u128),
+            amount: Uint128::from(100u128),
+        }
+    );
+
+    let msg = withdraw_msg(
+        vec![Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uluna".to_string(),
+            },
+            amount: Uint128::zero(),
+        }],
+        None,
+    );
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("liquidity0000", &[]),
+        msg,
+    )
+    .unwrap_err();
+    assert_eq!(
+        res,
+        ContractError::UnknownAsset {
+            asset: "uluna".to_string(),
+        }
+    );
+
+    // the refund can go to another address
+    let msg = withdraw_msg(vec![], Some("addr0001".to_string()));
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("liquidity0000", &[]),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages.first().expect("no message"),
+        &SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0001".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(100u128),
+            }],
+        }))
+    );
+
+    // withdraw liquidity
+    sync_reserves(deps.as_mut());
+    let msg = withdraw_msg(
+        vec![Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::from(100u128),
+        }],
+        None,
+    );
+
+    let env = mock_env();
+    let info = mock_info("liquidity0000", &[]);
+    let res = execute(deps.as_mut(), env, info, msg).unwrap();
+    let log_withdrawn_share = res.attributes.get(3).expect("no log");
+    // let log_refund_assets = res.attributes.get(3).expect("no log");
+    let msg_refund_0 = res.messages.first().expect("no message");
+    let msg_refund_1 = res.messages.get(1).expect("no message");
+    let msg_burn_liquidity = res.messages.get(2).expect("no message");
+    assert_eq!(
+        msg_refund_0,
+        &SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0000".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(100u128),
+            }],
+        }))
+    );
+    assert_eq!(
+        msg_refund_1,
+        &SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "asset0000".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "addr0000".to_string(),
+                amount: Uint128::from(100u128),
+            })
+            .unwrap(),
+            funds: vec![],
+        }))
+    );
+    assert_eq!(
+        msg_burn_liquidity,
+        &SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "liquidity0000".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Burn {
+                amount: Uint128::from(100u128),
+            })
+            .unwrap(),
+            funds: vec![],
+        }))
+    );
+
+    assert_eq!(
+        log_withdrawn_share,
+        &attr("withdrawn_share", 100u128.to_string())
+    );
+    // assert_eq!(
+    //     log_refund_assets,
+    //     &attr("refund_assets", "100uusd, 100asset0000")
+    // );
+}
+
+#[test]
+fn withdraw_imbalanced_liquidity() {
+    let pool_amount = Uint128::from(1000000000u128);
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: pool_amount,
+    }]);
+
+    deps.querier.with_tax(
+        Decimal::zero(),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&"addr0000".to_string(), &Uint128::from(2000000000u128))],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &pool_amount)],
+        ),
+    ]);
+
+    let msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::from(100u128),
+        fee: Uint128::from(4u128),
+        token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+    let _res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // store liquidity token
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(
+                vec![
+                    10, 13, 108, 105, 113, 117, 105, 100, 105, 116, 121, 48, 48, 48, 48,
+                ]
+                .into(),
+            ),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+    sync_reserves(deps.as_mut());
+
+    let withdraw_msg = |max_burn_amount: u128, receiver: Option<String>| {
+        ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: "addr0000".to_string(),
+            msg: to_binary(&Cw20HookMsg::WithdrawImbalancedLiquidity {
+                assets: vec![
+                    Asset {
+                        info: AssetInfo::NativeToken {
+                            denom: "uusd".to_string(),
+                        },
+                        amount: Uint128::from(100000000u128),
+                    },
+                    Asset {
+                        info: AssetInfo::Token {
+                            contract_addr: "asset0000".to_string(),
+                        },
+                        amount: Uint128::zero(),
+                    },
+                ],
+                max_burn_amount: Uint128::from(max_burn_amount),
+                receiver,
+                deadline: None,
+            })
+            .unwrap(),
+            amount: Uint128::from(150000000u128),
+        })
+    };
+
+    // only the liquidity token can execute this message
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("asset0000", &[]),
+        withdraw_msg(150000000, None),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+
+    // a one sided withdrawal costs more than its value
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("liquidity0000", &[]),
+        withdraw_msg(100000000, None),
+    )
+    .unwrap_err();
+    let burn_amount = match res {
+        ContractError::MaxBurnExceeded {
+            max_burn_amount,
+            burn_amount,
+        } => {
+            assert_eq!(max_burn_amount, Uint128::from(100000000u128));
+            burn_amount
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    };
+    assert!(burn_amount > Uint128::from(100000000u128));
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("liquidity0000", &[]),
+        withdraw_msg(150000000, None),
+    )
+    .unwrap();
+    let refund_share = Uint128::from(150000000u128) - burn_amount;
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "withdraw_imbalanced_liquidity"),
+            attr("sender", "addr0000"),
+            attr("receiver", "addr0000"),
+            attr("withdrawn_share", burn_amount.to_string()),
+            attr("refund_share", refund_share.to_string()),
+        ]
+    );
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "addr0000".to_string(),
+                amount: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: Uint128::from(100000000u128),
+                }],
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "liquidity0000".to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Burn {
+                    amount: burn_amount,
+                })
+                .unwrap(),
+                funds: vec![],
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "liquidity0000".to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: "addr0000".to_string(),
+                    amount: refund_share,
+                })
+                .unwrap(),
+                funds: vec![],
+            })),
+        ]
+    );
+
+    // the assets go to the receiver, the unburned LP tokens back to the sender
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("liquidity0000", &[]),
+        withdraw_msg(150000000, Some("addr0001".to_string())),
+    )
+    .unwrap();
+    assert_eq!(res.attributes[1], attr("sender", "addr0000"));
+    assert_eq!(res.attributes[2], attr("receiver", "addr0001"));
+    let refund_share = Uint128::from(res.attributes[4].value.parse::<u128>().unwrap());
+    assert_eq!(
+        res.messages[0],
+        SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0001".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(100000000u128),
+            }],
+        }))
+    );
+    assert_eq!(
+        res.messages[2],
+        SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "liquidity0000".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "addr0000".to_string(),
+                amount: refund_share,
+            })
+            .unwrap(),
+            funds: vec![],
+        }))
+    );
+}
+
+#[test]
+fn try_native_to_token() {
+    let total_share = Uint128::from(30000000000u128);
+    let asset_pool_amount = Uint128::from(20000000000u128);
+    let collateral_pool_amount = Uint128::from(30000000000u128);
+    let offer_amount = Uint128::from(1500000000u128);
+
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: collateral_pool_amount,
+    }]);
+
+    deps.querier.with_tax(
+        Decimal::zero(),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &total_share)],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &asset_pool_amount)],
+        ),
+    ]);
+
+    let msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::from(60u128),
+        fee: Uint128::from(4u128),
+        token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+
+    let env = mock_env();
+    let info = mock_info("addr0000", &[]);
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env, info, msg).unwrap();
+
+    // store liquidity token
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(
+                vec![
+                    10, 13, 108, 105, 113, 117, 105, 100, 105, 116, 121, 48, 48, 48, 48,
+                ]
+                .into(),
+            ),
+        }),
+    };
+
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+    sync_reserves(deps.as_mut());
+
+    // normal swap
+    let msg = ExecuteMsg::Swap {
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: offer_amount,
+        },
+        ask_asset: Asset {
+            info: AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+            amount: Uint128::from(0u128),
+        },
+        min_out_amount: Uint128::zero(),
+        to: None,
+        deadline: None,
+    };
+    let env = mock_env();
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: offer_amount,
+        }],
+    );
+    let res = execute(deps.as_mut(), env, info, msg).unwrap();
+    let msg_transfer = res.messages.first().expect("no message");
+
+    let expected_return_amount = Uint128::from(1486872698u128);
+
+    // check simulation res
+    deps.querier.with_balance(&[(
+        &MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: collateral_pool_amount,
+        }],
+    )]);
+    sync_reserves(deps.as_mut());
+
+    let simulation_res: SimulationResponse = query_simulation(
+        deps.as_ref(),
+        mock_env(),
+        Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: offer_amount,
+        },
+        AssetInfo::Token {
+            contract_addr: "asset0000".to_string(),
+        },
+    )
+    .unwrap();
+    assert_eq!(expected_return_amount, simulation_res.return_amount);
+    assert_eq!(Uint128::from(594987u128), simulation_res.commission_amount);
+    assert_eq!(
+        offer_amount,
+        simulation_res.return_amount
+            + simulation_res.commission_amount
+            + simulation_res.spread_amount
+    );
+
+    // check reverse simulation res
+    let reverse_simulation_res: ReverseSimulationResponse = query_reverse_simulation(
+        deps.as_ref(),
+        mock_env(),
+        AssetInfo::NativeToken {
+            denom: "uusd".to_string(),
+        },
+        Asset {
+            info: AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+            amount: expected_return_amount,
+        },
+    )
+    .unwrap();
+    // the reverse quote rounds up in favor of the pool
+    assert_eq!(
+        offer_amount + Uint128::from(1u128),
+        reverse_simulation_res.offer_amount
+    );
+    assert_eq!(
+        simulation_res.commission_amount,
+        reverse_simulation_res.commission_amount
+    );
+
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "swap"),
+            attr("sender", "addr0000"),
+            attr("receiver", "addr0000"),
+            attr("offer_asset", "uusd"),
+            attr("ask_asset", "asset0000"),
+            attr("offer_amount", offer_amount.to_string()),
+            attr("return_amount", expected_return_amount.to_string()),
+            attr("fee", simulation_res.fee.to_string()),
+            attr(
+                "commission_amount",
+                simulation_res.commission_amount.to_string()
+            ),
+            attr("admin_fee_amount", "0"),
+            attr("rates", "1000000000000000000, 1000000000000000000"),
+        ]
+    );
+
+    assert_eq!(
+        &SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "asset0000".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "addr0000".to_string(),
+                amount: expected_return_amount,
+            })
+            .unwrap(),
+            funds: vec![],
+        })),
+        msg_transfer,
+    );
+}
+
+#[test]
+fn try_token_to_native() {
+    let total_share = Uint128::from(20000000000u128);
+    let asset_pool_amount = Uint128::from(30000000000u128);
+    let collateral_pool_amount = Uint128::from(20000000000u128);
+    let offer_amount = Uint128::from(1500000000u128);
+
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: collateral_pool_amount,
+    }]);
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &total_share)],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &asset_pool_amount)],
+        ),
+    ]);
+
+    let msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::from(60u128),
+        fee: Uint128::from(4u128),
+        token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+
+    let env = mock_env();
+    let info = mock_info("addr0000", &[]);
+    // we can just call .unwrap() to assert this was a success
+    let _res = instantiate(deps.as_mut(), env, info, msg).unwrap();
+
+    // store liquidity token
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(
+                vec![
+                    10, 13, 108, 105, 113, 117, 105, 100, 105, 116, 121, 48, 48, 48, 48,
+                ]
+                .into(),
+            ),
+        }),
+    };
+
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+    sync_reserves(deps.as_mut());
+
+    // unauthorized access; can not execute swap directly for token swap
+    let msg = ExecuteMsg::Swap {
+        offer_asset: Asset {
+            info: AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+            amount: offer_amount,
+        },
+        ask_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::from(0u128),
+        },
+        min_out_amount: Uint128::zero(),
+        to: None,
+        deadline: None,
+    };
+    let env = mock_env();
+    let info = mock_info("addr0000", &[]);
+    let res = execute(deps.as_mut(), env, info, msg).unwrap_err();
+    match res {
+        ContractError::Unauthorized {} => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+
+    // normal sell
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0000".to_string(),
+        amount: offer_amount,
+        msg: to_binary(&Cw20HookMsg::Swap {
+            ask_asset: Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+                amount: Uint128::from(0u128),
+            },
+            min_out_amount: Uint128::zero(),
+            to: None,
+            deadline: None,
+        })
+        .unwrap(),
+    });
+    let env = mock_env();
+    let info = mock_info("asset0000", &[]);
+
+    let res = execute(deps.as_mut(), env, info, msg).unwrap();
+    let msg_transfer = res.messages.first().expect("no message");
+
+    // current price is 1.5, so expected return without spread is 1000
+    // 952.380952 = 20000 - 20000 * 30000 / (30000 + 1500)
+    let expected_return_amount = Uint128::from(1486872698u128);
+    // check simulation res
+    // return asset token balance as normal
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &total_share)],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &(asset_pool_amount))],
+        ),
+    ]);
+    sync_reserves(deps.as_mut());
+
+    let simulation_res: SimulationResponse = query_simulation(
+        deps.as_ref(),
+        mock_env(),
+        Asset {
+            amount: offer_amount,
+            info: AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        },
+        AssetInfo::NativeToken {
+            denom: "uusd".to_string(),
+        },
+    )
+    .unwrap();
+    assert_eq!(expected_return_amount, simulation_res.return_amount);
+
+    // check reverse simulation res
+    let reverse_simulation_res: ReverseSimulationResponse = query_reverse_simulation(
+        deps.as_ref(),
+        mock_env(),
+        AssetInfo::Token {
+            contract_addr: "asset0000".to_string(),
+        },
+        Asset {
+            amount: expected_return_amount,
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+        },
+    )
+    .unwrap();
+    // the reverse quote rounds up in favor of the pool
+    assert_eq!(
+        offer_amount + Uint128::from(1u128),
+        reverse_simulation_res.offer_amount
+    );
+
+    assert_eq!(
+        &SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0000".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: expected_return_amount - Uint128::from(1000000u128),
+            }],
+        })),
+        msg_transfer,
+    );
+
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "swap"),
+            attr("sender", "addr0000"),
+            attr("receiver", "addr0000"),
+            attr("offer_asset", "asset0000"),
+            attr("ask_asset", "uusd"),
+            attr("offer_amount", offer_amount.to_string()),
+            attr("return_amount", expected_return_amount.to_string()),
+            attr("fee", simulation_res.fee.to_string()),
+            attr(
+                "commission_amount",
+                simulation_res.commission_amount.to_string()
+            ),
+            attr("admin_fee_amount", "0"),
+            attr("rates", "1000000000000000000, 1000000000000000000"),
+        ]
+    );
+
+    // failed due to non asset token contract try to execute sell
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0000".to_string(),
+        amount: offer_amount,
+        msg: to_binary(&Cw20HookMsg::Swap {
+            ask_asset: Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+                amount: Uint128::from(0u128),
+            },
+            min_out_amount: Uint128::zero(),
+            to: None,
+            deadline: None,
+        })
+        .unwrap(),
+    });
+    let env = mock_env();
+    let info = mock_info("liquidity0000", &[]);
+    let res = execute(deps.as_mut(), env, info, msg).unwrap_err();
+    match res {
+        ContractError::Unauthorized {} => (),
+        _ => panic!("DO NOT ENTER HERE"),
+    }
+}
+
+#[test]
+fn simulation_spread_across_decimals() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(1_000_000_000u128),
+    }]);
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&"addr0000".to_string(), &Uint128::from(2_000_000_000u128))],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(
+                &MOCK_CONTRACT_ADDR.to_string(),
+                &Uint128::from(1_000u128 * 10u128.pow(18)),
+            )],
+        ),
+    ]);
+    deps.querier
+        .with_token_decimals(&[(&"asset0000".to_string(), 18u8)]);
+
+    let msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::from(100u128),
+        fee: Uint128::from(4u128),
+        token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+    let _res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // store liquidity token
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(
+                vec![
+                    10, 13, 108, 105, 113, 117, 105, 100, 105, 116, 121, 48, 48, 48, 48,
+                ]
+                .into(),
+            ),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+    sync_reserves(deps.as_mut());
+
+    let uusd_info = AssetInfo::NativeToken {
+        denom: "uusd".to_string(),
+    };
+    let asset_info = AssetInfo::Token {
+        contract_addr: "asset0000".to_string(),
+    };
+
+    // 100 uusd are worth 100 * 10^18 of the asset, the spread is the price impact on top of
+    // the commission, well under 1% in a balanced pool of 1000 each
+    let offer_value = Uint128::from(100u128 * 10u128.pow(18));
+    let simulation_res = query_simulation(
+        deps.as_ref(),
+        mock_env(),
+        Asset {
+            info: uusd_info.clone(),
+            amount: Uint128::from(100_000_000u128),
+        },
+        asset_info.clone(),
+    )
+    .unwrap();
+    assert!(!simulation_res.spread_amount.is_zero());
+    assert!(simulation_res.spread_amount < offer_value.multiply_ratio(1u128, 100u128));
+    assert_eq!(
+        simulation_res.return_amount
+            + simulation_res.commission_amount
+            + simulation_res.spread_amount,
+        offer_value
+    );
+
+    let offer_value = Uint128::from(100_000_000u128);
+    let simulation_res = query_simulation(
+        deps.as_ref(),
+        mock_env(),
+        Asset {
+            info: asset_info.clone(),
+            amount: Uint128::from(100u128 * 10u128.pow(18)),
+        },
+        uusd_info.clone(),
+    )
+    .unwrap();
+    assert!(!simulation_res.spread_amount.is_zero());
+    assert!(simulation_res.spread_amount < offer_value.multiply_ratio(1u128, 100u128));
+    assert_eq!(
+        simulation_res.return_amount
+            + simulation_res.commission_amount
+            + simulation_res.spread_amount,
+        offer_value
+    );
+
+    // the reverse spread is what the offer costs beyond the value of the ask and commission
+    let reverse_simulation_res = query_reverse_simulation(
+        deps.as_ref(),
+        mock_env(),
+        uusd_info.clone(),
+        Asset {
+            info: asset_info.clone(),
+            amount: Uint128::from(100u128 * 10u128.pow(18)),
+        },
+    )
+    .unwrap();
+    assert!(!reverse_simulation_res.spread_amount.is_zero());
+    assert!(
+        reverse_simulation_res.spread_amount
+            < reverse_simulation_res
+                .offer_amount
+                .multiply_ratio(1u128, 100u128)
+    );
+
+    let reverse_simulation_res = query_reverse_simulation(
+        deps.as_ref(),
+        mock_env(),
+        asset_info,
+        Asset {
+            info: uusd_info,
+            amount: Uint128::from(100_000_000u128),
+        },
+    )
+    .unwrap();
+    assert!(!reverse_simulation_res.spread_amount.is_zero());
+    assert!(
+        reverse_simulation_res.spread_amount
+            < reverse_simulation_res
+                .offer_amount
+                .multiply_ratio(1u128, 100u128)
+    );
+}
+
+#[test]
+fn swap_errors() {
+    let total_share = Uint128::from(30000000000u128);
+    let asset_pool_amount = Uint128::from(20000000000u128);
+    let collateral_pool_amount = Uint128::from(30000000000u128);
+    let offer_amount = Uint128::from(1500000000u128);
+
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: collateral_pool_amount,
+    }]);
+
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &total_share)],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &asset_pool_amount)],
+        ),
+    ]);
+
+    let msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::from(60u128),
+        fee: Uint128::from(4u128),
+        token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+
+    let env = mock_env();
+    let info = mock_info("addr0000", &[]);
+    let _res = instantiate(deps.as_mut(), env, info, msg).unwrap();
+    sync_reserves(deps.as_mut());
+
+    let swap_msg = |ask_info: AssetInfo, min_out_amount: Uint128| ExecuteMsg::Swap {
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: offer_amount,
+        },
+        ask_asset: Asset {
+            info: ask_info,
+            amount: Uint128::zero(),
+        },
+        min_out_amount,
+        to: None,
+        deadline: None,
+    };
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: offer_amount,
+        }],
+    );
+
+    // the return amount is under the minimum
+    let msg = swap_msg(
+        AssetInfo::Token {
+            contract_addr: "asset0000".to_string(),
+        },
+        Uint128::from(1500000000u128),
+    );
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+    assert_eq!(
+        res,
+        ContractError::SlippageExceeded {
+            min_out_amount: Uint128::from(1500000000u128),
+            out_amount: Uint128::from(1486872698u128),
+        }
+    );
+
+    // the ask asset is not in the pool
+    let msg = swap_msg(
+        AssetInfo::NativeToken {
+            denom: "uluna".to_string(),
+        },
+        Uint128::zero(),
+    );
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap_err();
+    assert_eq!(
+        res,
+        ContractError::UnknownAsset {
+            asset: "uluna".to_string(),
+        }
+    );
+
+    // the ask side has no liquidity
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &total_share)],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::zero())],
+        ),
+    ]);
+    sync_reserves(deps.as_mut());
+    let msg = swap_msg(
+        AssetInfo::Token {
+            contract_addr: "asset0000".to_string(),
+        },
+        Uint128::zero(),
+    );
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+    assert_eq!(res, ContractError::EmptyPool {});
+}
+
+#[test]
+fn ramp_amp() {
+    let mut deps = mock_dependencies(&[]);
+
+    deps.querier.with_token_balances(&[(
+        &"asset0000".to_string(),
+        &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::zero())],
+    )]);
+
+    let msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::from(60u128),
+        fee: Uint128::from(4u128),
+        token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+    let _res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // store liquidity token
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(
+                vec![
+                    10, 13, 108, 105, 113, 117, 105, 100, 105, 116, 121, 48, 48, 48, 48,
+                ]
+                .into(),
+            ),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    assert_eq!(
+        query_amp(deps.as_ref(), mock_env()).unwrap(),
+        AmpResponse {
+            amp: Uint128::from(60u128),
+            future_amp: Uint128::from(60u128),
+            future_time: 0,
+        }
+    );
+
+    let now = mock_env().block.time.seconds();
+    let ramp_msg = |future_amp: u128, future_time: u64| ExecuteMsg::RampAmp {
+        future_amp: Uint128::from(future_amp),
+        future_time,
+    };
+
+    // only the owner can ramp
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0001", &[]),
+        ramp_msg(120, now + 2 * 86_400),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+
+    let info = mock_info("addr0000", &[]);
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        ramp_msg(120, now + 3_600),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::InvalidRampTime {});
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        ramp_msg(0, now + 2 * 86_400),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::InvalidAmp {});
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        ramp_msg(601, now + 2 * 86_400),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::AmpChangeTooLarge {});
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        ramp_msg(5, now + 2 * 86_400),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::AmpChangeTooLarge {});
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        ramp_msg(120, now + 2 * 86_400),
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "ramp_amp"),
+            attr("initial_amp", "60"),
+            attr("future_amp", "120"),
+            attr("future_time", (now + 2 * 86_400).to_string()),
+        ]
+    );
+
+    // halfway through the ramp
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(86_400);
+    assert_eq!(
+        query_amp(deps.as_ref(), env.clone()).unwrap(),
+        AmpResponse {
+            amp: Uint128::from(90u128),
+            future_amp: Uint128::from(120u128),
+            future_time: now + 2 * 86_400,
+        }
+    );
+    let pair_info: PairInfo = query_pair_info(deps.as_ref(), env.clone()).unwrap();
+    assert_eq!(pair_info.amplification, Uint128::from(90u128));
+
+    // a new ramp can not start right after the last one
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        info.clone(),
+        ramp_msg(100, now + 3 * 86_400),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::InvalidRampTime {});
+
+    // stopping keeps the current amp
+    let res = execute(deps.as_mut(), env, info, ExecuteMsg::StopRampAmp {}).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![attr("action", "stop_ramp_amp"), attr("amp", "90")]
+    );
+
+    let mut env = mock_env();
+    env.block.time = env.block.time.plus_seconds(3 * 86_400);
+    assert_eq!(
+        query_amp(deps.as_ref(), env).unwrap(),
+        AmpResponse {
+            amp: Uint128::from(90u128),
+            future_amp: Uint128::from(90u128),
+            future_time: now + 86_400,
+        }
+    );
+}
+
+#[test]
+fn admin() {
+    let total_share = Uint128::from(30000000000u128);
+    let asset_pool_amount = Uint128::from(20000000000u128);
+    let collateral_pool_amount = Uint128::from(30000000000u128);
+    let offer_amount = Uint128::from(1500000000u128);
+
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: collateral_pool_amount,
+    }]);
+
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &total_share)],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &asset_pool_amount)],
+        ),
+    ]);
+
+    let msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::from(60u128),
+        fee: Uint128::from(4u128),
+        token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: Some("owner0000".to_string()),
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+    let _res = instantiate(deps.as_mut(), mock_env(), mock_info("factory", &[]), msg).unwrap();
+    sync_reserves(deps.as_mut());
+
+    assert_eq!(
+        query_config(deps.as_ref(), mock_env()).unwrap(),
+        ConfigResponse {
+            owner: "owner0000".to_string(),
+            pending_owner: None,
+            guardian: None,
+            fee: Uint128::from(4u128),
+            admin_fee: Uint128::zero(),
+            fee_collector: None,
+            flash_loan_fee: Uint128::zero(),
+            fee_multiplier: Uint128::zero(),
+            amp: Uint128::from(60u128),
+            future_amp: Uint128::from(60u128),
+            future_time: 0,
+            paused: false,
+            pause_expiry_height: None,
+            base_pool: None,
+            rate_providers: vec![],
+        }
+    );
+
+    let owner = mock_info("owner0000", &[]);
+
+    // the instantiator is not the owner when one is given
+    let msg = ExecuteMsg::UpdateFee {
+        fee: Uint128::from(10u128),
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("factory", &[]), msg).unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+
+    let msg = ExecuteMsg::UpdateFee {
+        fee: Uint128::from(5001u128),
+    };
+    let res = execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap_err();
+    assert_eq!(res, ContractError::InvalidFee {});
+
+    let msg = ExecuteMsg::UpdateFee {
+        fee: Uint128::from(10u128),
+    };
+    let res = execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![attr("action", "update_fee"), attr("fee", "10")]
+    );
+
+    // swaps are blocked while paused
+    let pause_msg = ExecuteMsg::Pause {
+        expiry_height: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        pause_msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+
+    let res = execute(deps.as_mut(), mock_env(), owner.clone(), pause_msg.clone()).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![attr("action", "pause"), attr("expiry_height", "none")]
+    );
+
+    let swap_msg = ExecuteMsg::Swap {
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: offer_amount,
+        },
+        ask_asset: Asset {
+            info: AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+            amount: Uint128::zero(),
+        },
+        min_out_amount: Uint128::zero(),
+        to: None,
+        deadline: None,
+    };
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: offer_amount,
+        }],
+    );
+    let res = execute(deps.as_mut(), mock_env(), info.clone(), swap_msg.clone()).unwrap_err();
+    assert_eq!(res, ContractError::Paused {});
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        owner.clone(),
+        ExecuteMsg::Unpause {},
+    )
+    .unwrap();
+    assert_eq!(res.attributes, vec![attr("action", "unpause")]);
+    execute(deps.as_mut(), mock_env(), info, swap_msg).unwrap();
+
+    // ownership moves only once the proposed owner accepts
+    let msg = ExecuteMsg::ProposeNewOwner {
+        owner: "owner0001".to_string(),
+    };
+    let res = execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "propose_new_owner"),
+            attr("pending_owner", "owner0001")
+        ]
+    );
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        ExecuteMsg::AcceptOwnership {},
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+
+    let config = query_config(deps.as_ref(), mock_env()).unwrap();
+    assert_eq!(config.owner, "owner0000".to_string());
+    assert_eq!(config.pending_owner, Some("owner0001".to_string()));
+    assert_eq!(config.fee, Uint128::from(10u128));
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("owner0001", &[]),
+        ExecuteMsg::AcceptOwnership {},
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "accept_ownership"),
+            attr("owner", "owner0001")
+        ]
+    );
+
+    let config = query_config(deps.as_ref(), mock_env()).unwrap();
+    assert_eq!(config.owner, "owner0001".to_string());
+    assert_eq!(config.pending_owner, None);
+
+    let res = execute(deps.as_mut(), mock_env(), owner, pause_msg).unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+}
+
+#[test]
+fn pause() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(100u128),
+    }]);
+
+    deps.querier.with_tax(
+        Decimal::zero(),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&"addr0000".to_string(), &Uint128::from(100u128))],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::from(100u128))],
+        ),
+    ]);
+
+    let msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::from(60u128),
+        fee: Uint128::from(4u128),
+        token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+    let _res = instantiate(deps.as_mut(), mock_env(), mock_info("owner0000", &[]), msg).unwrap();
+
+    // store liquidity token
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(
+                vec![
+                    10, 13, 108, 105, 113, 117, 105, 100, 105, 116, 121, 48, 48, 48, 48,
+                ]
+                .into(),
+            ),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+    sync_reserves(deps.as_mut());
+
+    // only the owner sets the guardian
+    let msg = ExecuteMsg::UpdateGuardian {
+        guardian: Some("guardian0000".to_string()),
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("guardian0000", &[]),
+        msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("owner0000", &[]), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "update_guardian"),
+            attr("guardian", "guardian0000")
+        ]
+    );
+
+    let guardian = mock_info("guardian0000", &[]);
+    let height = mock_env().block.height;
+
+    let msg = ExecuteMsg::Pause {
+        expiry_height: Some(height),
+    };
+    let res = execute(deps.as_mut(), mock_env(), guardian.clone(), msg).unwrap_err();
+    assert_eq!(res, ContractError::InvalidExpiryHeight {});
+
+    let msg = ExecuteMsg::Pause {
+        expiry_height: Some(height + 100),
+    };
+    let res = execute(deps.as_mut(), mock_env(), guardian, msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "pause"),
+            attr("expiry_height", (height + 100).to_string())
+        ]
+    );
+
+    let config = query_config(deps.as_ref(), mock_env()).unwrap();
+    assert_eq!(config.guardian, Some("guardian0000".to_string()));
+    assert!(config.paused);
+    assert_eq!(config.pause_expiry_height, Some(height + 100));
+
+    // deposits and single asset withdrawals are blocked
+    let msg = ExecuteMsg::ProvideLiquidity {
+        assets: vec![
+            Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+                amount: Uint128::from(100u128),
+            },
+            Asset {
+                info: AssetInfo::Token {
+                    contract_addr: "asset0000".to_string(),
+                },
+                amount: Uint128::from(100u128),
+            },
+        ],
+        min_out_amount: Uint128::zero(),
+        receiver: None,
+        deadline: None,
+    };
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(100u128),
+        }],
+    );
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap_err();
+    assert_eq!(res, ContractError::Paused {});
+
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0000".to_string(),
+        msg: to_binary(&Cw20HookMsg::WithdrawSingleLiquidity {
+            asset: Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+                amount: Uint128::zero(),
+            },
+            min_out_amount: Uint128::zero(),
+            receiver: None,
+            deadline: None,
+        })
+        .unwrap(),
+        amount: Uint128::from(10u128),
+    });
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("liquidity0000", &[]),
+        msg,
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Paused {});
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        ExecuteMsg::Sync {},
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Paused {});
+
+    // balanced withdrawals keep working
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0000".to_string(),
+        msg: to_binary(&Cw20HookMsg::WithdrawLiquidity {
+            min_assets: vec![],
+            receiver: None,
+            deadline: None,
+        })
+        .unwrap(),
+        amount: Uint128::from(10u128),
+    });
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("liquidity0000", &[]),
+        msg,
+    )
+    .unwrap();
+    assert_eq!(res.messages.len(), 3);
+
+    // the pause lifts by itself at the expiry height
+    let mut env = mock_env();
+    env.block.height = height + 100;
+    let config = query_config(deps.as_ref(), env).unwrap();
+    assert!(!config.paused);
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        ExecuteMsg::Unpause {},
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("owner0000", &[]),
+        ExecuteMsg::Unpause {},
+    )
+    .unwrap();
+    assert_eq!(res.attributes, vec![attr("action", "unpause")]);
+    let config = query_config(deps.as_ref(), mock_env()).unwrap();
+    assert!(!config.paused);
+    assert_eq!(config.pause_expiry_height, None);
+}
+
+#[test]
+fn admin_fees() {
+    let total_share = Uint128::from(30000000000u128);
+    let asset_pool_amount = Uint128::from(20000000000u128);
+    let collateral_pool_amount = Uint128::from(30000000000u128);
+    let offer_amount = Uint128::from(1500000000u128);
+
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: collateral_pool_amount,
+    }]);
+
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &total_share)],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &asset_pool_amount)],
+        ),
+    ]);
+
+    let msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::from(60u128),
+        fee: Uint128::from(4u128),
+        token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+    let _res = instantiate(deps.as_mut(), mock_env(), mock_info("owner0000", &[]), msg).unwrap();
+
+    // store liquidity token
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(
+                vec![
+                    10, 13, 108, 105, 113, 117, 105, 100, 105, 116, 121, 48, 48, 48, 48,
+                ]
+                .into(),
+            ),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+    sync_reserves(deps.as_mut());
+
+    let owner = mock_info("owner0000", &[]);
+    let msg = ExecuteMsg::UpdateAdminFee {
+        admin_fee: Uint128::from(5000u128),
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::Unauthorized {});
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        owner.clone(),
+        ExecuteMsg::UpdateAdminFee {
+            admin_fee: Uint128::from(10001u128),
+        },
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::InvalidFee {});
+
+    let res = execute(deps.as_mut(), mock_env(), owner.clone(), msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "update_admin_fee"),
+            attr("admin_fee", "5000")
+        ]
+    );
+
+    // half of the swap fee is held for the protocol
+    let msg = ExecuteMsg::Swap {
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: offer_amount,
+        },
+        ask_asset: Asset {
+            info: AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+            amount: Uint128::zero(),
+        },
+        min_out_amount: Uint128::zero(),
+        to: None,
+        deadline: None,
+    };
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: offer_amount,
+        }],
+    );
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    assert_eq!(
+        res.attributes[7..],
+        [
+            attr("fee", "4"),
+            attr("commission_amount", "594987"),
+            attr("admin_fee_amount", "297493"),
+            attr("rates", "1000000000000000000, 1000000000000000000"),
+        ]
+    );
+
+    let admin_fee_amount = Uint128::from(297493u128);
+    assert_eq!(
+        query_admin_fees(deps.as_ref()).unwrap().assets,
+        vec![
+            Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+                amount: Uint128::zero(),
+            },
+            Asset {
+                info: AssetInfo::Token {
+                    contract_addr: "asset0000".to_string(),
+                },
+                amount: admin_fee_amount,
+            },
+        ]
+    );
+
+    // the reserves pay out the return and the admin fees
+    let pool = query_pool(deps.as_ref()).unwrap();
+    assert_eq!(
+        pool.assets,
+        vec![
+            Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+                amount: collateral_pool_amount + offer_amount,
+            },
+            Asset {
+                info: AssetInfo::Token {
+                    contract_addr: "asset0000".to_string(),
+                },
+                amount: asset_pool_amount - Uint128::from(1486872698u128) - admin_fee_amount,
+            },
+        ]
+    );
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        ExecuteMsg::WithdrawAdminFees {},
+    )
+    .unwrap_err();
+    assert_eq!(res, ContractError::NoFeeCollector {});
+
+    let msg = ExecuteMsg::UpdateFeeCollector {
+        fee_collector: "collector0000".to_string(),
+    };
+    let res = execute(deps.as_mut(), mock_env(), owner, msg).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "update_fee_collector"),
+            attr("fee_collector", "collector0000")
+        ]
+    );
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        ExecuteMsg::WithdrawAdminFees {},
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "asset0000".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: "collector0000".to_string(),
+                amount: admin_fee_amount,
+            })
+            .unwrap(),
+            funds: vec![],
+        }))]
+    );
+    assert!(query_admin_fees(deps.as_ref())
+        .unwrap()
+        .assets
+        .iter()
+        .all(|asset| asset.amount.is_zero()));
+
+    let config = query_config(deps.as_ref(), mock_env()).unwrap();
+    assert_eq!(config.admin_fee, Uint128::from(5000u128));
+    assert_eq!(config.fee_collector, Some("collector0000".to_string()));
+}
+
+#[test]
+fn skim_and_sync() {
+    let pool_amount = Uint128::from(1000000u128);
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: pool_amount,
+    }]);
+
+    deps.querier.with_tax(
+        Decimal::zero(),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&"addr0000".to_string(), &Uint128::from(2000000u128))],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &pool_amount)],
+        ),
+    ]);
+
+    let msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::from(60u128),
+        fee: Uint128::from(4u128),
+        token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+    let _res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // store liquidity token
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(
+                vec![
+                    10, 13, 108, 105, 113, 117, 105, 100, 105, 116, 121, 48, 48, 48, 48,
+                ]
+                .into(),
+            ),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        ExecuteMsg::Sync {},
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "sync"),
+            attr("reserves", "1000000uusd, 1000000asset0000"),
+        ]
+    );
+
+    let offer_asset = Asset {
+        info: AssetInfo::NativeToken {
+            denom: "uusd".to_string(),
+        },
+        amount: Uint128::from(10000u128),
+    };
+    let ask_asset_info = AssetInfo::Token {
+        contract_addr: "asset0000".to_string(),
+    };
+    let simulation_res = query_simulation(
+        deps.as_ref(),
+        mock_env(),
+        offer_asset.clone(),
+        ask_asset_info.clone(),
+    )
+    .unwrap();
+
+    // a direct transfer to the pair does not move the price
+    let donation_amount = Uint128::from(500000u128);
+    deps.querier.with_balance(&[(
+        &MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: pool_amount + donation_amount,
+        }],
+    )]);
+    assert_eq!(
+        query_pool(deps.as_ref()).unwrap().assets[0].amount,
+        pool_amount
+    );
+    assert_eq!(
+        query_simulation(
+            deps.as_ref(),
+            mock_env(),
+            offer_asset.clone(),
+            ask_asset_info.clone()
+        )
+        .unwrap(),
+        simulation_res
+    );
+
+    // anyone can skim the surplus
+    let msg = ExecuteMsg::Skim {
+        to: "addr0001".to_string(),
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("addr0002", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0001".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: donation_amount,
+            }],
+        }))]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "skim"),
+            attr("to", "addr0001"),
+            attr("assets", "500000uusd, 0asset0000"),
+        ]
+    );
+
+    // or sync it into the reserves for the liquidity providers, uusd gets cheaper
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0002", &[]),
+        ExecuteMsg::Sync {},
+    )
+    .unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "sync"),
+            attr("reserves", "1500000uusd, 1000000asset0000"),
+        ]
+    );
+    assert_eq!(
+        query_pool(deps.as_ref()).unwrap().assets[0].amount,
+        pool_amount + donation_amount
+    );
+    assert!(
+        query_simulation(deps.as_ref(), mock_env(), offer_asset, ask_asset_info)
+            .unwrap()
+            .return_amount
+            < simulation_res.return_amount
+    );
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0002", &[]),
+        ExecuteMsg::Skim {
+            to: "addr0001".to_string(),
+        },
+    )
+    .unwrap();
+    assert!(res.messages.is_empty());
+}
+
+#[test]
+fn cumulative_prices() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(2000000000u128),
+    }]);
+
+    deps.querier.with_tax(
+        Decimal::zero(),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&"addr0000".to_string(), &Uint128::from(3000000000u128))],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(
+                &MOCK_CONTRACT_ADDR.to_string(),
+                &Uint128::from(1000000000u128),
+            )],
+        ),
+    ]);
+
+    let msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::from(60u128),
+        fee: Uint128::from(4u128),
+        token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+    let _res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // store liquidity token
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(
+                vec![
+                    10, 13, 108, 105, 113, 117, 105, 100, 105, 116, 121, 48, 48, 48, 48,
+                ]
+                .into(),
+            ),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+    sync_reserves(deps.as_mut());
+
+    let env_after = |seconds: u64| {
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(seconds);
+        env
+    };
+    let start = mock_env().block.time.seconds();
+
+    // the pool was empty until the sync in the first block
+    let res = query_cumulative_prices(deps.as_ref(), mock_env()).unwrap();
+    assert_eq!(
+        res,
+        CumulativePricesResponse {
+            timestamp: start,
+            price_cumulatives: vec![
+                PriceCumulative {
+                    offer_asset_info: AssetInfo::NativeToken {
+                        denom: "uusd".to_string(),
+                    },
+                    ask_asset_info: AssetInfo::Token {
+                        contract_addr: "asset0000".to_string(),
+                    },
+                    price_cumulative: Uint128::zero(),
+                },
+                PriceCumulative {
+                    offer_asset_info: AssetInfo::Token {
+                        contract_addr: "asset0000".to_string(),
+                    },
+                    ask_asset_info: AssetInfo::NativeToken {
+                        denom: "uusd".to_string(),
+                    },
+                    price_cumulative: Uint128::zero(),
+                },
+            ],
+            precision: 18,
+        }
+    );
+
+    // the abundant uusd is cheaper than par
+    let prices: Vec<Uint128> = query_cumulative_prices(deps.as_ref(), env_after(1))
+        .unwrap()
+        .price_cumulatives
+        .iter()
+        .map(|price| price.price_cumulative)
+        .collect();
+    let one = Uint128::from(1000000000000000000u128);
+    assert!(prices[0] < one && prices[1] > one);
+
+    // the first action of a block accumulates the prices held since the last one
+    let msg = ExecuteMsg::Swap {
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::from(500000000u128),
+        },
+        ask_asset: Asset {
+            info: AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+            amount: Uint128::zero(),
+        },
+        min_out_amount: Uint128::zero(),
+        to: None,
+        deadline: None,
+    };
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(500000000u128),
+        }],
+    );
+    execute(deps.as_mut(), env_after(100), info.clone(), msg.clone()).unwrap();
+    execute(deps.as_mut(), env_after(100), info, msg).unwrap();
+
+    let cumulatives = |res: &CumulativePricesResponse| -> Vec<Uint128> {
+        res.price_cumulatives
+            .iter()
+            .map(|price| price.price_cumulative)
+            .collect()
+    };
+    let res = query_observe(deps.as_ref(), env_after(300), vec![200, 250, 0]).unwrap();
+    assert_eq!(res.observations[0].timestamp, start + 100);
+    assert_eq!(
+        cumulatives(&res.observations[0]),
+        vec![
+            Uint128::from(prices[0].u128() * 100),
+            Uint128::from(prices[1].u128() * 100)
+        ]
+    );
+    assert_eq!(
+        cumulatives(&res.observations[1]),
+        vec![
+            Uint128::from(prices[0].u128() * 50),
+            Uint128::from(prices[1].u128() * 50)
+        ]
+    );
+
+    // past the latest observation the current prices are extrapolated
+    let current = query_cumulative_prices(deps.as_ref(), env_after(300)).unwrap();
+    assert_eq!(res.observations[2], current);
+    let current_prices = cumulatives(&current);
+    assert!(
+        current_prices[0] - Uint128::from(prices[0].u128() * 100)
+            < Uint128::from(prices[0].u128() * 200)
+    );
+
+    let res = query_observe(deps.as_ref(), env_after(300), vec![301]).unwrap_err();
+    assert_eq!(
+        res,
+        ContractError::ObservationUnavailable { seconds_ago: 301 }
+    );
+}
+
+#[test]
+fn flash_loan() {
+    let pool_amount = Uint128::from(1000000u128);
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: pool_amount,
+    }]);
+
+    deps.querier.with_tax(
+        Decimal::zero(),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&"addr0000".to_string(), &Uint128::from(2000000u128))],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &pool_amount)],
+        ),
+    ]);
+
+    let msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::from(60u128),
+        fee: Uint128::from(4u128),
+        token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+    let _res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // store liquidity token
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(
+                vec![
+                    10, 13, 108, 105, 113, 117, 105, 100, 105, 116, 121, 48, 48, 48, 48,
+                ]
+                .into(),
+            ),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+    sync_reserves(deps.as_mut());
+
+    // only the owner sets the flash loan fee, within the fee cap
+    let msg = ExecuteMsg::UpdateFlashLoanFee {
+        flash_loan_fee: Uint128::from(9u128),
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("addr0001", &[]), msg);
+    assert_eq!(res, Err(ContractError::Unauthorized {}));
+
+    let msg = ExecuteMsg::UpdateFlashLoanFee {
+        flash_loan_fee: Uint128::from(5001u128),
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg);
+    assert_eq!(res, Err(ContractError::InvalidFee {}));
+
+    let msg = ExecuteMsg::UpdateFlashLoanFee {
+        flash_loan_fee: Uint128::from(9u128),
+    };
+    let _res = execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+    assert_eq!(
+        query_config(deps.as_ref(), mock_env())
+            .unwrap()
+            .flash_loan_fee,
+        Uint128::from(9u128)
+    );
+
+    let msg = ExecuteMsg::UpdateAdminFee {
+        admin_fee: Uint128::from(5000u128),
+    };
+    let _res = execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // the loan is capped by the reserve
+    let callback_msg = to_binary(&"callback").unwrap();
+    let msg = ExecuteMsg::FlashLoan {
+        assets: vec![Asset {
+            info: AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+            amount: pool_amount + Uint128::from(1u128),
+        }],
+        callback_msg: callback_msg.clone(),
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("borrower", &[]), msg);
+    assert_eq!(
+        res,
+        Err(ContractError::FlashLoanTooLarge {
+            asset: "asset0000".to_string(),
+            amount: pool_amount + Uint128::from(1u128),
+            reserve: pool_amount,
+        })
+    );
+
+    let loan_amount = Uint128::from(100000u128);
+    let msg = ExecuteMsg::FlashLoan {
+        assets: vec![Asset {
+            info: AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+            amount: loan_amount,
+        }],
+        callback_msg: callback_msg.clone(),
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("borrower", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "asset0000".to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: "borrower".to_string(),
+                    amount: loan_amount,
+                })
+                .unwrap(),
+                funds: vec![],
+            })),
+            SubMsg {
+                msg: WasmMsg::Execute {
+                    contract_addr: "borrower".to_string(),
+                    msg: callback_msg.clone(),
+                    funds: vec![],
+                }
+                .into(),
+                gas_limit: None,
+                id: 2,
+                reply_on: ReplyOn::Success,
+            },
+        ]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "flash_loan"),
+            attr("borrower", "borrower"),
+            attr("assets", "100000asset0000"),
+            attr("fees", "0uusd, 90asset0000"),
+        ]
+    );
+
+    // the pair is locked until the callback returns
+    let msg = ExecuteMsg::Swap {
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::from(1000u128),
+        },
+        ask_asset: Asset {
+            info: AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+            amount: Uint128::zero(),
+        },
+        min_out_amount: Uint128::zero(),
+        to: None,
+        deadline: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(
+            "borrower",
+            &[Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(1000u128),
+            }],
+        ),
+        msg,
+    );
+    assert_eq!(res, Err(ContractError::FlashLoanInProgress {}));
+
+    let msg = ExecuteMsg::ProvideLiquidity {
+        assets: vec![Asset {
+            info: AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+            amount: loan_amount,
+        }],
+        min_out_amount: Uint128::zero(),
+        receiver: None,
+        deadline: None,
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("borrower", &[]), msg);
+    assert_eq!(res, Err(ContractError::FlashLoanInProgress {}));
+
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "borrower".to_string(),
+        amount: Uint128::from(100u128),
+        msg: to_binary(&Cw20HookMsg::WithdrawLiquidity {
+            min_assets: vec![],
+            receiver: None,
+            deadline: None,
+        })
+        .unwrap(),
+    });
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("liquidity0000", &[]),
+        msg,
+    );
+    assert_eq!(res, Err(ContractError::FlashLoanInProgress {}));
+
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("borrower", &[]),
+        ExecuteMsg::Sync {},
+    );
+    assert_eq!(res, Err(ContractError::FlashLoanInProgress {}));
+
+    let msg = ExecuteMsg::FlashLoan {
+        assets: vec![Asset {
+            info: AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+            amount: loan_amount,
+        }],
+        callback_msg: callback_msg.clone(),
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("borrower", &[]), msg);
+    assert_eq!(res, Err(ContractError::FlashLoanInProgress {}));
+
+    // the reply fails unless the loan came back with the fee
+    let callback_reply = Reply {
+        id: 2,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    };
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&"addr0000".to_string(), &Uint128::from(2000000u128))],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(
+                &MOCK_CONTRACT_ADDR.to_string(),
+                &(pool_amount + Uint128::from(89u128)),
+            )],
+        ),
+    ]);
+    let res = reply(deps.as_mut(), mock_env(), callback_reply.clone());
+    assert_eq!(
+        res,
+        Err(ContractError::FlashLoanNotRepaid {
+            asset: "asset0000".to_string(),
+            expected: pool_amount + Uint128::from(90u128),
+            amount: pool_amount + Uint128::from(89u128),
+        })
+    );
+
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&"addr0000".to_string(), &Uint128::from(2000000u128))],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(
+                &MOCK_CONTRACT_ADDR.to_string(),
+                &(pool_amount + Uint128::from(90u128)),
+            )],
+        ),
+    ]);
+    let res = reply(deps.as_mut(), mock_env(), callback_reply).unwrap();
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "repay_flash_loan"),
+            attr("borrower", "borrower"),
+            attr("fees", "0uusd, 90asset0000"),
+        ]
+    );
+
+    // the fee is split between the reserves and the admin fees
+    assert_eq!(
+        query_pool(deps.as_ref()).unwrap().assets[1].amount,
+        pool_amount + Uint128::from(45u128)
+    );
+    assert_eq!(
+        query_admin_fees(deps.as_ref()).unwrap().assets[1].amount,
+        Uint128::from(45u128)
+    );
+
+    // and the pair is unlocked
+    sync_reserves(deps.as_mut());
+
+    // the fee rounds up, so small loans are not free
+    let msg = ExecuteMsg::FlashLoan {
+        assets: vec![Asset {
+            info: AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+            amount: Uint128::from(100u128),
+        }],
+        callback_msg,
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("borrower", &[]), msg).unwrap();
+    assert_eq!(res.attributes[3], attr("fees", "0uusd, 1asset0000"));
+}
+
+#[test]
+fn flash_loan_taxed_native() {
+    let pool_amount = Uint128::from(1000000u128);
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: pool_amount,
+    }]);
+
+    deps.querier.with_tax(
+        Decimal::percent(1),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&"addr0000".to_string(), &Uint128::from(2000000u128))],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &pool_amount)],
+        ),
+    ]);
+
+    let msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::from(60u128),
+        fee: Uint128::from(4u128),
+        token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+    let _res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // store liquidity token
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(
+                vec![
+                    10, 13, 108, 105, 113, 117, 105, 100, 105, 116, 121, 48, 48, 48, 48,
+                ]
+                .into(),
+            ),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+    sync_reserves(deps.as_mut());
+
+    let msg = ExecuteMsg::UpdateFlashLoanFee {
+        flash_loan_fee: Uint128::from(9u128),
+    };
+    let _res = execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // the borrower gets the full amount despite the tax
+    let loan_amount = Uint128::from(100000u128);
+    let callback_msg = to_binary(&"callback").unwrap();
+    let msg = ExecuteMsg::FlashLoan {
+        assets: vec![Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: loan_amount,
+        }],
+        callback_msg,
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("borrower", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages[0],
+        SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "borrower".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: loan_amount,
+            }],
+        }))
+    );
+    assert_eq!(res.attributes[3], attr("fees", "90uusd, 0asset0000"));
+
+    // the pair paid 1000uusd of tax on the loan, returning the loan and the fee alone falls
+    // short by it
+    let callback_reply = Reply {
+        id: 2,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    };
+    deps.querier.with_balance(&[(
+        &MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: pool_amount + Uint128::from(90u128) - Uint128::from(1000u128),
+        }],
+    )]);
+    let res = reply(deps.as_mut(), mock_env(), callback_reply.clone());
+    assert_eq!(
+        res,
+        Err(ContractError::FlashLoanNotRepaid {
+            asset: "uusd".to_string(),
+            expected: pool_amount + Uint128::from(90u128),
+            amount: pool_amount + Uint128::from(90u128) - Uint128::from(1000u128),
+        })
+    );
+
+    deps.querier.with_balance(&[(
+        &MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: pool_amount + Uint128::from(90u128),
+        }],
+    )]);
+    let res = reply(deps.as_mut(), mock_env(), callback_reply).unwrap();
+    assert_eq!(res.attributes[0], attr("action", "repay_flash_loan"));
+    assert_eq!(
+        query_pool(deps.as_ref()).unwrap().assets[0].amount,
+        pool_amount + Uint128::from(90u128)
+    );
+}
+
+#[test]
+fn swap_exact_out() {
+    let pool_amount = Uint128::from(1000000u128);
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: pool_amount,
+    }]);
+
+    deps.querier.with_tax(
+        Decimal::zero(),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&"addr0000".to_string(), &Uint128::from(2000000u128))],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &pool_amount)],
+        ),
+    ]);
+
+    let msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::from(60u128),
+        fee: Uint128::from(4u128),
+        token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+    let _res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // store liquidity token
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(
+                vec![
+                    10, 13, 108, 105, 113, 117, 105, 100, 105, 116, 121, 48, 48, 48, 48,
+                ]
+                .into(),
+            ),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+    sync_reserves(deps.as_mut());
+
+    let ask_amount = Uint128::from(1000u128);
+    let ask_asset = Asset {
+        info: AssetInfo::Token {
+            contract_addr: "asset0000".to_string(),
+        },
+        amount: ask_amount,
+    };
+    let reverse_simulation_res = query_reverse_simulation(
+        deps.as_ref(),
+        mock_env(),
+        AssetInfo::NativeToken {
+            denom: "uusd".to_string(),
+        },
+        ask_asset.clone(),
+    )
+    .unwrap();
+    let offer_amount = reverse_simulation_res.offer_amount;
+    assert!(offer_amount > ask_amount);
+
+    // the offer is capped by max_offer_amount
+    let msg = ExecuteMsg::SwapExactOut {
+        ask_asset: ask_asset.clone(),
+        max_offer_amount: ask_amount,
+        to: None,
+        deadline: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(
+            "addr0000",
+            &[Coin {
+                denom: "uusd".to_string(),
+                amount: ask_amount,
+            }],
+        ),
+        msg,
+    );
+    assert_eq!(
+        res,
+        Err(ContractError::MaxOfferExceeded {
+            max_offer_amount: ask_amount,
+            offer_amount,
+        })
+    );
+
+    // a single native coin has to be sent
+    let msg = ExecuteMsg::SwapExactOut {
+        ask_asset: ask_asset.clone(),
+        max_offer_amount: Uint128::from(2000u128),
+        to: None,
+        deadline: None,
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg);
+    assert_eq!(res, Err(ContractError::AssetMismatch {}));
+
+    let msg = ExecuteMsg::SwapExactOut {
+        ask_asset: ask_asset.clone(),
+        max_offer_amount: Uint128::from(2000u128),
+        to: Some("addr0001".to_string()),
+        deadline: None,
+    };
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info(
+            "addr0000",
+            &[Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(2000u128),
+            }],
+        ),
+        msg,
+    )
+    .unwrap();
+    let refund_amount = Uint128::from(2000u128) - offer_amount;
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "asset0000".to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: "addr0001".to_string(),
+                    amount: ask_amount,
+                })
+                .unwrap(),
+                funds: vec![],
+            })),
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "addr0000".to_string(),
+                amount: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: refund_amount,
+                }],
+            })),
+        ]
+    );
+    assert_eq!(
+        res.attributes,
+        vec![
+            attr("action", "swap_exact_out"),
+            attr("sender", "addr0000"),
+            attr("receiver", "addr0001"),
+            attr("offer_asset", "uusd"),
+            attr("ask_asset", "asset0000"),
+            attr("offer_amount", offer_amount.to_string()),
+            attr("return_amount", ask_amount.to_string()),
+            attr("refund_amount", refund_amount.to_string()),
+            attr("fee", reverse_simulation_res.fee.to_string()),
+            attr(
+                "commission_amount",
+                reverse_simulation_res.commission_amount.to_string()
+            ),
+            attr("admin_fee_amount", "0"),
+            attr("rates", "1000000000000000000, 1000000000000000000"),
+        ]
+    );
+
+    // only the offer taken enters the reserves
+    let pool_res = query_pool(deps.as_ref()).unwrap();
+    assert_eq!(pool_res.assets[0].amount, pool_amount + offer_amount);
+    assert_eq!(pool_res.assets[1].amount, pool_amount - ask_amount);
+
+    // the cw20 hook refunds what is left of the sent tokens
+    let ask_asset = Asset {
+        info: AssetInfo::NativeToken {
+            denom: "uusd".to_string(),
+        },
+        amount: ask_amount,
+    };
+    let offer_amount = query_reverse_simulation(
+        deps.as_ref(),
+        mock_env(),
+        AssetInfo::Token {
+            contract_addr: "asset0000".to_string(),
+        },
+        ask_asset.clone(),
+    )
+    .unwrap()
+    .offer_amount;
+
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0000".to_string(),
+        amount: Uint128::from(2000u128),
+        msg: to_binary(&Cw20HookMsg::SwapExactOut {
+            ask_asset: ask_asset.clone(),
+            to: None,
+            deadline: None,
+        })
+        .unwrap(),
+    });
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("asset0001", &[]),
+        msg.clone(),
+    );
+    assert_eq!(res, Err(ContractError::Unauthorized {}));
+
+    let res = execute(deps.as_mut(), mock_env(), mock_info("asset0000", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![
+            SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+                to_address: "addr0000".to_string(),
+                amount: vec![Coin {
+                    denom: "uusd".to_string(),
+                    amount: ask_amount,
+                }],
+            })),
+            SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "asset0000".to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: "addr0000".to_string(),
+                    amount: Uint128::from(2000u128) - offer_amount,
+                })
+                .unwrap(),
+                funds: vec![],
+            })),
+        ]
+    );
+}
+
+#[test]
+fn deadline() {
+    let pool_amount = Uint128::from(1000000u128);
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: pool_amount,
+    }]);
+
+    deps.querier.with_tax(
+        Decimal::zero(),
+        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
+    );
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&"addr0000".to_string(), &Uint128::from(2000000u128))],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &pool_amount)],
+        ),
+    ]);
+
+    let msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::from(60u128),
+        fee: Uint128::from(4u128),
+        token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+    let _res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // store liquidity token
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(
+                vec![
+                    10, 13, 108, 105, 113, 117, 105, 100, 105, 116, 121, 48, 48, 48, 48,
+                ]
+                .into(),
+            ),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+    sync_reserves(deps.as_mut());
+
+    let env = mock_env();
+    let swap_msg = |deadline: Option<Expiration>| ExecuteMsg::Swap {
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::from(1000u128),
+        },
+        ask_asset: Asset {
+            info: AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+            amount: Uint128::zero(),
+        },
+        min_out_amount: Uint128::zero(),
+        to: None,
+        deadline,
+    };
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(1000u128),
+        }],
+    );
+
+    // a deadline expires at its block height or time
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        swap_msg(Some(Expiration::AtHeight(env.block.height))),
+    );
+    assert_eq!(res, Err(ContractError::DeadlineExpired {}));
+
+    let res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        swap_msg(Some(Expiration::AtTime(env.block.time))),
+    );
+    assert_eq!(res, Err(ContractError::DeadlineExpired {}));
+
+    let _res = execute(
+        deps.as_mut(),
+        env.clone(),
+        info.clone(),
+        swap_msg(Some(Expiration::AtHeight(env.block.height + 1))),
+    )
+    .unwrap();
+    let _res = execute(deps.as_mut(), env.clone(), info, swap_msg(None)).unwrap();
+
+    let msg = ExecuteMsg::ProvideLiquidity {
+        assets: vec![Asset {
+            info: AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+            amount: Uint128::from(100u128),
+        }],
+        min_out_amount: Uint128::zero(),
+        receiver: None,
+        deadline: Some(Expiration::AtTime(env.block.time.minus_seconds(1))),
+    };
+    let res = execute(deps.as_mut(), env.clone(), mock_info("addr0000", &[]), msg);
+    assert_eq!(res, Err(ContractError::DeadlineExpired {}));
+
+    // the cw20 hooks take a deadline as well
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0000".to_string(),
+        amount: Uint128::from(100u128),
+        msg: to_binary(&Cw20HookMsg::WithdrawLiquidity {
+            min_assets: vec![],
+            receiver: None,
+            deadline: Some(Expiration::AtHeight(env.block.height)),
+        })
+        .unwrap(),
+    });
+    let res = execute(deps.as_mut(), env, mock_info("liquidity0000", &[]), msg);
+    assert_eq!(res, Err(ContractError::DeadlineExpired {}));
+}
+
+#[test]
+fn lp_token_info() {
+    let mut deps = mock_dependencies(&[]);
+
+    let denoms = ["uusd", "ukrw", "uluna"];
+    let instantiate_msg = || InstantiateMsg {
+        asset_infos: denoms
+            .iter()
+            .map(|denom| AssetInfo::NativeToken {
+                denom: denom.to_string(),
+            })
+            .collect(),
+        amplification: Uint128::from(60u128),
+        fee: Uint128::from(4u128),
+        token_code_id: 10u64,
+        native_decimals: denoms
+            .iter()
+            .map(|denom| NativeDecimals {
+                denom: denom.to_string(),
+                decimals: 6,
+            })
+            .collect(),
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+    let token_instantiate_msg = |res: Response| match &res.messages[0].msg {
+        CosmosMsg::Wasm(WasmMsg::Instantiate { msg, .. }) => {
+            from_binary::<TokenInstantiateMsg>(msg).unwrap()
+        }
+        _ => panic!("DO NOT ENTER HERE"),
+    };
+
+    // the symbol shortens every asset evenly to fit
+    let res = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        instantiate_msg(),
+    )
+    .unwrap();
+    let token_msg = token_instantiate_msg(res);
+    assert_eq!(token_msg.name, "uusd-ukrw-uluna-LP");
+    assert_eq!(token_msg.symbol, "UU-UK-UL-LP");
+    assert_eq!(token_msg.decimals, 6);
+
+    // assets without letters get a fixed segment, the decimals follow the most precise asset
+    let mut msg = instantiate_msg();
+    msg.asset_infos[1] = AssetInfo::NativeToken {
+        denom: "123".to_string(),
+    };
+    msg.native_decimals[1] = NativeDecimals {
+        denom: "123".to_string(),
+        decimals: 8,
+    };
+    let res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+    let token_msg = token_instantiate_msg(res);
+    assert_eq!(token_msg.name, "uusd-123-uluna-LP");
+    assert_eq!(token_msg.symbol, "UU-TO-UL-LP");
+    assert_eq!(token_msg.decimals, 8);
+
+    let mut msg = instantiate_msg();
+    msg.lp_token_name = Some("Stable USD KRW LUNA".to_string());
+    msg.lp_token_symbol = Some("sLP".to_string());
+    msg.lp_token_decimals = Some(18);
+    let res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+    let token_msg = token_instantiate_msg(res);
+    assert_eq!(token_msg.name, "Stable USD KRW LUNA");
+    assert_eq!(token_msg.symbol, "sLP");
+    assert_eq!(token_msg.decimals, 18);
+
+    // overrides are held to the token contract rules
+    let mut msg = instantiate_msg();
+    msg.lp_token_symbol = Some("LP1".to_string());
+    let res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg);
+    assert_eq!(
+        res,
+        Err(ContractError::Std(StdError::generic_err(
+            "Ticker symbol is not in expected format [a-zA-Z\\-]{3,12}"
+        )))
+    );
+
+    let mut msg = instantiate_msg();
+    msg.lp_token_decimals = Some(19);
+    let res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg);
+    assert_eq!(
+        res,
+        Err(ContractError::Std(StdError::generic_err(
+            "Decimals must not exceed 18"
+        )))
+    );
+}
+
+#[test]
+fn first_depositor_attack() {
+    let mut deps = mock_dependencies(&[]);
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::zero())],
+        ),
+        (&"asset0000".to_string(), &[]),
+    ]);
+
+    let msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::from(60u128),
+        fee: Uint128::from(4u128),
+        token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+    let _res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // store liquidity token
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(
+                vec![
+                    10, 13, 108, 105, 113, 117, 105, 100, 105, 116, 121, 48, 48, 48, 48,
+                ]
+                .into(),
+            ),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+
+    let provide_msg = |amount: u128| ExecuteMsg::ProvideLiquidity {
+        assets: vec![
+            Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+                amount: Uint128::from(amount),
+            },
+            Asset {
+                info: AssetInfo::Token {
+                    contract_addr: "asset0000".to_string(),
+                },
+                amount: Uint128::from(amount),
+            },
+        ],
+        min_out_amount: Uint128::zero(),
+        receiver: None,
+        deadline: None,
+    };
+    let provide_info = |amount: u128| {
+        mock_info(
+            "attacker0000",
+            &[Coin {
+                denom: "uusd".to_string(),
+                amount: Uint128::from(amount),
+            }],
+        )
+    };
+
+    // a first deposit that cannot cover the locked liquidity is rejected
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        provide_info(400_000),
+        provide_msg(400_000),
+    );
+    assert_eq!(
+        res.unwrap_err(),
+        ContractError::MinimumLiquidity {
+            min_share: Uint128::from(1_000_000u128),
+            share: Uint128::from(800_000u128),
+        }
+    );
+
+    // the attacker makes the smallest accepted first deposit
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        provide_info(500_000),
+        provide_msg(500_000),
+    )
+    .unwrap();
+    assert_eq!(
+        res.messages[1],
+        SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "liquidity0000".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Mint {
+                recipient: MOCK_CONTRACT_ADDR.to_string(),
+                amount: Uint128::from(1000u128),
+            })
+            .unwrap(),
+            funds: vec![],
+        }))
+    );
+    assert_eq!(
+        res.messages[2],
+        SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "liquidity0000".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Mint {
+                recipient: "attacker0000".to_string(),
+                amount: Uint128::from(999_000u128),
+            })
+            .unwrap(),
+            funds: vec![],
+        }))
+    );
+
+    // then donates to the pair to inflate the share price
+    let donation = 1_000_000_000u128;
+    deps.querier.with_balance(&[(
+        &MOCK_CONTRACT_ADDR.to_string(),
+        vec![Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(500_000 + donation),
+        }],
+    )]);
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[
+                (&MOCK_CONTRACT_ADDR.to_string(), &Uint128::from(1000u128)),
+                (&"attacker0000".to_string(), &Uint128::from(999_000u128)),
+            ],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(
+                &MOCK_CONTRACT_ADDR.to_string(),
+                &Uint128::from(500_000 + donation),
+            )],
+        ),
+    ]);
+    sync_reserves(deps.as_mut());
+
+    // the victim's deposit still mints its worth, the rounding costs it less than a thousandth
+    let deposit = Uint128::from(1_000_000u128);
+    let simulation_res = query_simulate_provide_liquidity(
+        deps.as_ref(),
+        mock_env(),
+        vec![
+            Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+                amount: deposit,
+            },
+            Asset {
+                info: AssetInfo::Token {
+                    contract_addr: "asset0000".to_string(),
+                },
+                amount: deposit,
+            },
+        ],
+    )
+    .unwrap();
+    let withdraw_res =
+        query_simulate_withdraw_liquidity(deps.as_ref(), simulation_res.share).unwrap();
+    for asset in withdraw_res.assets {
+        assert!(asset.amount > deposit - deposit.multiply_ratio(1u128, 1000u128));
+    }
+
+    // while the attacker loses a thousandth of the donation to the locked shares
+    let withdraw_res =
+        query_simulate_withdraw_liquidity(deps.as_ref(), Uint128::from(999_000u128)).unwrap();
+    for asset in withdraw_res.assets {
+        assert_eq!(asset.amount, Uint128::from(999_499_500u128));
+    }
+}
+
+#[test]
+fn exchange_underlying() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(1_500_000_000u128),
+    }]);
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&"addr0000".to_string(), &Uint128::from(3_000_000_000u128))],
+        ),
+        (
+            &"baselp0000".to_string(),
+            &[(
+                &MOCK_CONTRACT_ADDR.to_string(),
+                &Uint128::from(1_000_000_000u128),
+            )],
+        ),
+    ]);
+    // one base pool LP token is worth 1.5 of the invariant
+    deps.querier.with_pair(
+        PairInfo {
+            asset_infos: vec![
+                AssetInfo::NativeToken {
+                    denom: "ukrw".to_string(),
+                },
+                AssetInfo::Token {
+                    contract_addr: "asset0000".to_string(),
+                },
+            ],
+            contract_addr: "basepool0000".to_string(),
+            liquidity_token: "baselp0000".to_string(),
+            amplification: Uint128::from(100u128),
+            fee: Uint128::from(4u128),
+            precision_multipliers: vec![Uint128::from(1u128); 2],
+        },
+        Uint128::from(1_500_000_000_000_000_000u128),
+    );
+
+    let mut msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "liquidity0000".to_string(),
+            },
+        ],
+        amplification: Uint128::from(100u128),
+        fee: Uint128::from(4u128),
+        token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: Some("basepool0000".to_string()),
+        rate_providers: vec![],
+    };
+
+    // the base pool LP token has to be one of the pair assets
+    let res = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    );
+    assert_eq!(
+        res.unwrap_err(),
+        ContractError::Std(StdError::generic_err(
+            "Base pool LP token is not one of the pair assets"
+        ))
+    );
+
+    msg.asset_infos[1] = AssetInfo::Token {
+        contract_addr: "baselp0000".to_string(),
+    };
+    let _res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // store liquidity token
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(
+                vec![
+                    10, 13, 108, 105, 113, 117, 105, 100, 105, 116, 121, 48, 48, 48, 48,
+                ]
+                .into(),
+            ),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+    sync_reserves(deps.as_mut());
+
+    let config = query_config(deps.as_ref(), mock_env()).unwrap();
+    assert_eq!(config.base_pool, Some("basepool0000".to_string()));
+
+    // the LP leg is valued at the base pool virtual price
+    let lp_simulation = query_simulation(
+        deps.as_ref(),
+        mock_env(),
+        Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::from(1_500_000u128),
+        },
+        AssetInfo::Token {
+            contract_addr: "baselp0000".to_string(),
+        },
+    )
+    .unwrap();
+    assert!(
+        lp_simulation.return_amount > Uint128::from(999_000u128)
+            && lp_simulation.return_amount < Uint128::from(1_000_000u128)
+    );
+
+    // a metapool asset for a base pool asset withdraws the LP tokens from the base pool
+    let msg = ExecuteMsg::ExchangeUnderlying {
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::from(1_500_000u128),
+        },
+        ask_asset: Asset {
+            info: AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+            amount: Uint128::zero(),
+        },
+        min_out_amount: Uint128::from(990_000u128),
+        to: None,
+        deadline: None,
+    };
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(1_500_000u128),
+        }],
+    );
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "baselp0000".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Send {
+                contract: "basepool0000".to_string(),
+                amount: lp_simulation.return_amount,
+                msg: to_binary(&Cw20HookMsg::WithdrawSingleLiquidity {
+                    asset: Asset {
+                        info: AssetInfo::Token {
+                            contract_addr: "asset0000".to_string(),
+                        },
+                        amount: Uint128::zero(),
+                    },
+                    min_out_amount: Uint128::from(990_000u128),
+                    receiver: Some("addr0000".to_string()),
+                    deadline: None,
+                })
+                .unwrap(),
+            })
+            .unwrap(),
+            funds: vec![],
+        }))]
+    );
+    assert_eq!(
+        res.attributes[6],
+        attr("base_lp_amount", lp_simulation.return_amount.to_string())
+    );
+    let pool = query_pool(deps.as_ref()).unwrap();
+    assert_eq!(pool.assets[0].amount, Uint128::from(1_501_500_000u128));
+
+    // a base pool asset for a metapool asset deposits into the base pool, the reply swaps the
+    // minted LP tokens
+    let usd_simulation = query_simulation(
+        deps.as_ref(),
+        mock_env(),
+        Asset {
+            info: AssetInfo::Token {
+                contract_addr: "baselp0000".to_string(),
+            },
+            amount: Uint128::from(1_000_000u128),
+        },
+        AssetInfo::NativeToken {
+            denom: "uusd".to_string(),
+        },
+    )
+    .unwrap();
+    let msg = ExecuteMsg::ExchangeUnderlying {
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "ukrw".to_string(),
+            },
+            amount: Uint128::from(1_000_000u128),
+        },
+        ask_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::zero(),
+        },
+        min_out_amount: usd_simulation.return_amount,
+        to: Some("addr0001".to_string()),
+        deadline: None,
+    };
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "ukrw".to_string(),
+            amount: Uint128::from(1_000_000u128),
+        }],
+    );
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg {
+            msg: CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "basepool0000".to_string(),
+                msg: to_binary(&ExecuteMsg::ProvideLiquidity {
+                    assets: vec![Asset {
+                        info: AssetInfo::NativeToken {
+                            denom: "ukrw".to_string(),
+                        },
+                        amount: Uint128::from(1_000_000u128),
+                    }],
+                    min_out_amount: Uint128::zero(),
+                    receiver: None,
+                    deadline: None,
+                })
+                .unwrap(),
+                funds: vec![Coin {
+                    denom: "ukrw".to_string(),
+                    amount: Uint128::from(1_000_000u128),
+                }],
+            }),
+            gas_limit: None,
+            id: 3,
+            reply_on: ReplyOn::Success,
+        }]
+    );
+
+    // the base pool minted one million LP tokens to the pair
+    deps.querier.with_token_balances(&[(
+        &"baselp0000".to_string(),
+        &[(
+            &MOCK_CONTRACT_ADDR.to_string(),
+            &Uint128::from(1_001_000_000u128),
+        )],
+    )]);
+    let deposit_reply = Reply {
+        id: 3,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: None,
+        }),
+    };
+    let res = reply(deps.as_mut(), mock_env(), deposit_reply).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Bank(BankMsg::Send {
+            to_address: "addr0001".to_string(),
+            amount: vec![Coin {
+                denom: "uusd".to_string(),
+                amount: usd_simulation.return_amount,
+            }],
+        }))]
+    );
+    assert_eq!(
+        res.attributes[6],
+        attr("base_lp_amount", "1000000".to_string())
+    );
+
+    // two base pool assets swap on the base pool
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0000".to_string(),
+        amount: Uint128::from(1_000_000u128),
+        msg: to_binary(&Cw20HookMsg::ExchangeUnderlying {
+            ask_asset: Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "ukrw".to_string(),
+                },
+                amount: Uint128::zero(),
+            },
+            min_out_amount: Uint128::from(990_000u128),
+            to: None,
+            deadline: None,
+        })
+        .unwrap(),
+    });
+    let res = execute(deps.as_mut(), mock_env(), mock_info("asset0000", &[]), msg).unwrap();
+    assert_eq!(
+        res.messages,
+        vec![SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "asset0000".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Send {
+                contract: "basepool0000".to_string(),
+                amount: Uint128::from(1_000_000u128),
+                msg: to_binary(&Cw20HookMsg::Swap {
+                    ask_asset: Asset {
+                        info: AssetInfo::NativeToken {
+                            denom: "ukrw".to_string(),
+                        },
+                        amount: Uint128::zero(),
+                    },
+                    min_out_amount: Uint128::from(990_000u128),
+                    to: Some("addr0000".to_string()),
+                    deadline: None,
+                })
+                .unwrap(),
+            })
+            .unwrap(),
+            funds: vec![],
+        }))]
+    );
+
+    // the LP token itself and unknown tokens are no underlying assets
+    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "addr0000".to_string(),
+        amount: Uint128::from(1_000_000u128),
+        msg: to_binary(&Cw20HookMsg::ExchangeUnderlying {
+            ask_asset: Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+                amount: Uint128::zero(),
+            },
+            min_out_amount: Uint128::zero(),
+            to: None,
+            deadline: None,
+        })
+        .unwrap(),
+    });
+    let res = execute(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("baselp0000", &[]),
+        msg.clone(),
+    );
+    assert_eq!(res.unwrap_err(), ContractError::AssetMismatch {});
+    let res = execute(deps.as_mut(), mock_env(), mock_info("asset0001", &[]), msg);
+    assert_eq!(
+        res.unwrap_err(),
+        ContractError::UnknownAsset {
+            asset: "asset0001".to_string()
+        }
+    );
+}
+
+#[test]
+fn rate_provider() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(1_100_000_000u128),
+    }]);
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&"addr0000".to_string(), &Uint128::from(2_200_000_000u128))],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(
+                &MOCK_CONTRACT_ADDR.to_string(),
+                &Uint128::from(1_000_000_000u128),
+            )],
+        ),
+    ]);
+    // one asset0000 redeems for 1.1 of the underlying
+    deps.querier
+        .with_rate("rateprovider0000", Uint128::from(1_100_000u128), 6);
+
+    let mut msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::from(100u128),
+        fee: Uint128::from(4u128),
+        token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![RateProvider {
+            asset_info: AssetInfo::Token {
+                contract_addr: "asset0001".to_string(),
+            },
+            contract_addr: "rateprovider0000".to_string(),
+        }],
+    };
+
+    // the rate provider has to name a pair asset
+    let res = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    );
+    assert_eq!(
+        res.unwrap_err(),
+        ContractError::Std(StdError::generic_err(
+            "Rate provider asset asset0001 is not one of the pair assets"
+        ))
+    );
+
+    msg.rate_providers[0].asset_info = AssetInfo::Token {
+        contract_addr: "asset0000".to_string(),
+    };
+    let _res = instantiate(
+        deps.as_mut(),
+        mock_env(),
+        mock_info("addr0000", &[]),
+        msg.clone(),
+    )
+    .unwrap();
+    sync_reserves(deps.as_mut());
+
+    let config = query_config(deps.as_ref(), mock_env()).unwrap();
+    assert_eq!(config.rate_providers, msg.rate_providers);
+
+    // the pool holds the same value of each asset, so it trades at the rate
+    let rates = vec![
+        Uint128::from(1_000_000_000_000_000_000u128),
+        Uint128::from(1_100_000_000_000_000_000u128),
+    ];
+    let simulation_res = query_simulation(
+        deps.as_ref(),
+        mock_env(),
+        Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::from(1_100_000u128),
+        },
+        AssetInfo::Token {
+            contract_addr: "asset0000".to_string(),
+        },
+    )
+    .unwrap();
+    assert_eq!(simulation_res.rates, rates);
+    assert_eq!(simulation_res.commission_amount, Uint128::from(399u128));
+    assert!(
+        simulation_res.return_amount + simulation_res.commission_amount
+            > Uint128::from(999_900u128)
+    );
+    assert!(simulation_res.spread_amount < Uint128::from(100u128));
+
+    let reverse_simulation_res = query_reverse_simulation(
+        deps.as_ref(),
+        mock_env(),
+        AssetInfo::NativeToken {
+            denom: "uusd".to_string(),
+        },
+        Asset {
+            info: AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+            amount: simulation_res.return_amount,
+        },
+    )
+    .unwrap();
+    assert_eq!(reverse_simulation_res.rates, rates);
+    assert_eq!(
+        reverse_simulation_res.offer_amount,
+        Uint128::from(1_100_001u128)
+    );
+
+    let msg = ExecuteMsg::Swap {
+        offer_asset: Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::from(1_100_000u128),
+        },
         ask_asset: Asset {
             info: AssetInfo::Token {
                 contract_addr: "asset0000".to_string(),
             },
-            amount: Uint128::from(0u128),
+            amount: Uint128::zero(),
+        },
+        min_out_amount: simulation_res.return_amount,
+        to: None,
+        deadline: None,
+    };
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: Uint128::from(1_100_000u128),
+        }],
+    );
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    assert_eq!(
+        res.attributes.last(),
+        Some(&attr("rates", "1000000000000000000, 1100000000000000000"))
+    );
+
+    // a higher rate makes asset0000 dearer
+    deps.querier
+        .with_rate("rateprovider0000", Uint128::from(1_200_000u128), 6);
+    let res = query_simulation(
+        deps.as_ref(),
+        mock_env(),
+        Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::from(1_100_000u128),
+        },
+        AssetInfo::Token {
+            contract_addr: "asset0000".to_string(),
+        },
+    )
+    .unwrap();
+    assert!(res.return_amount < simulation_res.return_amount);
+    assert_eq!(res.rates[1], Uint128::from(1_200_000_000_000_000_000u128));
+}
+
+#[test]
+fn dynamic_fee() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(3_000_000_000u128),
+    }]);
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&"addr0000".to_string(), &Uint128::from(4_000_000_000u128))],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(
+                &MOCK_CONTRACT_ADDR.to_string(),
+                &Uint128::from(1_000_000_000u128),
+            )],
+        ),
+    ]);
+
+    let msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::from(100u128),
+        fee: Uint128::from(30u128),
+        token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+    let _res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // store liquidity token
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(
+                vec![
+                    10, 13, 108, 105, 113, 117, 105, 100, 105, 116, 121, 48, 48, 48, 48,
+                ]
+                .into(),
+            ),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+    sync_reserves(deps.as_mut());
+
+    let uusd_offer = Asset {
+        info: AssetInfo::NativeToken {
+            denom: "uusd".to_string(),
+        },
+        amount: Uint128::from(500_000_000u128),
+    };
+    let asset_info = AssetInfo::Token {
+        contract_addr: "asset0000".to_string(),
+    };
+
+    // the fee is flat until the owner sets a multiplier
+    let simulation_res = query_simulation(
+        deps.as_ref(),
+        mock_env(),
+        uusd_offer.clone(),
+        asset_info.clone(),
+    )
+    .unwrap();
+    assert_eq!(simulation_res.fee, Uint128::from(30u128));
+
+    // only the owner sets the multiplier, the capped fee has to stay within the fee cap
+    let msg = ExecuteMsg::UpdateFeeMultiplier {
+        fee_multiplier: Uint128::from(50_000u128),
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("addr0001", &[]), msg);
+    assert_eq!(res, Err(ContractError::Unauthorized {}));
+
+    let msg = ExecuteMsg::UpdateFeeMultiplier {
+        fee_multiplier: Uint128::from(100_001u128),
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg);
+    assert_eq!(res, Err(ContractError::InvalidFee {}));
+
+    let msg = ExecuteMsg::UpdateFeeMultiplier {
+        fee_multiplier: Uint128::from(50_000u128),
+    };
+    let _res = execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+    assert_eq!(
+        query_config(deps.as_ref(), mock_env())
+            .unwrap()
+            .fee_multiplier,
+        Uint128::from(50_000u128)
+    );
+
+    // five times a fee above 1000 would pass the fee cap
+    let msg = ExecuteMsg::UpdateFee {
+        fee: Uint128::from(1_001u128),
+    };
+    let res = execute(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg);
+    assert_eq!(res, Err(ContractError::InvalidFee {}));
+
+    // adding to the abundant asset pays more than the base fee, restoring the balance does not
+    let simulation_res = query_simulation(
+        deps.as_ref(),
+        mock_env(),
+        uusd_offer.clone(),
+        asset_info.clone(),
+    )
+    .unwrap();
+    assert!(simulation_res.fee > Uint128::from(30u128));
+    assert!(simulation_res.fee < Uint128::from(150u128));
+    assert_eq!(
+        simulation_res.commission_amount,
+        (simulation_res.return_amount + simulation_res.commission_amount)
+            .multiply_ratio(simulation_res.fee, 10_000u128)
+    );
+
+    let restoring_simulation_res = query_simulation(
+        deps.as_ref(),
+        mock_env(),
+        Asset {
+            info: asset_info.clone(),
+            amount: Uint128::from(500_000_000u128),
+        },
+        uusd_offer.info.clone(),
+    )
+    .unwrap();
+    assert_eq!(restoring_simulation_res.fee, Uint128::from(30u128));
+
+    let reverse_simulation_res = query_reverse_simulation(
+        deps.as_ref(),
+        mock_env(),
+        uusd_offer.info.clone(),
+        Asset {
+            info: asset_info.clone(),
+            amount: simulation_res.return_amount,
+        },
+    )
+    .unwrap();
+    assert!(reverse_simulation_res.fee > Uint128::from(30u128));
+
+    let msg = ExecuteMsg::Swap {
+        offer_asset: uusd_offer.clone(),
+        ask_asset: Asset {
+            info: asset_info,
+            amount: Uint128::zero(),
         },
         min_out_amount: Uint128::zero(),
         to: None,
+        deadline: None,
+    };
+    let info = mock_info(
+        "addr0000",
+        &[Coin {
+            denom: "uusd".to_string(),
+            amount: uusd_offer.amount,
+        }],
+    );
+    let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    assert_eq!(
+        res.attributes[6..9],
+        [
+            attr("return_amount", simulation_res.return_amount.to_string()),
+            attr("fee", simulation_res.fee.to_string()),
+            attr(
+                "commission_amount",
+                simulation_res.commission_amount.to_string()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn spot_price_and_price_impact() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(2_000_000_000u128),
+    }]);
+    deps.querier.with_token_balances(&[
+        (
+            &"liquidity0000".to_string(),
+            &[(&"addr0000".to_string(), &Uint128::from(3_000_000_000u128))],
+        ),
+        (
+            &"asset0000".to_string(),
+            &[(
+                &MOCK_CONTRACT_ADDR.to_string(),
+                &Uint128::from(1_000_000_000u128),
+            )],
+        ),
+    ]);
+
+    let msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::from(100u128),
+        fee: Uint128::from(4u128),
+        token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+    let _res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
+
+    // store liquidity token
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(
+                vec![
+                    10, 13, 108, 105, 113, 117, 105, 100, 105, 116, 121, 48, 48, 48, 48,
+                ]
+                .into(),
+            ),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+    sync_reserves(deps.as_mut());
+
+    let uusd_info = AssetInfo::NativeToken {
+        denom: "uusd".to_string(),
     };
-    let env = mock_env();
-    let info = mock_info(
-        "addr0000",
-        &[Coin {
-            denom: "uusd".to_string(),
-            amount: offer_amount,
-        }],
-    );
-    let res = execute(deps.as_mut(), env, info, msg).unwrap();
-    let msg_transfer = res.messages.get(0).expect("no message");
+    let asset_info = AssetInfo::Token {
+        contract_addr: "asset0000".to_string(),
+    };
+    let one = Uint128::from(10u128.pow(18));
 
-    let expected_return_amount = Uint128::from(1486872698u128);
+    // the abundant asset is the cheaper one, both ways round the prices are inverses
+    let spot_price_res = query_spot_price(
+        deps.as_ref(),
+        mock_env(),
+        uusd_info.clone(),
+        asset_info.clone(),
+    )
+    .unwrap();
+    assert_eq!(spot_price_res.precision, 18);
+    let price = spot_price_res.price;
+    let reverse_price = query_spot_price(
+        deps.as_ref(),
+        mock_env(),
+        asset_info.clone(),
+        uusd_info.clone(),
+    )
+    .unwrap()
+    .price;
+    assert!(price < one && reverse_price > one);
+    let product = price.multiply_ratio(reverse_price, one);
+    assert!(one.u128().abs_diff(product.u128()) < 1_000_000);
 
-    // check simulation res
-    // deps.querier.with_balance(&[(
-    //     &MOCK_CONTRACT_ADDR.to_string(),
-    //     vec![Coin {
-    //         denom: "uusd".to_string(),
-    //         amount: collateral_pool_amount, /* user deposit must be pre-applied */
-    //     }],
-    // )]);
-
-    // let simulation_res: SimulationResponse = query_simulation(
-    //     deps.as_ref(),
-    //     Asset {
-    //         info: AssetInfo::NativeToken {
-    //             denom: "uusd".to_string(),
-    //         },
-    //         amount: offer_amount,
-    //     },
-    // )
-    // .unwrap();
-    // assert_eq!(expected_return_amount, simulation_res.return_amount);
+    assert_eq!(
+        query_spot_price(
+            deps.as_ref(),
+            mock_env(),
+            uusd_info.clone(),
+            uusd_info.clone()
+        ),
+        Err(ContractError::AssetMismatch {})
+    );
 
-    // check reverse simulation res
-    // let reverse_simulation_res: ReverseSimulationResponse = query_reverse_simulation(
-    //     deps.as_ref(),
-    //     Asset {
-    //         info: AssetInfo::Token {
-    //             contract_addr: "asset0000".to_string(),
-    //         },
-    //         amount: expected_return_amount,
-    //     },
-    // )
-    // .unwrap();
+    // a small swap trades at about the spot price
+    let small_offer = Asset {
+        info: uusd_info.clone(),
+        amount: Uint128::from(1_000_000u128),
+    };
+    let simulation_res = query_simulation(
+        deps.as_ref(),
+        mock_env(),
+        small_offer.clone(),
+        asset_info.clone(),
+    )
+    .unwrap();
+    assert!(
+        (simulation_res.return_amount + simulation_res.commission_amount)
+            .u128()
+            .abs_diff(price.multiply_ratio(small_offer.amount, one).u128())
+            <= 100
+    );
 
+    let price_impact_res = query_price_impact(
+        deps.as_ref(),
+        mock_env(),
+        small_offer.clone(),
+        asset_info.clone(),
+    )
+    .unwrap();
+    assert_eq!(price_impact_res.spot_price, price);
     assert_eq!(
-        res.attributes,
-        vec![
-            attr("action", "swap"),
-            attr("sender", "addr0000"),
-            attr("receiver", "addr0000"),
-            attr("offer_asset", "uusd"),
-            attr("ask_asset", "asset0000"),
-            attr("offer_amount", offer_amount.to_string()),
-            attr("return_amount", expected_return_amount.to_string()),
-        ]
+        price_impact_res.effective_price,
+        one.multiply_ratio(simulation_res.return_amount, small_offer.amount)
     );
+    assert!(price_impact_res.price_impact < Decimal::from_ratio(1u128, 10_000u128));
 
+    // a large swap moves the price against itself
+    let large_offer = Asset {
+        info: uusd_info.clone(),
+        amount: Uint128::from(1_000_000_000u128),
+    };
+    let simulation_res = query_simulation(
+        deps.as_ref(),
+        mock_env(),
+        large_offer.clone(),
+        asset_info.clone(),
+    )
+    .unwrap();
+    let price_impact_res =
+        query_price_impact(deps.as_ref(), mock_env(), large_offer.clone(), asset_info).unwrap();
     assert_eq!(
-        &SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
-            contract_addr: "asset0000".to_string(),
-            msg: to_binary(&Cw20ExecuteMsg::Transfer {
-                recipient: "addr0000".to_string(),
-                amount: expected_return_amount,
-            })
-            .unwrap(),
-            funds: vec![],
-        })),
-        msg_transfer,
+        price_impact_res.effective_price,
+        one.multiply_ratio(simulation_res.return_amount, large_offer.amount)
+    );
+    assert!(price_impact_res.price_impact > Decimal::percent(1));
+    assert!(price_impact_res.price_impact < Decimal::percent(50));
+    assert!(price_impact_res.effective_price < price);
+
+    assert_eq!(
+        query_price_impact(
+            deps.as_ref(),
+            mock_env(),
+            Asset {
+                info: uusd_info.clone(),
+                amount: Uint128::zero(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ),
+        Err(ContractError::InvalidZeroAmount {})
     );
 }
 
 #[test]
-fn try_token_to_native() {
-    let total_share = Uint128::from(20000000000u128);
-    let asset_pool_amount = Uint128::from(30000000000u128);
-    let collateral_pool_amount = Uint128::from(20000000000u128);
-    let exchange_rate = Decimal::from_ratio(collateral_pool_amount, asset_pool_amount);
-    let offer_amount = Uint128::from(1500000000u128);
-
+fn trade_to_price() {
     let mut deps = mock_dependencies(&[Coin {
         denom: "uusd".to_string(),
-        amount: collateral_pool_amount,
+        amount: Uint128::from(3_000_000_000u128),
     }]);
-    deps.querier.with_tax(
-        Decimal::percent(1),
-        &[(&"uusd".to_string(), &Uint128::from(1000000u128))],
-    );
     deps.querier.with_token_balances(&[
         (
             &"liquidity0000".to_string(),
-            &[(&MOCK_CONTRACT_ADDR.to_string(), &total_share)],
+            &[(&"addr0000".to_string(), &Uint128::from(4_000_000_000u128))],
         ),
         (
             &"asset0000".to_string(),
             &[(
                 &MOCK_CONTRACT_ADDR.to_string(),
-                &(asset_pool_amount + offer_amount),
+                &Uint128::from(1_000_000_000u128),
             )],
         ),
     ]);
@@ -914,15 +5415,21 @@ fn try_token_to_native() {
                 contract_addr: "asset0000".to_string(),
             },
         ],
-        amplification: Uint128::from(60u128),
+        amplification: Uint128::from(100u128),
         fee: Uint128::from(4u128),
         token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
     };
-
-    let env = mock_env();
-    let info = mock_info("addr0000", &[]);
-    // we can just call .unwrap() to assert this was a success
-    let _res = instantiate(deps.as_mut(), env, info, msg).unwrap();
+    let _res = instantiate(deps.as_mut(), mock_env(), mock_info("addr0000", &[]), msg).unwrap();
 
     // store liquidity token
     let reply_msg = Reply {
@@ -937,132 +5444,92 @@ fn try_token_to_native() {
             ),
         }),
     };
-
     let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+    sync_reserves(deps.as_mut());
 
-    // unauthorized access; can not execute swap directly for token swap
-    let msg = ExecuteMsg::Swap {
-        offer_asset: Asset {
-            info: AssetInfo::Token {
-                contract_addr: "asset0000".to_string(),
-            },
-            amount: offer_amount,
-        },
-        ask_asset: Asset {
-            info: AssetInfo::NativeToken {
-                denom: "uusd".to_string(),
-            },
-            amount: Uint128::from(0u128),
-        },
-        min_out_amount: Uint128::zero(),
-        to: None,
+    let uusd_info = AssetInfo::NativeToken {
+        denom: "uusd".to_string(),
     };
-    let env = mock_env();
-    let info = mock_info("addr0000", &[]);
-    let res = execute(deps.as_mut(), env, info, msg).unwrap_err();
-    match res {
-        ContractError::Unauthorized {} => (),
-        _ => panic!("DO NOT ENTER HERE"),
-    }
+    let asset_info = AssetInfo::Token {
+        contract_addr: "asset0000".to_string(),
+    };
+    let one = Uint128::from(10u128.pow(18));
 
-    // normal sell
+    // selling the scarce asset back to par takes about half of the difference, the return is
+    // what a swap of that size gets
+    let trade_res = query_trade_to_price(
+        deps.as_ref(),
+        mock_env(),
+        asset_info.clone(),
+        uusd_info.clone(),
+        one,
+    )
+    .unwrap();
+    assert!(trade_res.offer_amount.u128().abs_diff(1_000_000_000) < 10_000_000);
+    let simulation_res = query_simulation(
+        deps.as_ref(),
+        mock_env(),
+        Asset {
+            info: asset_info.clone(),
+            amount: trade_res.offer_amount,
+        },
+        uusd_info.clone(),
+    )
+    .unwrap();
+    assert_eq!(trade_res.return_amount, simulation_res.return_amount);
+    assert_eq!(
+        trade_res.commission_amount,
+        simulation_res.commission_amount
+    );
+
+    // swapping it moves the spot price to the target, the fee the pool keeps aside
     let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
         sender: "addr0000".to_string(),
-        amount: offer_amount,
+        amount: trade_res.offer_amount,
         msg: to_binary(&Cw20HookMsg::Swap {
             ask_asset: Asset {
-                info: AssetInfo::NativeToken {
-                    denom: "uusd".to_string(),
-                },
-                amount: Uint128::from(0u128),
+                info: uusd_info.clone(),
+                amount: Uint128::zero(),
             },
-            min_out_amount: Uint128::zero(),
+            min_out_amount: trade_res.return_amount,
             to: None,
+            deadline: None,
         })
         .unwrap(),
     });
-    let env = mock_env();
-    let info = mock_info("asset0000", &[]);
+    let _res = execute(deps.as_mut(), mock_env(), mock_info("asset0000", &[]), msg).unwrap();
+    let price = query_spot_price(
+        deps.as_ref(),
+        mock_env(),
+        asset_info.clone(),
+        uusd_info.clone(),
+    )
+    .unwrap()
+    .price;
+    assert!(price >= one && price - one < Uint128::from(10u128.pow(15)));
 
-    let res = execute(deps.as_mut(), env, info, msg).unwrap();
-    let msg_transfer = res.messages.get(0).expect("no message");
-
-    // current price is 1.5, so expected return without spread is 1000
-    // 952.380952 = 20000 - 20000 * 30000 / (30000 + 1500)
-    let expected_return_amount = Uint128::from(1486872698u128);
-    // check simulation res
-    // return asset token balance as normal
-    // deps.querier.with_token_balances(&[
-    //     (
-    //         &"liquidity0000".to_string(),
-    //         &[(&MOCK_CONTRACT_ADDR.to_string(), &total_share)],
-    //     ),
-    //     (
-    //         &"asset0000".to_string(),
-    //         &[(&MOCK_CONTRACT_ADDR.to_string(), &(asset_pool_amount))],
-    //     ),
-    // ]);
-
-    // let simulation_res: SimulationResponse = query_simulation(
-    //     deps.as_ref(),
-    //     Asset {
-    //         amount: offer_amount,
-    //         info: AssetInfo::Token {
-    //             contract_addr: "asset0000".to_string(),
-    //         },
-    //     },
-    // )
-    // .unwrap();
-    // assert_eq!(expected_return_amount, simulation_res.return_amount);
-
-    // // check reverse simulation res
-    // let reverse_simulation_res: ReverseSimulationResponse = query_reverse_simulation(
-    //     deps.as_ref(),
-    //     Asset {
-    //         amount: expected_return_amount,
-    //         info: AssetInfo::NativeToken {
-    //             denom: "uusd".to_string(),
-    //         },
-    //     },
-    // )
-    // .unwrap();
+    // selling only lowers the price of the offer asset
+    let trade_res = query_trade_to_price(
+        deps.as_ref(),
+        mock_env(),
+        uusd_info.clone(),
+        asset_info.clone(),
+        one + one,
+    )
+    .unwrap();
+    assert_eq!(trade_res.offer_amount, Uint128::zero());
+    assert_eq!(trade_res.return_amount, Uint128::zero());
 
     assert_eq!(
-        res.attributes,
-        vec![
-            attr("action", "swap"),
-            attr("sender", "addr0000"),
-            attr("receiver", "addr0000"),
-            attr("offer_asset", "asset0000"),
-            attr("ask_asset", "uusd"),
-            attr("offer_amount", offer_amount.to_string()),
-            attr("return_amount", expected_return_amount.to_string()),
-        ]
+        query_trade_to_price(
+            deps.as_ref(),
+            mock_env(),
+            uusd_info,
+            asset_info,
+            Uint128::zero()
+        ),
+        Err(ContractError::InvalidZeroAmount {})
     );
-
-    // failed due to non asset token contract try to execute sell
-    let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
-        sender: "addr0000".to_string(),
-        amount: offer_amount,
-        msg: to_binary(&Cw20HookMsg::Swap {
-            ask_asset: Asset {
-                info: AssetInfo::NativeToken {
-                    denom: "uusd".to_string(),
-                },
-                amount: Uint128::from(0u128),
-            },
-            min_out_amount: Uint128::zero(),
-            to: None,
-        })
-        .unwrap(),
-    });
-    let env = mock_env();
-    let info = mock_info("liquidity0000", &[]);
-    let res = execute(deps.as_mut(), env, info, msg).unwrap_err();
-    match res {
-        ContractError::Unauthorized {} => (),
-        _ => panic!("DO NOT ENTER HERE"),
-    }
 }
 
 // #[test]
@@ -1166,6 +5633,16 @@ fn test_query_pool() {
         amplification: Uint128::from(60u128),
         fee: Uint128::from(4u128),
         token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
     };
 
     let env = mock_env();
@@ -1188,6 +5665,7 @@ fn test_query_pool() {
     };
 
     let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+    sync_reserves(deps.as_mut());
 
     let res: PoolResponse = query_pool(deps.as_ref()).unwrap();
 
@@ -1210,3 +5688,188 @@ fn test_query_pool() {
     );
     assert_eq!(res.total_share, total_share_amount);
 }
+
+#[test]
+fn test_query_virtual_price() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(1000000u128),
+    }]);
+
+    // asset0000 has 8 decimals against the 6 of uusd, so both sides hold one unit
+    deps.querier.with_token_balances(&[
+        (
+            &"asset0000".to_string(),
+            &[(
+                &MOCK_CONTRACT_ADDR.to_string(),
+                &Uint128::from(100000000u128),
+            )],
+        ),
+        (
+            &"liquidity0000".to_string(),
+            &[(
+                &MOCK_CONTRACT_ADDR.to_string(),
+                &Uint128::from(100000000u128),
+            )],
+        ),
+    ]);
+    deps.querier
+        .with_token_decimals(&[(&"asset0000".to_string(), 8u8)]);
+
+    let msg = InstantiateMsg {
+        asset_infos: vec![
+            AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfo::Token {
+                contract_addr: "asset0000".to_string(),
+            },
+        ],
+        amplification: Uint128::from(60u128),
+        fee: Uint128::from(4u128),
+        token_code_id: 10u64,
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+        owner: None,
+        lp_token_name: None,
+        lp_token_symbol: None,
+        lp_token_decimals: None,
+        base_pool: None,
+        rate_providers: vec![],
+    };
+
+    let env = mock_env();
+    let info = mock_info("addr0000", &[]);
+    let _res = instantiate(deps.as_mut(), env, info, msg).unwrap();
+
+    // store liquidity token
+    let reply_msg = Reply {
+        id: 1,
+        result: ContractResult::Ok(SubMsgExecutionResponse {
+            events: vec![],
+            data: Some(
+                vec![
+                    10, 13, 108, 105, 113, 117, 105, 100, 105, 116, 121, 48, 48, 48, 48,
+                ]
+                .into(),
+            ),
+        }),
+    };
+    let _res = reply(deps.as_mut(), mock_env(), reply_msg).unwrap();
+    sync_reserves(deps.as_mut());
+
+    // the invariant of a balanced pool is the sum of its scaled balances, 2 * 10^8 over 10^8 shares
+    let res = query_virtual_price(deps.as_ref(), mock_env()).unwrap();
+    assert_eq!(
+        res,
+        VirtualPriceResponse {
+            virtual_price: Uint128::from(2000000000000000000u128),
+            precision: 18,
+        }
+    );
+}
+
+#[test]
+fn migrate_legacy_pair() {
+    let mut deps = mock_dependencies(&[Coin {
+        denom: "uusd".to_string(),
+        amount: Uint128::from(1000000u128),
+    }]);
+    deps.querier.with_token_balances(&[
+        (
+            &"asset0000".to_string(),
+            &[(
+                &MOCK_CONTRACT_ADDR.to_string(),
+                &Uint128::from(100000000u128),
+            )],
+        ),
+        (
+            &"liquidity0000".to_string(),
+            &[(&"addr0000".to_string(), &Uint128::from(100000000u128))],
+        ),
+    ]);
+    deps.querier
+        .with_token_decimals(&[(&"asset0000".to_string(), 8u8)]);
+
+    // a pair instantiated before the owner, the reserves and the rest of the state were added
+    // stored nothing but its pair info, which had no precision multipliers
+    #[derive(Serialize, Deserialize)]
+    struct LegacyPairInfoRaw {
+        asset_infos: Vec<AssetInfoRaw>,
+        liquidity_token: CanonicalAddr,
+        contract_addr: CanonicalAddr,
+        amplification: Uint128,
+        fee: Uint128,
+    }
+    let legacy_pair_info = LegacyPairInfoRaw {
+        asset_infos: vec![
+            AssetInfoRaw::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            AssetInfoRaw::Token {
+                contract_addr: deps.api.addr_canonicalize("asset0000").unwrap(),
+            },
+        ],
+        liquidity_token: deps.api.addr_canonicalize("liquidity0000").unwrap(),
+        contract_addr: deps.api.addr_canonicalize(MOCK_CONTRACT_ADDR).unwrap(),
+        amplification: Uint128::from(60u128),
+        fee: Uint128::from(4u128),
+    };
+    singleton(&mut deps.storage, b"pair_info")
+        .save(&legacy_pair_info)
+        .unwrap();
+
+    let migrate_msg = |owner: Option<&str>| MigrateMsg {
+        owner: owner.map(|owner| owner.to_string()),
+        native_decimals: vec![NativeDecimals {
+            denom: "uusd".to_string(),
+            decimals: 6,
+        }],
+    };
+    let res = migrate(deps.as_mut(), mock_env(), migrate_msg(None)).unwrap_err();
+    assert_eq!(
+        res,
+        ContractError::Std(StdError::generic_err(
+            "Owner is required to migrate this pair"
+        ))
+    );
+    migrate(deps.as_mut(), mock_env(), migrate_msg(Some("owner0000"))).unwrap();
+
+    let pair_info: PairInfo = query_pair_info(deps.as_ref(), mock_env()).unwrap();
+    assert_eq!(pair_info.amplification, Uint128::from(60u128));
+    assert_eq!(
+        pair_info.precision_multipliers,
+        vec![Uint128::from(100u128), Uint128::from(1u128)]
+    );
+    let config: ConfigResponse = query_config(deps.as_ref(), mock_env()).unwrap();
+    assert_eq!(config.owner, "owner0000");
+    assert_eq!(config.fee, Uint128::from(4u128));
+
+    // the balances become the reserves, so the pair prices and trades right away
+    let res: PoolResponse = query_pool(deps.as_ref()).unwrap();
+    assert_eq!(res.assets[0].amount, Uint128::from(1000000u128));
+    assert_eq!(res.assets[1].amount, Uint128::from(100000000u128));
+    let res = query_simulation(
+        deps.as_ref(),
+        mock_env(),
+        Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::from(1000u128),
+        },
+        AssetInfo::Token {
+            contract_addr: "asset0000".to_string(),
+        },
+    )
+    .unwrap();
+    assert!(res.return_amount > Uint128::zero());
+    query_cumulative_prices(deps.as_ref(), mock_env()).unwrap();
+
+    // migrating a pair that has the state already keeps it
+    migrate(deps.as_mut(), mock_env(), migrate_msg(Some("owner0001"))).unwrap();
+    let config: ConfigResponse = query_config(deps.as_ref(), mock_env()).unwrap();
+    assert_eq!(config.owner, "owner0000");
+}
@@ -275,6 +275,10 @@ pub struct PairInfo {
     pub liquidity_token: String,
     pub amplification: Uint128,
     pub fee: Uint128,
+    /// Multipliers scaling each asset to the largest decimals in the pair, empty when a pair
+    /// that predates them answers
+    #[serde(default)]
+    pub precision_multipliers: Vec<Uint128>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -284,6 +288,9 @@ pub struct PairInfoRaw {
     pub contract_addr: CanonicalAddr,
     pub amplification: Uint128,
     pub fee: Uint128,
+    /// Empty for pairs stored before the multipliers were added, until they migrate
+    #[serde(default)]
+    pub precision_multipliers: Vec<Uint128>,
 }
 
 impl PairInfoRaw {
@@ -295,9 +302,10 @@ impl PairInfoRaw {
         Ok(PairInfo {
             liquidity_token: api.addr_humanize(&self.liquidity_token)?.to_string(),
             contract_addr: api.addr_humanize(&self.contract_addr)?.to_string(),
-            asset_infos: asset_infos,
+            asset_infos,
             amplification: self.amplification,
             fee: self.fee,
+            precision_multipliers: self.precision_multipliers.clone(),
         })
     }
 
@@ -312,7 +320,7 @@ impl PairInfoRaw {
             let info = asset.to_normal(api)?;
             asset_infos.push(Asset {
                 amount: info.query_pool(querier, api, contract_addr.clone())?,
-                info: info,
+                info,
             });
         }
 
@@ -2,6 +2,7 @@ pub mod asset;
 pub mod factory;
 pub mod pair;
 pub mod querier;
+pub mod rate_provider;
 pub mod router;
 pub mod token;
 
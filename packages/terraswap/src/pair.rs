@@ -3,8 +3,16 @@ use serde::{Deserialize, Serialize};
 
 use crate::asset::{Asset, AssetInfo};
 
-use cosmwasm_std::{Decimal, Uint128};
-use cw20::Cw20ReceiveMsg;
+use cosmwasm_std::{Binary, Decimal, Uint128};
+use cw20::{Cw20ReceiveMsg, Expiration};
+
+/// LP tokens the first deposit locks in the pair for good, so inflating the share price with
+/// donations costs the attacker as much as everyone else
+pub const MINIMUM_LIQUIDITY_AMOUNT: u128 = 1_000;
+/// LP tokens the first deposit has to mint, locked ones included. Keeping the locked share a
+/// thousandth of the supply or less, inflating the share price far enough to round away a
+/// later deposit takes a donation a thousand times that deposit.
+pub const MINIMUM_INITIAL_SHARE: u128 = 1_000 * MINIMUM_LIQUIDITY_AMOUNT;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InstantiateMsg {
@@ -14,17 +22,48 @@ pub struct InstantiateMsg {
     pub fee: Uint128,
     /// Token contract code id for initialization
     pub token_code_id: u64,
+    /// Decimals of the native denoms in `asset_infos`, cw20 decimals are queried from the token
+    pub native_decimals: Vec<NativeDecimals>,
+    /// Owner of the pair, defaults to the instantiator (the factory)
+    pub owner: Option<String>,
+    /// LP token name, defaults to the asset symbols or denoms joined with "-" and a "-LP" suffix
+    pub lp_token_name: Option<String>,
+    /// LP token symbol, defaults to a shortened upper case form of the name
+    pub lp_token_symbol: Option<String>,
+    /// LP token decimals, defaults to the largest decimals among the assets
+    pub lp_token_decimals: Option<u8>,
+    /// Stable pair whose LP token is one of `asset_infos`, which makes this pair a metapool
+    /// that values the LP token at the base pool virtual price
+    pub base_pool: Option<String>,
+    /// Contracts answering `Rate {}` for the assets whose value drifts from one, e.g. liquid
+    /// staking tokens. Balances are valued at these rates, other assets are worth one.
+    #[serde(default)]
+    pub rate_providers: Vec<RateProvider>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct NativeDecimals {
+    pub denom: String,
+    pub decimals: u8,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RateProvider {
+    pub asset_info: AssetInfo,
+    pub contract_addr: String,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
     Receive(Cw20ReceiveMsg),
-    /// ProvideLiquidity a user provides pool liquidity
+    /// ProvideLiquidity a user provides pool liquidity, any subset of the pool assets in any order
     ProvideLiquidity {
         assets: Vec<Asset>,
         min_out_amount: Uint128,
         receiver: Option<String>,
+        /// Block time or height from which the message is rejected
+        deadline: Option<Expiration>,
     },
     /// Swap an offer asset to the other
     Swap {
@@ -32,7 +71,86 @@ pub enum ExecuteMsg {
         ask_asset: Asset,
         min_out_amount: Uint128,
         to: Option<String>,
+        deadline: Option<Expiration>,
+    },
+    /// SwapExactOut buys exactly `ask_asset` with the single native coin sent, which has to be
+    /// `max_offer_amount`, refunding what is left of it
+    SwapExactOut {
+        ask_asset: Asset,
+        max_offer_amount: Uint128,
+        to: Option<String>,
+        deadline: Option<Expiration>,
+    },
+    /// ExchangeUnderlying swaps between the metapool assets and the base pool assets, depositing
+    /// into or withdrawing from the base pool on the way (metapool only)
+    ExchangeUnderlying {
+        offer_asset: Asset,
+        ask_asset: Asset,
+        min_out_amount: Uint128,
+        to: Option<String>,
+        deadline: Option<Expiration>,
+    },
+    /// RampAmp moves the amplification linearly to `future_amp` by `future_time` (owner only)
+    RampAmp {
+        future_amp: Uint128,
+        future_time: u64,
+    },
+    /// StopRampAmp freezes the amplification at its current value (owner only)
+    StopRampAmp {},
+    /// UpdateFee sets the swap fee numerator over 10000 (owner only)
+    UpdateFee {
+        fee: Uint128,
+    },
+    /// Pause stops swaps, deposits and imbalanced withdrawals until `expiry_height`,
+    /// or until unpaused when none is given (owner or guardian)
+    Pause {
+        expiry_height: Option<u64>,
+    },
+    /// Unpause resumes trading (owner or guardian)
+    Unpause {},
+    /// UpdateGuardian sets or removes the address allowed to pause the pair (owner only)
+    UpdateGuardian {
+        guardian: Option<String>,
+    },
+    /// UpdateAdminFee sets the protocol share of the swap and imbalance fees over 10000 (owner only)
+    UpdateAdminFee {
+        admin_fee: Uint128,
+    },
+    /// UpdateFeeCollector sets the address the admin fees are paid out to (owner only)
+    UpdateFeeCollector {
+        fee_collector: String,
+    },
+    /// WithdrawAdminFees sends the accrued admin fees to the fee collector
+    WithdrawAdminFees {},
+    /// Skim sends the balances held beyond the reserves and the admin fees to `to`
+    Skim {
+        to: String,
+    },
+    /// Sync adopts the balances held beyond the reserves and the admin fees into the reserves
+    Sync {},
+    /// FlashLoan sends `assets` from the reserves to the sender and executes `callback_msg` on it,
+    /// the assets plus the flash loan fee have to be paid back by the end of the callback.
+    /// Native assets arrive in full, the Terra tax the pair pays sending them is owed back too
+    FlashLoan {
+        assets: Vec<Asset>,
+        callback_msg: Binary,
+    },
+    /// UpdateFlashLoanFee sets the flash loan fee numerator over 10000 (owner only)
+    UpdateFlashLoanFee {
+        flash_loan_fee: Uint128,
     },
+    /// UpdateFeeMultiplier sets the cap of the dynamic swap fee as a multiple of the fee,
+    /// over 10000. Trades leaving the pool further off balance pay up to the capped fee,
+    /// zero keeps the fee flat (owner only)
+    UpdateFeeMultiplier {
+        fee_multiplier: Uint128,
+    },
+    /// ProposeNewOwner starts an ownership transfer, which `owner` has to accept (owner only)
+    ProposeNewOwner {
+        owner: String,
+    },
+    /// AcceptOwnership completes the ownership transfer (proposed owner only)
+    AcceptOwnership {},
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -43,21 +161,106 @@ pub enum Cw20HookMsg {
         ask_asset: Asset,
         min_out_amount: Uint128,
         to: Option<String>,
+        deadline: Option<Expiration>,
+    },
+    /// Buy exactly `ask_asset` with at most the sent amount, refunding the rest
+    SwapExactOut {
+        ask_asset: Asset,
+        to: Option<String>,
+        deadline: Option<Expiration>,
+    },
+    /// Sell a given amount of asset for a metapool or base pool asset (metapool only)
+    ExchangeUnderlying {
+        ask_asset: Asset,
+        min_out_amount: Uint128,
+        to: Option<String>,
+        deadline: Option<Expiration>,
     },
     WithdrawSingleLiquidity {
         asset: Asset,
         min_out_amount: Uint128,
+        receiver: Option<String>,
+        deadline: Option<Expiration>,
+    },
+    /// Withdraw every asset in proportion to the pool, each at least its amount in `min_assets`
+    WithdrawLiquidity {
+        #[serde(default)]
+        min_assets: Vec<Asset>,
+        receiver: Option<String>,
+        deadline: Option<Expiration>,
+    },
+    /// Withdraw exactly `assets` to `receiver`, burning at most `max_burn_amount` of the sent
+    /// LP tokens and refunding the rest to the sender
+    WithdrawImbalancedLiquidity {
+        assets: Vec<Asset>,
+        max_burn_amount: Uint128,
+        receiver: Option<String>,
+        deadline: Option<Expiration>,
     },
-    WithdrawLiquidity {},
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
     Pair {},
+    /// Pool returns the reserves accounted by the pair and the LP token supply
     Pool {},
-    // Simulation { offer_asset: Asset },
-    // ReverseSimulation { ask_asset: Asset },
+    /// Simulation returns the amount of `ask_asset_info` received for `offer_asset`
+    Simulation {
+        offer_asset: Asset,
+        ask_asset_info: AssetInfo,
+    },
+    /// ReverseSimulation returns the amount of `offer_asset_info` required for `ask_asset`
+    ReverseSimulation {
+        offer_asset_info: AssetInfo,
+        ask_asset: Asset,
+    },
+    /// SimulateProvideLiquidity returns the LP share minted for depositing `assets`
+    SimulateProvideLiquidity {
+        assets: Vec<Asset>,
+    },
+    /// SimulateWithdrawLiquidity returns the assets paid out for burning `share`
+    SimulateWithdrawLiquidity {
+        share: Uint128,
+    },
+    /// SimulateWithdrawSingleLiquidity returns the amount of `asset_info` paid out for burning `share`
+    SimulateWithdrawSingleLiquidity {
+        share: Uint128,
+        asset_info: AssetInfo,
+    },
+    /// Amp returns the current amplification and the target of an ongoing ramp
+    Amp {},
+    /// Config returns the owner and the adjustable parameters of the pair
+    Config {},
+    /// AdminFees returns the admin fees accrued since the last withdrawal
+    AdminFees {},
+    /// VirtualPrice returns the value of one LP token in units of the pool invariant
+    VirtualPrice {},
+    /// CumulativePrices returns the time weighted price accumulators as of the current block
+    CumulativePrices {},
+    /// Observe returns the price accumulators as of each of `seconds_ago`
+    Observe {
+        seconds_ago: Vec<u64>,
+    },
+    /// SpotPrice returns the marginal price of `offer_asset_info` in units of `ask_asset_info`
+    /// at the current reserves, before fees
+    SpotPrice {
+        offer_asset_info: AssetInfo,
+        ask_asset_info: AssetInfo,
+    },
+    /// PriceImpact returns the price a swap of `offer_asset` for `ask_asset_info` gets and how
+    /// far it is off the spot price
+    PriceImpact {
+        offer_asset: Asset,
+        ask_asset_info: AssetInfo,
+    },
+    /// TradeToPrice returns how much of `offer_asset_info` to sell for `ask_asset_info` to bring
+    /// the spot price of the offer asset down to `target_price`, scaled like `SpotPrice`
+    TradeToPrice {
+        offer_asset_info: AssetInfo,
+        ask_asset_info: AssetInfo,
+        target_price: Uint128,
+    },
 }
 
 // We define a custom struct for each query response
@@ -71,14 +274,154 @@ pub struct PoolResponse {
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct SimulationResponse {
     pub return_amount: Uint128,
+    pub spread_amount: Uint128,
+    pub commission_amount: Uint128,
+    /// Swap fee numerator over 10000 the trade was charged, zero for pairs that do not
+    /// report it
+    #[serde(default)]
+    pub fee: Uint128,
+    /// Rates the pair assets were valued at, scaled by 10^18 and in the order of the pair
+    /// assets, empty for pairs without rates
+    #[serde(default)]
+    pub rates: Vec<Uint128>,
 }
 
 /// ReverseSimulationResponse returns reverse swap simulation response
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct ReverseSimulationResponse {
     pub offer_amount: Uint128,
+    pub spread_amount: Uint128,
+    pub commission_amount: Uint128,
+    /// Swap fee numerator over 10000 the trade was charged, zero for pairs that do not
+    /// report it
+    #[serde(default)]
+    pub fee: Uint128,
+    /// Rates the pair assets were valued at, scaled by 10^18 and in the order of the pair
+    /// assets, empty for pairs without rates
+    #[serde(default)]
+    pub rates: Vec<Uint128>,
+}
+
+/// SimulateProvideLiquidityResponse returns provide liquidity simulation response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SimulateProvideLiquidityResponse {
+    pub share: Uint128,
+}
+
+/// SimulateWithdrawLiquidityResponse returns withdraw liquidity simulation response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SimulateWithdrawLiquidityResponse {
+    pub assets: Vec<Asset>,
+}
+
+/// SimulateWithdrawSingleLiquidityResponse returns single asset withdraw simulation response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SimulateWithdrawSingleLiquidityResponse {
+    pub asset: Asset,
+}
+
+/// AmpResponse returns the amplification ramp state
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AmpResponse {
+    pub amp: Uint128,
+    pub future_amp: Uint128,
+    pub future_time: u64,
+}
+
+/// ConfigResponse returns the pair configuration
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ConfigResponse {
+    pub owner: String,
+    pub pending_owner: Option<String>,
+    pub guardian: Option<String>,
+    pub fee: Uint128,
+    pub admin_fee: Uint128,
+    pub fee_collector: Option<String>,
+    pub flash_loan_fee: Uint128,
+    /// Cap of the dynamic swap fee as a multiple of the fee, over 10000
+    pub fee_multiplier: Uint128,
+    pub amp: Uint128,
+    pub future_amp: Uint128,
+    pub future_time: u64,
+    pub paused: bool,
+    pub pause_expiry_height: Option<u64>,
+    /// Base pool of a metapool
+    pub base_pool: Option<String>,
+    /// Rate providers of the assets valued at a rate
+    pub rate_providers: Vec<RateProvider>,
+}
+
+/// AdminFeesResponse returns the admin fees held for the protocol
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AdminFeesResponse {
+    pub assets: Vec<Asset>,
+}
+
+/// VirtualPriceResponse returns the LP token virtual price scaled by `10^precision`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VirtualPriceResponse {
+    pub virtual_price: Uint128,
+    pub precision: u8,
+}
+
+/// PriceCumulative sums the price of `offer_asset_info` in units of `ask_asset_info`, scaled by
+/// `10^precision`, times the seconds it held. It wraps around on overflow, so a time weighted
+/// average price is the wrapping difference of two accumulators over the seconds between them.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PriceCumulative {
+    pub offer_asset_info: AssetInfo,
+    pub ask_asset_info: AssetInfo,
+    pub price_cumulative: Uint128,
+}
+
+/// CumulativePricesResponse returns the price accumulators of every ordered asset pair at `timestamp`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CumulativePricesResponse {
+    pub timestamp: u64,
+    pub price_cumulatives: Vec<PriceCumulative>,
+    pub precision: u8,
+}
+
+/// ObserveResponse returns the price accumulators in the order of the requested `seconds_ago`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ObserveResponse {
+    pub observations: Vec<CumulativePricesResponse>,
+}
+
+/// SpotPriceResponse returns the marginal price scaled by `10^precision`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SpotPriceResponse {
+    pub price: Uint128,
+    pub precision: u8,
+}
+
+/// PriceImpactResponse returns the prices of a swap in units of the ask asset per offer asset,
+/// scaled by `10^precision`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PriceImpactResponse {
+    pub spot_price: Uint128,
+    /// Return amount per offer amount, after fees
+    pub effective_price: Uint128,
+    /// Share of the value at the spot price the swap loses to price impact, fees aside
+    pub price_impact: Decimal,
+    pub precision: u8,
+}
+
+/// TradeToPriceResponse returns the swap that moves the spot price to the target, all zero when
+/// the spot price is at or below the target already
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TradeToPriceResponse {
+    pub offer_amount: Uint128,
+    pub return_amount: Uint128,
+    pub commission_amount: Uint128,
 }
 
-/// We currently take no arguments for migrations
+/// Seeds the state a pair instantiated before it was added lacks, the rest is kept
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
-pub struct MigrateMsg {}
+pub struct MigrateMsg {
+    /// Owner of a pair that has none yet
+    pub owner: Option<String>,
+    /// Decimals of the native assets of a pair without precision multipliers yet
+    #[serde(default)]
+    pub native_decimals: Vec<NativeDecimals>,
+}
@@ -1,6 +1,9 @@
 use crate::asset::{Asset, AssetInfo, PairInfo};
 use crate::factory::QueryMsg as FactoryQueryMsg;
-use crate::pair::{QueryMsg as PairQueryMsg, ReverseSimulationResponse, SimulationResponse};
+use crate::pair::{
+    QueryMsg as PairQueryMsg, ReverseSimulationResponse, SimulationResponse, VirtualPriceResponse,
+};
+use crate::rate_provider::{QueryMsg as RateProviderQueryMsg, RateResponse};
 
 use cosmwasm_std::{
     to_binary, Addr, AllBalanceResponse, BalanceResponse, BankQuery, Coin, QuerierWrapper,
@@ -56,6 +59,24 @@ pub fn query_supply(querier: &QuerierWrapper, contract_addr: Addr) -> StdResult<
     Ok(token_info.total_supply)
 }
 
+pub fn query_token_decimals(querier: &QuerierWrapper, contract_addr: Addr) -> StdResult<u8> {
+    let token_info: TokenInfoResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: contract_addr.to_string(),
+        msg: to_binary(&Cw20QueryMsg::TokenInfo {})?,
+    }))?;
+
+    Ok(token_info.decimals)
+}
+
+pub fn query_token_symbol(querier: &QuerierWrapper, contract_addr: Addr) -> StdResult<String> {
+    let token_info: TokenInfoResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: contract_addr.to_string(),
+        msg: to_binary(&Cw20QueryMsg::TokenInfo {})?,
+    }))?;
+
+    Ok(token_info.symbol)
+}
+
 pub fn query_pair_info(
     querier: &QuerierWrapper,
     factory_contract: Addr,
@@ -69,28 +90,59 @@ pub fn query_pair_info(
     }))
 }
 
-// pub fn simulate(
-//     querier: &QuerierWrapper,
-//     pair_contract: Addr,
-//     offer_asset: &Asset,
-// ) -> StdResult<SimulationResponse> {
-//     querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
-//         contract_addr: pair_contract.to_string(),
-//         msg: to_binary(&PairQueryMsg::Simulation {
-//             offer_asset: offer_asset.clone(),
-//         })?,
-//     }))
-// }
-
-// pub fn reverse_simulate(
-//     querier: &QuerierWrapper,
-//     pair_contract: Addr,
-//     ask_asset: &Asset,
-// ) -> StdResult<ReverseSimulationResponse> {
-//     querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
-//         contract_addr: pair_contract.to_string(),
-//         msg: to_binary(&PairQueryMsg::ReverseSimulation {
-//             ask_asset: ask_asset.clone(),
-//         })?,
-//     }))
-// }
+pub fn query_pair_info_from_pair(
+    querier: &QuerierWrapper,
+    pair_contract: Addr,
+) -> StdResult<PairInfo> {
+    querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: pair_contract.to_string(),
+        msg: to_binary(&PairQueryMsg::Pair {})?,
+    }))
+}
+
+pub fn query_virtual_price(
+    querier: &QuerierWrapper,
+    pair_contract: Addr,
+) -> StdResult<VirtualPriceResponse> {
+    querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: pair_contract.to_string(),
+        msg: to_binary(&PairQueryMsg::VirtualPrice {})?,
+    }))
+}
+
+pub fn query_rate(querier: &QuerierWrapper, rate_provider: Addr) -> StdResult<RateResponse> {
+    querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: rate_provider.to_string(),
+        msg: to_binary(&RateProviderQueryMsg::Rate {})?,
+    }))
+}
+
+pub fn simulate(
+    querier: &QuerierWrapper,
+    pair_contract: Addr,
+    offer_asset: &Asset,
+    ask_asset_info: &AssetInfo,
+) -> StdResult<SimulationResponse> {
+    querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: pair_contract.to_string(),
+        msg: to_binary(&PairQueryMsg::Simulation {
+            offer_asset: offer_asset.clone(),
+            ask_asset_info: ask_asset_info.clone(),
+        })?,
+    }))
+}
+
+pub fn reverse_simulate(
+    querier: &QuerierWrapper,
+    pair_contract: Addr,
+    offer_asset_info: &AssetInfo,
+    ask_asset: &Asset,
+) -> StdResult<ReverseSimulationResponse> {
+    querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: pair_contract.to_string(),
+        msg: to_binary(&PairQueryMsg::ReverseSimulation {
+            offer_asset_info: offer_asset_info.clone(),
+            ask_asset: ask_asset.clone(),
+        })?,
+    }))
+}
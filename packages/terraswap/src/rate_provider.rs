@@ -0,0 +1,20 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::Uint128;
+
+/// Queries a stable pair sends to the rate provider of an asset
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryMsg {
+    /// Rate returns the value of one unit of the asset in units of the pool invariant,
+    /// e.g. the underlying a liquid staking token redeems for
+    Rate {},
+}
+
+/// RateResponse returns the asset rate scaled by `10^precision`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RateResponse {
+    pub rate: Uint128,
+    pub precision: u8,
+}
@@ -2,7 +2,7 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use cosmwasm_std::Uint128;
-use cw20::Cw20ReceiveMsg;
+use cw20::{Cw20ReceiveMsg, Expiration};
 
 use crate::asset::AssetInfo;
 
@@ -44,6 +44,8 @@ pub enum ExecuteMsg {
         operations: Vec<SwapOperation>,
         minimum_receive: Option<Uint128>,
         to: Option<String>,
+        /// Block time or height from which the operations are rejected
+        deadline: Option<Expiration>,
     },
 
     /// Internal use
@@ -69,6 +71,7 @@ pub enum Cw20HookMsg {
         operations: Vec<SwapOperation>,
         minimum_receive: Option<Uint128>,
         to: Option<String>,
+        deadline: Option<Expiration>,
     },
 }
 
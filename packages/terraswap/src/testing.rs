@@ -2,10 +2,13 @@ use crate::asset::{Asset, AssetInfo, PairInfo};
 use crate::mock_querier::mock_dependencies;
 use crate::querier::{
     query_all_balances, query_balance, query_pair_info, query_supply, query_token_balance,
+    query_token_decimals,
 };
 
 use cosmwasm_std::testing::MOCK_CONTRACT_ADDR;
-use cosmwasm_std::{to_binary, Addr, BankMsg, Coin, CosmosMsg, Decimal, Uint128, WasmMsg};
+use cosmwasm_std::{
+    from_slice, to_binary, Addr, BankMsg, Coin, CosmosMsg, Decimal, Uint128, WasmMsg,
+};
 use cw20::Cw20ExecuteMsg;
 
 #[test]
@@ -94,6 +97,21 @@ fn supply_querier() {
     )
 }
 
+#[test]
+fn token_decimals_querier() {
+    let mut deps = mock_dependencies(&[]);
+
+    deps.querier.with_token_balances(&[(
+        &"asset0000".to_string(),
+        &[(&MOCK_CONTRACT_ADDR.to_string(), &Uint128::from(123u128))],
+    )]);
+
+    assert_eq!(
+        query_token_decimals(&deps.as_ref().querier, Addr::unchecked("asset0000")).unwrap(),
+        6u8
+    )
+}
+
 #[test]
 fn test_asset_info() {
     let token_info: AssetInfo = AssetInfo::Token {
@@ -245,7 +263,7 @@ fn query_terraswap_pair_contract() {
     deps.querier.with_terraswap_pairs(&[(
         &"asset0000uusd".to_string(),
         &PairInfo {
-            asset_infos: [
+            asset_infos: vec![
                 AssetInfo::Token {
                     contract_addr: "asset0000".to_string(),
                 },
@@ -255,6 +273,9 @@ fn query_terraswap_pair_contract() {
             ],
             contract_addr: "pair0000".to_string(),
             liquidity_token: "liquidity0000".to_string(),
+            amplification: Uint128::from(60u128),
+            fee: Uint128::from(4u128),
+            precision_multipliers: vec![Uint128::from(1u128), Uint128::from(1u128)],
         },
     )]);
 
@@ -275,3 +296,25 @@ fn query_terraswap_pair_contract() {
     assert_eq!(pair_info.contract_addr, Addr::unchecked("pair0000"),);
     assert_eq!(pair_info.liquidity_token, Addr::unchecked("liquidity0000"),);
 }
+
+#[test]
+fn pair_info_without_precision_multipliers() {
+    // pairs that predate the multipliers answer without them
+    let pair_info: PairInfo = from_slice(
+        br#"{
+            "asset_infos": [
+                {"token": {"contract_addr": "asset0000"}},
+                {"native_token": {"denom": "uusd"}}
+            ],
+            "contract_addr": "pair0000",
+            "liquidity_token": "liquidity0000",
+            "amplification": "0",
+            "fee": "30"
+        }"#,
+    )
+    .unwrap();
+
+    assert_eq!(pair_info.contract_addr, "pair0000");
+    assert_eq!(pair_info.fee, Uint128::from(30u128));
+    assert_eq!(pair_info.precision_multipliers, Vec::<Uint128>::new());
+}